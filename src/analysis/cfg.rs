@@ -0,0 +1,507 @@
+// src/analysis/cfg.rs
+
+use crate::engine::interpreter::Interpreter;
+use crate::entities::{Direction, IPState};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// An IP about to execute the cell at (x, y).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct State {
+    pub x: usize,
+    pub y: usize,
+    pub direction: Direction,
+    pub string_mode: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// the IP moves on
+    Next,
+    /// `_` or `|` popped 0
+    Zero,
+    /// `_` or `|` popped anything else
+    NonZero,
+    /// the IP created by `t`
+    Spawn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    State(State),
+    /// the IP moved off the program to (x, y) in string mode, pushing the empty space it
+    /// crosses; the graph does not follow it further
+    OffGrid {
+        x: usize,
+        y: usize,
+        direction: Direction,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub kind: EdgeKind,
+    pub target: Target,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub instruction: char,
+    pub edges: Vec<Edge>,
+}
+
+/// A straight-line run of states: only the first is entered from elsewhere, only the last
+/// branches, spawns, ends or leaves the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub states: Vec<State>,
+    /// the edges of the last state
+    pub exits: Vec<Edge>,
+}
+
+/// A `p` in the graph and, when the block before it makes them constant, what it writes where.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PutTarget {
+    pub at: State,
+    pub target: Option<(usize, usize)>,
+    pub value: Option<usize>,
+}
+
+/// Every state an IP can reach from the start points, found without running the program.
+///
+/// The grid is taken as loaded: cells `p` may write later are not followed, and `t` is
+/// assumed to start its IP in the current direction (what it does with invalid deltas).
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub grid: Vec<Vec<char>>,
+    pub width: usize,
+    pub height: usize,
+    pub starts: Vec<State>,
+    pub nodes: BTreeMap<State, Node>,
+    /// the state each state was first reached from
+    parents: BTreeMap<State, State>,
+}
+
+impl ControlFlowGraph {
+    pub fn build(grid: &[Vec<char>]) -> Self {
+        let mut cfg = Self {
+            grid: grid.to_vec(),
+            width: grid.iter().map(Vec::len).max().unwrap_or(0),
+            height: grid.len(),
+            starts: Vec::new(),
+            nodes: BTreeMap::new(),
+            parents: BTreeMap::new(),
+        };
+        cfg.starts = cfg.start_points();
+
+        let mut queue: VecDeque<State> = cfg.starts.iter().copied().collect();
+        let mut seen: BTreeSet<State> = queue.iter().copied().collect();
+        while let Some(state) = queue.pop_front() {
+            let node = cfg.node(state);
+            for edge in &node.edges {
+                if let Target::State(next) = edge.target {
+                    if seen.insert(next) {
+                        cfg.parents.insert(next, state);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            cfg.nodes.insert(state, node);
+        }
+        cfg
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> char {
+        self.grid
+            .get(y)
+            .and_then(|row| row.get(x))
+            .copied()
+            .unwrap_or(' ')
+    }
+
+    /// the `→←↑↓` markers, or (0, 0) moving right, as `Interpreter` starts its IPs
+    fn start_points(&self) -> Vec<State> {
+        let mut starts = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let direction = match self.cell(x, y) {
+                    '→' => Direction::Right,
+                    '←' => Direction::Left,
+                    '↑' => Direction::Up,
+                    '↓' => Direction::Down,
+                    _ => continue,
+                };
+                starts.push(State {
+                    x,
+                    y,
+                    direction,
+                    string_mode: false,
+                });
+            }
+        }
+        if starts.is_empty() {
+            starts.push(State {
+                x: 0,
+                y: 0,
+                direction: Direction::Right,
+                string_mode: false,
+            });
+        }
+        starts
+    }
+
+    /// where an IP at (x, y) moving in `direction` ends up, wrapping like `Interpreter`;
+    /// `None` in the empty Funge-Space outside the program
+    pub fn advance(&self, x: usize, y: usize, direction: Direction) -> Option<(usize, usize)> {
+        let x = (x as isize + direction.dx()).rem_euclid(Interpreter::WIDTH as isize) as usize;
+        let y = (y as isize + direction.dy()).rem_euclid(Interpreter::HEIGHT as isize) as usize;
+        (x < self.width && y < self.height).then_some((x, y))
+    }
+
+    /// the cell an IP leaving (x, y) comes back in at, after crossing the empty Funge-Space
+    /// and wrapping around: the opposite edge of the program
+    fn reenter(&self, x: usize, y: usize, direction: Direction) -> (usize, usize) {
+        match direction {
+            Direction::Right => (0, y),
+            Direction::Left => (self.width - 1, y),
+            Direction::Down => (x, 0),
+            Direction::Up => (x, self.height - 1),
+            Direction::High | Direction::Low => (x, y),
+        }
+    }
+
+    /// the cell one step from (x, y) on the 1024x1024 torus
+    fn outside(&self, x: usize, y: usize, direction: Direction) -> (usize, usize) {
+        let x = (x as isize + direction.dx()).rem_euclid(Interpreter::WIDTH as isize);
+        let y = (y as isize + direction.dy()).rem_euclid(Interpreter::HEIGHT as isize);
+        (x as usize, y as usize)
+    }
+
+    /// the first empty cell an IP executing `state` steps into when it moves on in
+    /// `direction`, `None` if it stays on the program
+    pub fn exit(&self, state: State, direction: Direction) -> Option<(usize, usize)> {
+        let skip = !state.string_mode && self.cell(state.x, state.y) == '#';
+        let (mut x, mut y) = (state.x, state.y);
+        for _ in 0..1 + skip as usize {
+            match self.advance(x, y, direction) {
+                Some(next) => (x, y) = next,
+                None => return Some(self.outside(x, y, direction)),
+            }
+        }
+        None
+    }
+
+    /// empty cells between the program and the 1024 wrap, crossed moving in `direction`
+    pub fn empty_cells(&self, direction: Direction) -> usize {
+        match direction {
+            Direction::Right | Direction::Left => Interpreter::WIDTH - self.width,
+            _ => Interpreter::HEIGHT - self.height,
+        }
+    }
+
+    fn target(&self, x: usize, y: usize, direction: Direction, string_mode: bool) -> Target {
+        let next = match self.advance(x, y, direction) {
+            Some(next) => Some(next),
+            // spaces are no-ops outside string mode
+            None if !string_mode => Some(self.reenter(x, y, direction)),
+            None => None,
+        };
+        match next {
+            Some((x, y)) => Target::State(State {
+                x,
+                y,
+                direction,
+                string_mode,
+            }),
+            None => {
+                let (x, y) = self.outside(x, y, direction);
+                Target::OffGrid { x, y, direction }
+            }
+        }
+    }
+
+    /// what executing the cell of `state` can lead to
+    pub fn node(&self, state: State) -> Node {
+        let State {
+            x,
+            y,
+            direction,
+            string_mode,
+        } = state;
+        let instruction = self.cell(x, y);
+        let edge = |kind, direction, string_mode| Edge {
+            kind,
+            target: self.target(x, y, direction, string_mode),
+        };
+        let edges = if string_mode {
+            vec![edge(EdgeKind::Next, direction, instruction != '"')]
+        } else {
+            match instruction {
+                '"' => vec![edge(EdgeKind::Next, direction, true)],
+                '>' => vec![edge(EdgeKind::Next, Direction::Right, false)],
+                '<' => vec![edge(EdgeKind::Next, Direction::Left, false)],
+                '^' => vec![edge(EdgeKind::Next, Direction::Up, false)],
+                'v' => vec![edge(EdgeKind::Next, Direction::Down, false)],
+                '_' => vec![
+                    edge(EdgeKind::Zero, Direction::Right, false),
+                    edge(EdgeKind::NonZero, Direction::Left, false),
+                ],
+                '|' => vec![
+                    edge(EdgeKind::Zero, Direction::Down, false),
+                    edge(EdgeKind::NonZero, Direction::Up, false),
+                ],
+                '#' => match self.advance(x, y, direction) {
+                    Some((x, y)) => vec![Edge {
+                        kind: EdgeKind::Next,
+                        target: self.target(x, y, direction, false),
+                    }],
+                    None => vec![edge(EdgeKind::Next, direction, false)],
+                },
+                '@' => Vec::new(),
+                't' => vec![
+                    edge(EdgeKind::Next, direction, false),
+                    edge(EdgeKind::Spawn, direction, false),
+                ],
+                _ => vec![edge(EdgeKind::Next, direction, false)],
+            }
+        };
+        Node { instruction, edges }
+    }
+
+    /// the cells passed on the way to `state`, oldest first, as long as an IP's trail
+    pub fn path_to(&self, state: State) -> Vec<(usize, usize)> {
+        let mut path = Vec::new();
+        let mut current = state;
+        while let Some(parent) = self.parents.get(&current) {
+            path.push((parent.x, parent.y));
+            if path.len() == IPState::TRAIL_LENGTH {
+                break;
+            }
+            current = *parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// cells some IP can execute
+    pub fn reachable_cells(&self) -> BTreeSet<(usize, usize)> {
+        self.nodes.keys().map(|state| (state.x, state.y)).collect()
+    }
+
+    /// the graph cut into basic blocks, in the order of their first state
+    pub fn basic_blocks(&self) -> Vec<BasicBlock> {
+        let mut predecessors: BTreeMap<State, usize> = BTreeMap::new();
+        let mut leaders: BTreeSet<State> = self.starts.iter().copied().collect();
+        for node in self.nodes.values() {
+            let straight = matches!(node.edges.as_slice(), [edge] if edge.kind == EdgeKind::Next);
+            for edge in &node.edges {
+                if let Target::State(next) = edge.target {
+                    *predecessors.entry(next).or_default() += 1;
+                    if !straight {
+                        leaders.insert(next);
+                    }
+                }
+            }
+        }
+        leaders.extend(
+            predecessors
+                .iter()
+                .filter(|(_, count)| **count > 1)
+                .map(|(state, _)| *state),
+        );
+
+        leaders
+            .iter()
+            .map(|&leader| {
+                let mut states = vec![leader];
+                let mut node = &self.nodes[&leader];
+                while let [Edge {
+                    kind: EdgeKind::Next,
+                    target: Target::State(next),
+                }] = node.edges.as_slice()
+                {
+                    if leaders.contains(next) {
+                        break;
+                    }
+                    states.push(*next);
+                    node = &self.nodes[next];
+                }
+                BasicBlock {
+                    states,
+                    exits: node.edges.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// states that execute `p` and can therefore change the graph
+    pub fn writers(&self) -> Vec<State> {
+        self.nodes
+            .iter()
+            .filter(|(state, node)| !state.string_mode && node.instruction == 'p')
+            .map(|(state, _)| *state)
+            .collect()
+    }
+
+    /// every `p`, with its target folded from the constants pushed earlier in its block
+    pub fn put_targets(&self) -> Vec<PutTarget> {
+        let mut puts = Vec::new();
+        for block in self.basic_blocks() {
+            // values from before the block are unknown
+            let mut stack: Vec<Option<usize>> = Vec::new();
+            for state in &block.states {
+                let instruction = self.cell(state.x, state.y);
+                if state.string_mode {
+                    if instruction != '"' {
+                        stack.push(Some(instruction as usize));
+                    }
+                    continue;
+                }
+                let mut pop = || stack.pop().flatten();
+                match instruction {
+                    '0'..='9' => stack.push(instruction.to_digit(10).map(|d| d as usize)),
+                    '+' | '-' | '*' | '/' | '%' | '`' => {
+                        let (b, a) = (pop(), pop());
+                        let value = a.zip(b).and_then(|(a, b)| match instruction {
+                            '+' => Some(a.wrapping_add(b)),
+                            '-' => Some(a.wrapping_sub(b)),
+                            '*' => Some(a.wrapping_mul(b)),
+                            // signed, like the commands
+                            '/' => (a as isize).checked_div(b as isize).map(|v| v as usize),
+                            '%' => (a as isize).checked_rem(b as isize).map(|v| v as usize),
+                            _ => Some((a > b) as usize),
+                        });
+                        stack.push(value);
+                    }
+                    '!' => {
+                        let value = pop().map(|v| (v == 0) as usize);
+                        stack.push(value);
+                    }
+                    ':' => {
+                        let value = pop();
+                        stack.extend([value, value]);
+                    }
+                    '\\' => {
+                        let (b, a) = (pop(), pop());
+                        stack.extend([b, a]);
+                    }
+                    '$' | '.' | ',' | '_' | '|' => {
+                        pop();
+                    }
+                    '&' | '~' => stack.push(None),
+                    'g' | 't' => {
+                        pop();
+                        pop();
+                        if instruction == 'g' {
+                            stack.push(None);
+                        }
+                    }
+                    'p' => {
+                        let (y, x, value) = (pop(), pop(), pop());
+                        puts.push(PutTarget {
+                            at: *state,
+                            target: x.zip(y),
+                            value,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        puts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::testing::grid;
+
+    #[test]
+    fn test_branches_skips_and_string_mode() {
+        let cfg = ControlFlowGraph::build(&grid(&["v  #@", ">0_\"x\"v", "    @ <"]));
+        let cells = cfg.reachable_cells();
+        // `#` at (3, 0) is never reached, `_` leads both ways
+        assert!(!cells.contains(&(3, 0)));
+        assert!(cells.contains(&(6, 1)) && cells.contains(&(4, 2)));
+        let branch = cfg
+            .nodes
+            .iter()
+            .find(|(state, _)| (state.x, state.y) == (2, 1))
+            .map(|(_, node)| node)
+            .unwrap();
+        assert_eq!(branch.edges.len(), 2);
+        // the x inside the string is executed in string mode only
+        assert!(cfg
+            .nodes
+            .keys()
+            .filter(|state| (state.x, state.y) == (4, 1))
+            .all(|state| state.string_mode));
+        let end = *cfg
+            .nodes
+            .keys()
+            .find(|state| (state.x, state.y) == (4, 2))
+            .unwrap();
+        // the last 8 cells only, like an IP's trail
+        assert_eq!(
+            cfg.path_to(end),
+            vec![
+                (1, 1),
+                (2, 1),
+                (3, 1),
+                (4, 1),
+                (5, 1),
+                (6, 1),
+                (6, 2),
+                (5, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_start_markers_and_leaving_the_grid() {
+        let cfg = ControlFlowGraph::build(&grid(&["→1", "↓ "]));
+        assert_eq!(cfg.starts.len(), 2);
+        let leaving: Vec<_> = cfg
+            .nodes
+            .values()
+            .flat_map(|node| node.edges.iter())
+            .filter(|edge| matches!(edge.target, Target::OffGrid { .. }))
+            .collect();
+        // both come back in at the opposite edge
+        assert!(leaving.is_empty());
+        let right = State {
+            x: 0,
+            y: 0,
+            direction: Direction::Right,
+            string_mode: false,
+        };
+        assert_eq!(
+            cfg.exit(State { x: 1, ..right }, Direction::Right),
+            Some((2, 0))
+        );
+        assert_eq!(cfg.exit(right, Direction::Right), None);
+        assert_eq!(cfg.empty_cells(Direction::Right), 1022);
+        assert_eq!(
+            cfg.nodes[&State { x: 1, ..right }].edges[0].target,
+            Target::State(right)
+        );
+        let down = State {
+            direction: Direction::Down,
+            ..right
+        };
+        assert_eq!(
+            cfg.nodes[&State { y: 1, ..down }].edges[0].target,
+            Target::State(down)
+        );
+
+        // in string mode the spaces are pushed, which the graph does not follow
+        let cfg = ControlFlowGraph::build(&grid(&["\"a"]));
+        let leaving = cfg
+            .nodes
+            .values()
+            .flat_map(|node| node.edges.iter())
+            .filter(|edge| matches!(edge.target, Target::OffGrid { .. }))
+            .count();
+        assert_eq!(leaving, 1);
+    }
+}
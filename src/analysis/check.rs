@@ -0,0 +1,247 @@
+// src/analysis/check.rs
+
+use crate::analysis::cfg::{ControlFlowGraph, State, Target};
+use crate::analysis::dataflow;
+use crate::engine::semantics::{DivisionByZeroPolicy, StackUnderflowPolicy};
+use crate::entities::Direction;
+use crate::errors::{Diagnostic, RuntimeContext, RuntimeError, RuntimeErrorKind, Severity};
+use std::collections::BTreeSet;
+
+/// Lints found on the control-flow graph, without running the program.
+/// `stack_underflow` and `division_by_zero` are the policies the program is meant to run with.
+pub fn check(
+    grid: &[Vec<char>],
+    stack_underflow: StackUnderflowPolicy,
+    division_by_zero: DivisionByZeroPolicy,
+) -> Vec<Diagnostic> {
+    let cfg = ControlFlowGraph::build(grid);
+    let mut findings = Vec::new();
+    findings.extend(leaving_program(&cfg));
+    findings.extend(unreachable_code(&cfg));
+    findings.extend(dataflow::stack_findings(
+        &cfg,
+        stack_underflow,
+        division_by_zero,
+    ));
+    findings.sort_by_key(|(x, y, _)| (*y, *x));
+    findings.into_iter().map(|(_, _, d)| d).collect()
+}
+
+fn context(cfg: &ControlFlowGraph, x: usize, y: usize, direction: Direction) -> RuntimeContext {
+    RuntimeContext {
+        ip_id: 0,
+        x,
+        y,
+        z: None,
+        direction,
+        instruction: cfg.cell(x, y),
+        stack_top: None,
+        stack: Vec::new(),
+        trail: Vec::new(),
+    }
+}
+
+pub(super) fn finding(
+    cfg: &ControlFlowGraph,
+    severity: Severity,
+    kind: RuntimeErrorKind,
+    (x, y): (usize, usize),
+    direction: Direction,
+    notes: Vec<String>,
+) -> (usize, usize, Diagnostic) {
+    let error = RuntimeError::new(kind, context(cfg, x, y, direction));
+    (x, y, Diagnostic::finding(severity, error, &cfg.grid, notes))
+}
+
+pub(super) fn path_note(cfg: &ControlFlowGraph, state: State) -> String {
+    let path: Vec<String> = cfg
+        .path_to(state)
+        .iter()
+        .chain(std::iter::once(&(state.x, state.y)))
+        .map(|(x, y)| format!("({}, {})", x, y))
+        .collect();
+    format!("path: {}", path.join(" -> "))
+}
+
+/// the `"` that started the string `state` is in: the first one behind it
+fn opening_quote(cfg: &ControlFlowGraph, state: State) -> (usize, usize) {
+    let back = state.direction.reverse();
+    let (mut x, mut y) = (state.x, state.y);
+    for _ in 0..cfg.width * cfg.height {
+        if cfg.cell(x, y) == '"' {
+            return (x, y);
+        }
+        match cfg.advance(x, y, back) {
+            Some(next) => (x, y) = next,
+            None => break,
+        }
+    }
+    (state.x, state.y)
+}
+
+/// edges that move an IP off the program, into the empty space up to the 1024 wrap
+fn leaving_program(cfg: &ControlFlowGraph) -> Vec<(usize, usize, Diagnostic)> {
+    let mut findings = Vec::new();
+    let mut quotes = BTreeSet::new();
+    for (state, node) in &cfg.nodes {
+        for edge in &node.edges {
+            match edge.target {
+                // string mode pushes the spaces, the graph stops following
+                Target::OffGrid { direction, .. } => {
+                    let quote = opening_quote(cfg, *state);
+                    if quotes.insert(quote) {
+                        findings.push(finding(
+                            cfg,
+                            Severity::Warning,
+                            RuntimeErrorKind::UnclosedString,
+                            quote,
+                            direction,
+                            vec![
+                                format!(
+                                    "string mode is still on at ({}, {}) moving {}: it pushes a space for each of the {} empty cells up to the wrap",
+                                    state.x,
+                                    state.y,
+                                    direction,
+                                    cfg.empty_cells(direction)
+                                ),
+                                path_note(cfg, *state),
+                            ],
+                        ));
+                    }
+                }
+                Target::State(next) => {
+                    let Some((x, y)) = cfg.exit(*state, next.direction) else {
+                        continue;
+                    };
+                    findings.push(finding(
+                        cfg,
+                        Severity::Warning,
+                        RuntimeErrorKind::LeavesProgram,
+                        (state.x, state.y),
+                        next.direction,
+                        vec![
+                            format!(
+                                "moving {} the next cell is ({}, {}), outside the {}x{} program; the IP crosses {} empty cells before it wraps back in at ({}, {})",
+                                next.direction,
+                                x,
+                                y,
+                                cfg.width,
+                                cfg.height,
+                                cfg.empty_cells(next.direction),
+                                next.x,
+                                next.y
+                            ),
+                            path_note(cfg, *state),
+                        ],
+                    ));
+                }
+            }
+        }
+    }
+    findings
+}
+
+/// non-space cells no IP reaches, one finding per 4-connected group
+fn unreachable_code(cfg: &ControlFlowGraph) -> Vec<(usize, usize, Diagnostic)> {
+    let reachable = cfg.reachable_cells();
+    let mut dead: BTreeSet<(usize, usize)> = (0..cfg.height)
+        .flat_map(|y| (0..cfg.width).map(move |x| (x, y)))
+        .filter(|&(x, y)| cfg.cell(x, y) != ' ' && !reachable.contains(&(x, y)))
+        .collect();
+    let writers: Vec<String> = cfg
+        .writers()
+        .iter()
+        .map(|state| format!("({}, {})", state.x, state.y))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let mut findings = Vec::new();
+    while let Some(&first) = dead.iter().min_by_key(|(x, y)| (*y, *x)) {
+        // flood fill the group
+        dead.remove(&first);
+        let mut group = vec![first];
+        let mut index = 0;
+        while index < group.len() {
+            let (x, y) = group[index];
+            index += 1;
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for cell in neighbours {
+                if dead.remove(&cell) {
+                    group.push(cell);
+                }
+            }
+        }
+
+        let mut notes = vec![format!("{} cell(s) no IP reaches", group.len())];
+        if !writers.is_empty() {
+            notes.push(format!(
+                "`p` at {} may still write a path here",
+                writers.join(", ")
+            ));
+        }
+        findings.push(finding(
+            cfg,
+            Severity::Warning,
+            RuntimeErrorKind::UnreachableCode,
+            first,
+            Direction::Right,
+            notes,
+        ));
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::testing::grid;
+
+    fn check_default(grid: &[Vec<char>]) -> Vec<Diagnostic> {
+        check(
+            grid,
+            StackUnderflowPolicy::default(),
+            DivisionByZeroPolicy::default(),
+        )
+    }
+
+    fn kinds(diagnostics: &[Diagnostic]) -> Vec<(RuntimeErrorKind, usize, usize)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.error.kind.clone(), d.error.context.x, d.error.context.y))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_program() {
+        assert!(check_default(&grid(&["v @", ">1|", "  >.@"])).is_empty());
+    }
+
+    #[test]
+    fn test_findings() {
+        // unreachable `1+` below, IP leaves on the right after `.`
+        let diagnostics = check_default(&grid(&["1.", "1+"]));
+        assert_eq!(
+            kinds(&diagnostics),
+            vec![
+                (RuntimeErrorKind::LeavesProgram, 1, 0),
+                (RuntimeErrorKind::UnreachableCode, 0, 1),
+            ]
+        );
+        assert!(diagnostics[0].is_static);
+        assert!(diagnostics[0].notes[0]
+            .ends_with("the IP crosses 1022 empty cells before it wraps back in at (0, 0)"));
+        assert_eq!(diagnostics[1].notes[0], "2 cell(s) no IP reaches");
+
+        let diagnostics = check_default(&grid(&["v", "\"", "a"]));
+        assert_eq!(
+            kinds(&diagnostics),
+            vec![(RuntimeErrorKind::UnclosedString, 0, 1)]
+        );
+    }
+}
@@ -0,0 +1,415 @@
+// src/analysis/dataflow.rs
+
+use crate::analysis::cfg::{ControlFlowGraph, EdgeKind, State, Target};
+use crate::analysis::check::{finding, path_note};
+use crate::engine::semantics::{DivisionByZeroPolicy, StackUnderflowPolicy};
+use crate::entities::Direction;
+use crate::errors::{Diagnostic, GridAccess, RuntimeErrorKind, Severity};
+use crate::interfaces::command_registry::CommandRegistry;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+/// What is known about an IP's stack at one point of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbstractStack {
+    /// the top of the stack, last = top; `None` where the value is not constant
+    top: Vec<Option<usize>>,
+    min: usize,
+    /// `None` when the stack can grow without bound
+    max: Option<usize>,
+}
+
+impl AbstractStack {
+    /// constants remembered below the top
+    const TRACKED: usize = 32;
+
+    pub fn empty() -> Self {
+        Self {
+            top: Vec::new(),
+            min: 0,
+            max: Some(0),
+        }
+    }
+
+    /// what holds on both paths
+    pub fn join(&self, other: &Self) -> Self {
+        let len = self.top.len().min(other.top.len());
+        let mut top: Vec<Option<usize>> = self.top[self.top.len() - len..]
+            .iter()
+            .zip(&other.top[other.top.len() - len..])
+            .map(|(a, b)| if a == b { *a } else { None })
+            .collect();
+        // unknown values at the bottom tell nothing
+        let known = top.iter().position(Option::is_some).unwrap_or(top.len());
+        top.drain(..known);
+        Self {
+            top,
+            min: self.min.min(other.min),
+            max: self.max.zip(other.max).map(|(a, b)| a.max(b)),
+        }
+    }
+
+    pub fn push(&mut self, value: Option<usize>) {
+        self.top.push(value);
+        if self.top.len() > Self::TRACKED {
+            self.top.remove(0);
+        }
+        self.min += 1;
+        self.max = self.max.map(|max| max + 1);
+    }
+
+    /// popping an empty stack gives 0, as with the default `push-zero` policy
+    pub fn pop(&mut self) -> Option<usize> {
+        if self.max == Some(0) {
+            return Some(0);
+        }
+        let value = if self.min > 0 {
+            self.top.pop().flatten()
+        } else {
+            // the value or 0 from an underflow
+            None
+        };
+        self.min = self.min.saturating_sub(1);
+        self.max = self.max.map(|max| max - 1);
+        value
+    }
+}
+
+impl fmt::Display for AbstractStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "exactly {} value(s)", max),
+            Some(max) => write!(f, "{} to {} values", self.min, max),
+            None => write!(f, "at least {} value(s)", self.min),
+        }
+    }
+}
+
+/// values `instruction` pops, as its command declares
+fn pops(registry: &CommandRegistry, instruction: char) -> usize {
+    registry
+        .stack_effect(instruction)
+        .map_or(0, |effect| effect.pops)
+}
+
+/// runs `instruction` on `stack` and returns the popped values, first popped first
+fn execute(
+    registry: &CommandRegistry,
+    instruction: char,
+    string_mode: bool,
+    stack: &mut AbstractStack,
+) -> Vec<Option<usize>> {
+    if string_mode {
+        if instruction != '"' {
+            stack.push(Some(instruction as usize));
+        }
+        return Vec::new();
+    }
+    let popped: Vec<Option<usize>> = (0..pops(registry, instruction))
+        .map(|_| stack.pop())
+        .collect();
+    let constant = |f: fn(usize, usize) -> usize| popped[1].zip(popped[0]).map(|(a, b)| f(a, b));
+    match instruction {
+        '0'..='9' => stack.push(instruction.to_digit(10).map(|d| d as usize)),
+        '+' => stack.push(constant(usize::wrapping_add)),
+        '-' => stack.push(constant(usize::wrapping_sub)),
+        '*' => stack.push(constant(usize::wrapping_mul)),
+        // signed like the commands; division by zero pushes 0
+        '/' => stack.push(constant(|a, b| {
+            (a as isize)
+                .checked_div(b as isize)
+                .map_or(0, |v| v as usize)
+        })),
+        '%' => stack.push(constant(|a, b| {
+            (a as isize)
+                .checked_rem(b as isize)
+                .map_or(0, |v| v as usize)
+        })),
+        '`' => stack.push(constant(|a, b| (a > b) as usize)),
+        '!' => stack.push(popped[0].map(|v| (v == 0) as usize)),
+        ':' => {
+            stack.push(popped[0]);
+            stack.push(popped[0]);
+        }
+        '\\' => {
+            stack.push(popped[0]);
+            stack.push(popped[1]);
+        }
+        '&' | '~' | 'g' => stack.push(None),
+        _ => {}
+    }
+    popped
+}
+
+/// the stack on arrival at every state some IP can reach, assuming the default policies
+pub fn analyze(
+    cfg: &ControlFlowGraph,
+    registry: &CommandRegistry,
+) -> BTreeMap<State, AbstractStack> {
+    let mut stacks: BTreeMap<State, AbstractStack> = BTreeMap::new();
+    let mut queue: VecDeque<State> = VecDeque::new();
+    for start in &cfg.starts {
+        stacks.insert(*start, AbstractStack::empty());
+        queue.push_back(*start);
+    }
+    let mut queued: BTreeSet<State> = queue.iter().copied().collect();
+
+    while let Some(state) = queue.pop_front() {
+        queued.remove(&state);
+        let node = &cfg.nodes[&state];
+        let mut stack = stacks[&state].clone();
+        let popped = execute(registry, node.instruction, state.string_mode, &mut stack);
+        for edge in &node.edges {
+            // a constant condition only takes one side
+            match (edge.kind, popped.first()) {
+                (EdgeKind::Zero, Some(Some(v))) if *v != 0 => continue,
+                (EdgeKind::NonZero, Some(Some(0))) => continue,
+                _ => {}
+            }
+            let Target::State(next) = edge.target else {
+                continue;
+            };
+            let merged = match stacks.get(&next) {
+                None => stack.clone(),
+                Some(old) => {
+                    let mut merged = old.join(&stack);
+                    // widen: a stack that keeps growing around a loop has no upper bound
+                    if merged.max != old.max {
+                        merged.max = None;
+                    }
+                    if merged == *old {
+                        continue;
+                    }
+                    merged
+                }
+            };
+            stacks.insert(next, merged);
+            if queued.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    stacks
+}
+
+/// underflows, division by a constant 0, `p`/`g` off the program and `t` with bad deltas
+///
+/// Under `push-zero` an underflow is well-defined, so only one that always happens is reported.
+/// A division by 0 is an error only when `division_by_zero` stops the IP.
+pub fn stack_findings(
+    cfg: &ControlFlowGraph,
+    stack_underflow: StackUnderflowPolicy,
+    division_by_zero: DivisionByZeroPolicy,
+) -> Vec<(usize, usize, Diagnostic)> {
+    let registry = CommandRegistry::new();
+    // every direction an IP can execute a cell in, joined
+    let mut cells: BTreeMap<(usize, usize), (State, AbstractStack)> = BTreeMap::new();
+    for (state, stack) in analyze(cfg, &registry) {
+        if state.string_mode {
+            continue;
+        }
+        cells
+            .entry((state.x, state.y))
+            .and_modify(|(_, joined)| *joined = joined.join(&stack))
+            .or_insert((state, stack));
+    }
+
+    let mut findings = Vec::new();
+    for ((x, y), (state, stack)) in cells {
+        let instruction = cfg.cell(x, y);
+        let mut report = |severity, kind, note: String| {
+            findings.push(finding(
+                cfg,
+                severity,
+                kind,
+                (x, y),
+                state.direction,
+                vec![note, path_note(cfg, state)],
+            ));
+        };
+
+        let needed = pops(&registry, instruction);
+        let always = stack.max.is_some_and(|max| max < needed);
+        let severity = match stack_underflow {
+            StackUnderflowPolicy::Error if always => Some(Severity::Error),
+            StackUnderflowPolicy::PushZero if !always => None,
+            _ => Some(Severity::Warning),
+        };
+        if let Some(severity) = severity.filter(|_| stack.min < needed) {
+            let outcome = match stack_underflow {
+                StackUnderflowPolicy::Error => "the IP stops with an error",
+                StackUnderflowPolicy::Reflect => "missing values are 0 and the IP turns around",
+                StackUnderflowPolicy::PushZero | StackUnderflowPolicy::WarnOnce => {
+                    "missing values are 0"
+                }
+            };
+            report(
+                severity,
+                RuntimeErrorKind::StackUnderflow,
+                format!(
+                    "{:?} pops {} value(s), the stack holds {} here; {}",
+                    instruction, needed, stack, outcome
+                ),
+            );
+        }
+
+        let popped = execute(&registry, instruction, false, &mut stack.clone());
+        match (instruction, popped.as_slice()) {
+            ('/' | '%', [Some(0), ..]) => {
+                let (severity, outcome) = match division_by_zero {
+                    DivisionByZeroPolicy::Error => (Severity::Error, "the IP stops with an error"),
+                    DivisionByZeroPolicy::Prompt => {
+                        (Severity::Warning, "the user is asked for the result")
+                    }
+                    DivisionByZeroPolicy::Reflect => (Severity::Warning, "the IP turns around"),
+                    DivisionByZeroPolicy::PushZero | DivisionByZeroPolicy::WarnOnce => {
+                        (Severity::Warning, "the result is 0")
+                    }
+                };
+                report(
+                    severity,
+                    RuntimeErrorKind::DivisionByZero,
+                    format!("the divisor is always 0; {}", outcome),
+                );
+            }
+            ('p' | 'g', [Some(py), Some(px), ..]) => {
+                let (px, py) = (*px, *py);
+                let inside = cfg.grid.get(py).is_some_and(|row| px < row.len());
+                if !inside {
+                    let access = if instruction == 'p' {
+                        GridAccess::Put
+                    } else {
+                        GridAccess::Get
+                    };
+                    report(
+                        Severity::Error,
+                        RuntimeErrorKind::OutOfBounds {
+                            access,
+                            x: px,
+                            y: py,
+                            z: None,
+                        },
+                        format!(
+                            "the program is {}x{}; the coordinates are always ({}, {})",
+                            cfg.width, cfg.height, px as isize, py as isize
+                        ),
+                    );
+                }
+            }
+            ('t', [Some(dy), Some(dx)])
+                if Direction::from_dx_dy(*dx as isize, *dy as isize).is_none() =>
+            {
+                report(
+                    Severity::Warning,
+                    RuntimeErrorKind::InvalidDelta { dx: *dx, dy: *dy },
+                    format!(
+                        "the new IP moves {} like its parent instead",
+                        state.direction
+                    ),
+                );
+            }
+            _ => {}
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::testing::grid;
+
+    fn findings(lines: &[&str]) -> Vec<(Severity, String, usize, usize)> {
+        findings_under(lines, StackUnderflowPolicy::default())
+    }
+
+    fn findings_under(
+        lines: &[&str],
+        stack_underflow: StackUnderflowPolicy,
+    ) -> Vec<(Severity, String, usize, usize)> {
+        findings_with(lines, stack_underflow, DivisionByZeroPolicy::Error)
+    }
+
+    fn findings_with(
+        lines: &[&str],
+        stack_underflow: StackUnderflowPolicy,
+        division_by_zero: DivisionByZeroPolicy,
+    ) -> Vec<(Severity, String, usize, usize)> {
+        let cfg = ControlFlowGraph::build(&grid(lines));
+        stack_findings(&cfg, stack_underflow, division_by_zero)
+            .into_iter()
+            .map(|(x, y, d)| (d.severity, d.error.kind.to_string(), x, y))
+            .collect()
+    }
+
+    #[test]
+    fn test_join_and_widen() {
+        let mut a = AbstractStack::empty();
+        a.push(Some(1));
+        a.push(Some(2));
+        let mut b = AbstractStack::empty();
+        b.push(Some(2));
+        let joined = a.join(&b);
+        assert_eq!((joined.min, joined.max), (1, Some(2)));
+        assert_eq!(joined.top, vec![Some(2)]);
+        assert_eq!(joined.to_string(), "1 to 2 values");
+
+        // a loop pushing forever: the depth at the loop head is unbounded
+        let cfg = ControlFlowGraph::build(&grid(&[">1v", "^ <"]));
+        let stacks = analyze(&cfg, &CommandRegistry::new());
+        assert_eq!(stacks.values().map(|s| s.max).min(), Some(None));
+    }
+
+    #[test]
+    fn test_findings() {
+        // popping 0 is fine by default, but `+` never sees two values here
+        assert_eq!(
+            findings(&["1+.@"]),
+            vec![(Severity::Warning, "stack underflow".to_string(), 1, 0)]
+        );
+        assert_eq!(
+            findings_under(&["1+.@"], StackUnderflowPolicy::Error),
+            vec![(Severity::Error, "stack underflow".to_string(), 1, 0)]
+        );
+        // a branch that may or may not push first
+        let branch = ["&v", "v_12v", ">>>>>+.@"];
+        assert!(findings(&branch).is_empty());
+        assert_eq!(
+            findings_under(&branch, StackUnderflowPolicy::Error),
+            vec![(Severity::Warning, "stack underflow".to_string(), 5, 2)]
+        );
+        assert_eq!(
+            findings(&["50/.@"]),
+            vec![(Severity::Error, "division by zero".to_string(), 2, 0)]
+        );
+        // the default policy pushes 0 with a warning
+        assert_eq!(
+            findings_with(
+                &["50%.@"],
+                StackUnderflowPolicy::default(),
+                DivisionByZeroPolicy::default()
+            ),
+            vec![(Severity::Warning, "division by zero".to_string(), 2, 0)]
+        );
+        assert_eq!(
+            findings(&["199p@", "00g.@"]),
+            vec![(
+                Severity::Error,
+                "attempt to set value out of bounds at (9, 9)".to_string(),
+                3,
+                0
+            )]
+        );
+        assert_eq!(
+            findings(&["12t@"]),
+            vec![(
+                Severity::Warning,
+                "invalid delta (1, 2) for `t`".to_string(),
+                2,
+                0
+            )]
+        );
+        // constant conditions only follow one side; `1|` never reaches the `.`
+        assert!(findings(&["v @", ">1|", "  >.@"]).is_empty());
+    }
+}
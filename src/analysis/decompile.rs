@@ -0,0 +1,848 @@
+// src/analysis/decompile.rs
+
+use crate::analysis::cfg::{BasicBlock, ControlFlowGraph, Edge, EdgeKind, State, Target};
+use crate::analysis::export::{code, describe_put};
+use crate::interfaces::command_registry::CommandRegistry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// A value in the pseudocode. Only reads of the stack, input and the grid get names, so
+/// everything else is a pure expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Number(usize),
+    /// pushed in string mode
+    Char(char),
+    Name(String),
+    /// `b op a`, `>` for `` ` ``
+    Binary(char, Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+impl Expr {
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary('*' | '/' | '%', ..) => 3,
+            Expr::Binary('+' | '-', ..) => 2,
+            Expr::Binary(..) => 1,
+            _ => 4,
+        }
+    }
+
+    fn is_atom(&self) -> bool {
+        self.precedence() == 4 && !matches!(self, Expr::Not(_))
+    }
+
+    fn negate(self) -> Expr {
+        match self {
+            Expr::Not(inner) => *inner,
+            other => Expr::Not(Box::new(other)),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Char(c) => write!(f, "{:?}", c),
+            Expr::Name(name) => write!(f, "{}", name),
+            Expr::Not(inner) if inner.is_atom() => write!(f, "!{}", inner),
+            Expr::Not(inner) => write!(f, "!({})", inner),
+            Expr::Binary(op, b, a) => {
+                let precedence = self.precedence();
+                if b.precedence() < precedence {
+                    write!(f, "({})", b)?;
+                } else {
+                    write!(f, "{}", b)?;
+                }
+                write!(f, " {} ", op)?;
+                // evaluation is left to right, so an equal right operand keeps its parentheses
+                if a.precedence() <= precedence {
+                    write!(f, "({})", a)
+                } else {
+                    write!(f, "{}", a)
+                }
+            }
+        }
+    }
+}
+
+/// One block's statements, from running its instructions on a symbolic stack.
+struct Evaluation {
+    lines: Vec<String>,
+    /// what `_` or `|` popped
+    condition: Option<Expr>,
+}
+
+struct Evaluator<'a> {
+    registry: &'a CommandRegistry,
+    names: &'a mut usize,
+    lines: Vec<String>,
+    /// `,` of string-mode characters, merged until something else happens
+    printing: String,
+    stack: Vec<Expr>,
+}
+
+impl Evaluator<'_> {
+    fn fresh(&mut self) -> String {
+        *self.names += 1;
+        format!("v{}", *self.names - 1)
+    }
+
+    fn line(&mut self, line: String) {
+        self.flush_printing();
+        self.lines.push(line);
+    }
+
+    fn flush_printing(&mut self) {
+        match self.printing.chars().count() {
+            0 => {}
+            1 => {
+                let c = self.printing.remove(0);
+                self.lines.push(format!("print_char({:?})", c));
+            }
+            _ => {
+                let text = std::mem::take(&mut self.printing);
+                self.lines.push(format!("print_chars({:?})", text));
+            }
+        }
+    }
+
+    /// a value pushed earlier in the block, or one from the stack it started with
+    fn pop(&mut self) -> Expr {
+        match self.stack.pop() {
+            Some(expr) => expr,
+            None => {
+                let name = self.fresh();
+                self.line(format!("{} = pop()", name));
+                Expr::Name(name)
+            }
+        }
+    }
+
+    /// `value` under a name if using it twice would repeat a computation
+    fn keep(&mut self, value: Expr) -> Expr {
+        if value.is_atom() {
+            return value;
+        }
+        let name = self.fresh();
+        self.line(format!("{} = {}", name, value));
+        Expr::Name(name)
+    }
+
+    fn read(&mut self, source: String) {
+        let name = self.fresh();
+        self.line(format!("{} = {}", name, source));
+        self.stack.push(Expr::Name(name));
+    }
+
+    /// `spawn` names the function the new IP runs
+    fn instruction(&mut self, c: char, string_mode: bool, spawn: &str) -> Option<Expr> {
+        if string_mode {
+            if c != '"' {
+                self.stack.push(Expr::Char(c));
+            }
+            return None;
+        }
+        // unknown instructions are skipped, as with the default policy
+        let effect = self.registry.stack_effect(c)?;
+        if c == '$' {
+            if self.stack.pop().is_none() {
+                self.line("pop()".to_string());
+            }
+            return None;
+        }
+        // args[0] is the top of the stack
+        let mut args: Vec<Expr> = (0..effect.pops).map(|_| self.pop()).collect();
+        match c {
+            '0'..='9' => self.stack.push(Expr::Number(c as usize - '0' as usize)),
+            '+' | '-' | '*' | '/' | '%' | '`' => {
+                let op = if c == '`' { '>' } else { c };
+                let (a, b) = (args.remove(0), args.remove(0));
+                self.stack.push(Expr::Binary(op, Box::new(b), Box::new(a)));
+            }
+            '!' => self.stack.push(args.remove(0).negate()),
+            ':' => {
+                let value = self.keep(args.remove(0));
+                self.stack.push(value.clone());
+                self.stack.push(value);
+            }
+            '\\' => self.stack.extend(args),
+            '.' => self.line(format!("print({})", args[0])),
+            ',' => match args[0] {
+                Expr::Char(c) => self.printing.push(c),
+                ref value => self.line(format!("print_char({})", value)),
+            },
+            '&' => self.read("input_number()".to_string()),
+            '~' => self.read("input_char()".to_string()),
+            'g' => self.read(format!("grid[{}, {}]", args[1], args[0])),
+            'p' => self.line(format!("grid[{}, {}] = {}", args[1], args[0], args[2])),
+            't' => {
+                // the new IP copies the real stack
+                self.spill();
+                self.line(format!("spawn {}(dx: {}, dy: {})", spawn, args[1], args[0]));
+            }
+            '_' | '|' => return args.pop(),
+            // movement and no-ops pop nothing
+            _ if effect.pushes == 0 && effect.pops > 0 => {
+                let args: Vec<String> = args.iter().rev().map(ToString::to_string).collect();
+                self.line(format!("call {:?}({})", c, args.join(", ")));
+            }
+            _ if effect.pushes > 0 => {
+                // a command this file does not know: its results are opaque values
+                let args: Vec<String> = args.iter().rev().map(ToString::to_string).collect();
+                let names: Vec<String> = (0..effect.pushes).map(|_| self.fresh()).collect();
+                self.line(format!(
+                    "{} = call {:?}({})",
+                    names.join(", "),
+                    c,
+                    args.join(", ")
+                ));
+                self.stack.extend(names.into_iter().map(Expr::Name));
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn finish(mut self, condition: Option<Expr>) -> Evaluation {
+        self.spill();
+        self.flush_printing();
+        Evaluation {
+            lines: self.lines,
+            condition,
+        }
+    }
+
+    /// pushes for what is left on the symbolic stack, characters merged
+    fn spill(&mut self) {
+        let mut chars = String::new();
+        let stack = std::mem::take(&mut self.stack);
+        for value in stack {
+            match value {
+                Expr::Char(c) => chars.push(c),
+                value => {
+                    self.push_chars(&mut chars);
+                    self.line(format!("push({})", value));
+                }
+            }
+        }
+        self.push_chars(&mut chars);
+    }
+
+    fn push_chars(&mut self, chars: &mut String) {
+        match chars.chars().count() {
+            0 => {}
+            1 => self.line(format!("push({:?})", chars.remove(0))),
+            _ => self.line(format!("push_chars({:?})", std::mem::take(chars))),
+        }
+    }
+}
+
+/// the virtual node every ending block leads to, for post-dominators
+const EXIT: usize = usize::MAX;
+
+/// Structured pseudocode for one IP's region of blocks.
+struct Function<'a> {
+    decompiler: &'a Decompiler,
+    /// natural loops: header -> body
+    loops: BTreeMap<usize, BTreeSet<usize>>,
+    ipdom: BTreeMap<usize, usize>,
+    emitted: BTreeSet<usize>,
+    /// blocks and loops jumped to from elsewhere, found by a first pass
+    labels: BTreeSet<usize>,
+    jumps: BTreeSet<usize>,
+    /// enclosing loops: header and the block after the loop
+    loop_stack: Vec<(usize, Option<usize>)>,
+    /// nothing follows the current sequence in the innermost loop body, so `continue` is implied
+    tail: bool,
+    out: Vec<String>,
+    indent: usize,
+    names: usize,
+}
+
+impl<'a> Function<'a> {
+    fn new(decompiler: &'a Decompiler, entry: usize) -> Self {
+        let region = decompiler.region(entry);
+        let mut function = Self {
+            decompiler,
+            loops: BTreeMap::new(),
+            ipdom: BTreeMap::new(),
+            emitted: BTreeSet::new(),
+            labels: BTreeSet::new(),
+            jumps: BTreeSet::new(),
+            loop_stack: Vec::new(),
+            tail: false,
+            out: Vec::new(),
+            indent: 1,
+            names: 0,
+        };
+        function.find_loops(entry, &region);
+        function.find_post_dominators(&region);
+        function
+    }
+
+    fn successors(&self, block: usize) -> Vec<usize> {
+        self.decompiler.successors(block)
+    }
+
+    /// back edges of a depth-first search, and the natural loops they close
+    fn find_loops(&mut self, entry: usize, region: &BTreeSet<usize>) {
+        let mut on_path = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut back_edges = Vec::new();
+        let mut stack = vec![(entry, 0)];
+        visited.insert(entry);
+        on_path.insert(entry);
+        while let Some((block, next)) = stack.last_mut() {
+            let block = *block;
+            let successors = self.successors(block);
+            if let Some(&successor) = successors.get(*next) {
+                *next += 1;
+                if on_path.contains(&successor) {
+                    back_edges.push((block, successor));
+                } else if visited.insert(successor) {
+                    on_path.insert(successor);
+                    stack.push((successor, 0));
+                }
+            } else {
+                on_path.remove(&block);
+                stack.pop();
+            }
+        }
+
+        let mut predecessors: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for &block in region {
+            for successor in self.successors(block) {
+                predecessors.entry(successor).or_default().push(block);
+            }
+        }
+        for (tail, header) in back_edges {
+            let body = self.loops.entry(header).or_default();
+            body.insert(header);
+            let mut work = vec![tail];
+            while let Some(block) = work.pop() {
+                if body.insert(block) {
+                    work.extend(predecessors.get(&block).into_iter().flatten());
+                }
+            }
+        }
+    }
+
+    fn find_post_dominators(&mut self, region: &BTreeSet<usize>) {
+        let all: BTreeSet<usize> = region.iter().copied().chain([EXIT]).collect();
+        let mut pdom: BTreeMap<usize, BTreeSet<usize>> =
+            region.iter().map(|&block| (block, all.clone())).collect();
+        pdom.insert(EXIT, BTreeSet::from([EXIT]));
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in region.iter().rev() {
+                let mut next: Vec<usize> = self.successors(block);
+                if self.decompiler.ends(block) {
+                    next.push(EXIT);
+                }
+                let mut set = next
+                    .iter()
+                    .map(|n| pdom[n].clone())
+                    .reduce(|a, b| a.intersection(&b).copied().collect())
+                    .unwrap_or_default();
+                set.insert(block);
+                if set != pdom[&block] {
+                    pdom.insert(block, set);
+                    changed = true;
+                }
+            }
+        }
+        for &block in region {
+            // the closest strict post-dominator has the most post-dominators itself
+            let closest = pdom[&block]
+                .iter()
+                .filter(|&&d| d != block)
+                .max_by_key(|d| pdom[d].len());
+            // blocks that never end post-dominate nothing
+            if let Some(&d) = closest.filter(|_| pdom[&block].len() < all.len()) {
+                if d != EXIT {
+                    self.ipdom.insert(block, d);
+                }
+            }
+        }
+    }
+
+    fn line(&mut self, text: impl Into<String>) {
+        self.out
+            .push(format!("{}{}", "    ".repeat(self.indent), text.into()));
+    }
+
+    /// `continue` or `break` when `block` starts or follows an enclosing loop
+    fn loop_jump(&mut self, block: usize) -> Option<String> {
+        for (depth, &(header, follow)) in self.loop_stack.iter().enumerate().rev() {
+            let innermost = depth + 1 == self.loop_stack.len();
+            let keyword = if block == header {
+                "continue"
+            } else if Some(block) == follow {
+                "break"
+            } else {
+                continue;
+            };
+            if innermost && keyword == "continue" && self.tail {
+                return Some(String::new());
+            }
+            if innermost {
+                return Some(keyword.to_string());
+            }
+            self.jumps.insert(header);
+            return Some(format!("{} b{}", keyword, header));
+        }
+        None
+    }
+
+    /// where control goes once the loop at `header` is left
+    fn follow(&self, header: usize) -> Option<usize> {
+        let body = &self.loops[&header];
+        if let Some(&after) = self.ipdom.get(&header) {
+            if !body.contains(&after) {
+                return Some(after);
+            }
+        }
+        body.iter()
+            .flat_map(|&block| self.successors(block))
+            .find(|block| !body.contains(block))
+    }
+
+    fn sequence(&mut self, mut next: Option<usize>, stop: Option<usize>) {
+        while let Some(block) = next {
+            if Some(block) == stop {
+                return;
+            }
+            if let Some(jump) = self.loop_jump(block) {
+                if !jump.is_empty() {
+                    self.line(jump);
+                }
+                return;
+            }
+            if self.emitted.contains(&block) {
+                self.jumps.insert(block);
+                self.line(format!("goto b{}", block));
+                return;
+            }
+            if self.loops.contains_key(&block) {
+                let follow = self.follow(block);
+                if self.labels.contains(&block) {
+                    self.line(format!("b{}: loop {{", block));
+                } else {
+                    self.line("loop {");
+                }
+                self.indent += 1;
+                self.loop_stack.push((block, follow));
+                let tail = std::mem::replace(&mut self.tail, true);
+                let inside = self.block(block);
+                self.sequence(inside, None);
+                self.tail = tail;
+                self.loop_stack.pop();
+                self.indent -= 1;
+                self.line("}");
+                next = follow;
+                continue;
+            }
+            if self.labels.contains(&block) {
+                self.indent -= 1;
+                self.line(format!("b{}:", block));
+                self.indent += 1;
+            }
+            next = self.block(block);
+        }
+    }
+
+    /// the lines `f` adds, taken out of the output
+    fn capture(&mut self, f: impl FnOnce(&mut Self)) -> Vec<String> {
+        let start = self.out.len();
+        f(self);
+        self.out.split_off(start)
+    }
+
+    fn side(&mut self, target: &Target, stop: Option<usize>) {
+        match target {
+            Target::State(state) => {
+                let block = self.decompiler.block_of[state];
+                self.sequence(Some(block), stop);
+            }
+            Target::OffGrid { x, y, .. } => {
+                self.line(format!("exit  // off the program at ({}, {})", x, y))
+            }
+        }
+    }
+
+    /// the statements of `block`, and the block to continue with
+    fn block(&mut self, block: usize) -> Option<usize> {
+        self.emitted.insert(block);
+        let decompiler = self.decompiler;
+        let evaluation = decompiler.evaluate(block, &mut self.names);
+        for line in evaluation.lines {
+            self.line(line);
+        }
+        let exits = &decompiler.blocks[block].exits;
+        let zero = exits.iter().find(|edge| edge.kind == EdgeKind::Zero);
+        let nonzero = exits.iter().find(|edge| edge.kind == EdgeKind::NonZero);
+        if let (Some(zero), Some(nonzero)) = (zero, nonzero) {
+            let condition = evaluation
+                .condition
+                .unwrap_or_else(|| Expr::Name("pop()".to_string()));
+            // `0_` and the like always go one way
+            let taken = match condition {
+                Expr::Number(0) => Some(zero),
+                Expr::Char('\0') => Some(zero),
+                Expr::Number(_) | Expr::Char(_) => Some(nonzero),
+                _ => None,
+            };
+            if let Some(edge) = taken {
+                return self.follow_edge(edge);
+            }
+            let join = self.join(block);
+            self.indent += 1;
+            let tail = self.tail;
+            self.tail = tail && join.is_none();
+            let mut then = self.capture(|f| f.side(&nonzero.target, join));
+            let mut otherwise = self.capture(|f| f.side(&zero.target, join));
+            self.tail = tail;
+            self.indent -= 1;
+            // both sides stop the IP: say so once, after the if
+            let ends = |lines: &[String]| lines.last().is_some_and(|l| l.trim() == "end");
+            let end = join.is_none() && ends(&then) && ends(&otherwise);
+            if end {
+                then.pop();
+                otherwise.pop();
+            }
+            match (then.is_empty(), otherwise.is_empty()) {
+                (true, true) => {}
+                (false, true) => {
+                    self.line(format!("if {} {{", condition));
+                    self.out.extend(then);
+                    self.line("}");
+                }
+                (true, false) => {
+                    self.line(format!("if {} {{", condition.negate()));
+                    self.out.extend(otherwise);
+                    self.line("}");
+                }
+                (false, false) => {
+                    self.line(format!("if {} {{", condition));
+                    self.out.extend(then);
+                    self.line("} else {");
+                    self.out.extend(otherwise);
+                    self.line("}");
+                }
+            }
+            if end {
+                self.line("end");
+            }
+            return join;
+        }
+        match exits.iter().find(|edge| edge.kind == EdgeKind::Next) {
+            Some(edge) => self.follow_edge(edge),
+            None => {
+                self.line("end");
+                None
+            }
+        }
+    }
+
+    fn follow_edge(&mut self, edge: &Edge) -> Option<usize> {
+        match edge.target {
+            Target::State(state) => Some(self.decompiler.block_of[&state]),
+            target => {
+                self.side(&target, None);
+                None
+            }
+        }
+    }
+
+    /// where the sides of a branch at `block` meet again, inside the innermost loop
+    fn join(&self, block: usize) -> Option<usize> {
+        let join = *self.ipdom.get(&block)?;
+        // leaving or restarting a loop is a `break` or `continue` on each side
+        let jumps = self
+            .loop_stack
+            .iter()
+            .any(|&(header, follow)| join == header || Some(join) == follow);
+        let inside = match self.loop_stack.last() {
+            Some((header, _)) => self.loops[header].contains(&join),
+            None => true,
+        };
+        (inside && !jumps).then_some(join)
+    }
+
+    fn render(mut self, entry: usize) -> Vec<String> {
+        // the first pass finds jump targets, the second labels them
+        self.sequence(Some(entry), None);
+        let labels = std::mem::take(&mut self.jumps);
+        self.emitted.clear();
+        self.out.clear();
+        self.names = 0;
+        self.labels = labels;
+        self.sequence(Some(entry), None);
+        self.out
+    }
+}
+
+struct Decompiler {
+    cfg: ControlFlowGraph,
+    registry: CommandRegistry,
+    blocks: Vec<BasicBlock>,
+    block_of: BTreeMap<State, usize>,
+    /// cells a `p` rewrites, and the notes saying so
+    rewritten: BTreeMap<(usize, usize), Vec<String>>,
+}
+
+impl Decompiler {
+    fn new(grid: &[Vec<char>]) -> Self {
+        let cfg = ControlFlowGraph::build(grid);
+        let blocks = cfg.basic_blocks();
+        let block_of = blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(index, block)| block.states.iter().map(move |state| (*state, index)))
+            .collect();
+        let mut rewritten: BTreeMap<(usize, usize), Vec<String>> = BTreeMap::new();
+        for put in cfg.put_targets() {
+            if let Some(target) = put.target {
+                rewritten
+                    .entry(target)
+                    .or_default()
+                    .push(describe_put(&put));
+            }
+        }
+        Self {
+            cfg,
+            registry: CommandRegistry::new(),
+            blocks,
+            block_of,
+            rewritten,
+        }
+    }
+
+    /// blocks control moves to, not counting IPs created by `t`
+    fn successors(&self, block: usize) -> Vec<usize> {
+        let mut successors = Vec::new();
+        for edge in &self.blocks[block].exits {
+            if let (EdgeKind::Next | EdgeKind::Zero | EdgeKind::NonZero, Target::State(state)) =
+                (edge.kind, edge.target)
+            {
+                let next = self.block_of[&state];
+                if !successors.contains(&next) {
+                    successors.push(next);
+                }
+            }
+        }
+        successors
+    }
+
+    /// the IP can stop or leave the program at the end of `block`
+    fn ends(&self, block: usize) -> bool {
+        let exits = &self.blocks[block].exits;
+        exits.is_empty()
+            || exits
+                .iter()
+                .any(|edge| matches!(edge.target, Target::OffGrid { .. }))
+    }
+
+    fn region(&self, entry: usize) -> BTreeSet<usize> {
+        let mut region = BTreeSet::from([entry]);
+        let mut work = vec![entry];
+        while let Some(block) = work.pop() {
+            for next in self.successors(block) {
+                if region.insert(next) {
+                    work.push(next);
+                }
+            }
+        }
+        region
+    }
+
+    fn spawned(&self, block: usize) -> Option<usize> {
+        self.blocks[block].exits.iter().find_map(|edge| match edge {
+            Edge {
+                kind: EdgeKind::Spawn,
+                target: Target::State(state),
+            } => Some(self.block_of[state]),
+            _ => None,
+        })
+    }
+
+    fn evaluate(&self, block: usize, names: &mut usize) -> Evaluation {
+        let states = &self.blocks[block].states;
+        // from the first rewritten cell on, the block is not known before it runs
+        let known = states
+            .iter()
+            .position(|s| self.rewritten.contains_key(&(s.x, s.y)))
+            .unwrap_or(states.len());
+        let spawn = match self.spawned(block) {
+            Some(target) => format!("thread_b{}", target),
+            None => "thread".to_string(),
+        };
+        let mut evaluator = Evaluator {
+            registry: &self.registry,
+            names,
+            lines: Vec::new(),
+            printing: String::new(),
+            stack: Vec::new(),
+        };
+        let mut condition = None;
+        for state in &states[..known] {
+            let c = self.cfg.cell(state.x, state.y);
+            condition = evaluator.instruction(c, state.string_mode, &spawn);
+        }
+        let mut evaluation = evaluator.finish(condition);
+        if known < states.len() {
+            evaluation.lines.extend(self.opaque(&states[known..]));
+        }
+        evaluation
+    }
+
+    /// the code of `states` as loaded, and the writes that change it
+    fn opaque(&self, states: &[State]) -> Vec<String> {
+        let (first, last) = (states[0], states[states.len() - 1]);
+        let run = BasicBlock {
+            states: states.to_vec(),
+            exits: Vec::new(),
+        };
+        let mut lines = vec![format!(
+            "opaque {:?}  // ({}, {})..({}, {}) may change while running",
+            code(&self.cfg, &run),
+            first.x,
+            first.y,
+            last.x,
+            last.y
+        )];
+        let cells: BTreeSet<(usize, usize)> = states.iter().map(|s| (s.x, s.y)).collect();
+        for cell in cells {
+            for note in self.rewritten.get(&cell).into_iter().flatten() {
+                lines.push(format!("// {}", note));
+            }
+        }
+        lines
+    }
+}
+
+/// Structured pseudocode for `grid`, loaded from `name`: one function per start and per IP
+/// created by `t`, with loops and if/else rebuilt from the control-flow graph.
+pub fn decompile(grid: &[Vec<char>], name: &str) -> String {
+    let decompiler = Decompiler::new(grid);
+    let mut out = format!(
+        "// {}, decompiled from the loaded program\n\
+         // pop() gives 0 on an empty stack; `opaque` code is rewritten by `p` while running\n",
+        name
+    );
+    for put in decompiler.cfg.put_targets() {
+        if put.target.is_none() {
+            out.push_str(&format!(
+                "// {}: any code may change while running\n",
+                describe_put(&put)
+            ));
+        }
+    }
+
+    let mut functions: Vec<(usize, String)> = decompiler
+        .cfg
+        .starts
+        .iter()
+        .map(|start| {
+            (
+                decompiler.block_of[start],
+                format!(
+                    "ip at ({}, {}) moving {}",
+                    start.x, start.y, start.direction
+                ),
+            )
+        })
+        .collect();
+    let mut index = 0;
+    while index < functions.len() {
+        let (entry, title) = functions[index].clone();
+        index += 1;
+        let function = Function::new(&decompiler, entry);
+        for block in decompiler.region(entry) {
+            if let Some(target) = decompiler.spawned(block) {
+                if !functions.iter().any(|(e, _)| *e == target) {
+                    let state = decompiler.blocks[target].states[0];
+                    functions.push((
+                        target,
+                        format!(
+                            "thread_b{} at ({}, {}) moving {}",
+                            target, state.x, state.y, state.direction
+                        ),
+                    ));
+                }
+            }
+        }
+        out.push_str(&format!("\n{} {{\n", title));
+        for line in function.render(entry) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::testing::grid;
+
+    fn body(lines: &[&str]) -> String {
+        let out = decompile(&grid(lines), "prog.bf");
+        out.split_once("\n\n").unwrap().1.to_string()
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(
+            body(&["&123*+-.\"!iH\",,,@"]),
+            "ip at (0, 0) moving right {\n    \
+             v0 = input_number()\n    \
+             print(v0 - (1 + 2 * 3))\n    \
+             print_chars(\"Hi!\")\n    \
+             end\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_loops_and_branches() {
+        // count down from the input, then print a newline if it was odd
+        assert_eq!(
+            body(&["&:>:.1-:v", "  ^     _$2%v", "       @,+55_@",]),
+            "ip at (0, 0) moving right {\n    \
+             v0 = input_number()\n    \
+             push(v0)\n    \
+             push(v0)\n    \
+             loop {\n        \
+             v1 = pop()\n        \
+             print(v1)\n        \
+             v2 = v1 - 1\n        \
+             push(v2)\n        \
+             if !v2 {\n            \
+             break\n        \
+             }\n    \
+             }\n    \
+             pop()\n    \
+             v3 = pop()\n    \
+             if v3 % 2 {\n        \
+             print_char(5 + 5)\n    \
+             }\n    \
+             end\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_self_modification_is_opaque() {
+        let out = body(&["\"@\"71p&v", "@,\"B\"  _\"A\",@"]);
+        assert!(out.contains("opaque \"_\"  // (7, 1)..(7, 1) may change while running"));
+        assert!(out.contains("if pop() {"));
+        assert!(out.contains("// p at (5, 0) writes '@' to (7, 1)"));
+        assert!(out.contains("grid[7, 1] = '@'"));
+    }
+}
@@ -0,0 +1,252 @@
+// src/analysis/export.rs
+
+use crate::analysis::cfg::{BasicBlock, ControlFlowGraph, EdgeKind, PutTarget, State, Target};
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum CfgFormat {
+    /// one line per basic block
+    #[default]
+    Text,
+    /// Graphviz, e.g. `cfg prog.bf --format dot | dot -Tsvg > prog.svg`
+    Dot,
+}
+
+/// characters per line of a block label
+const LABEL_WIDTH: usize = 32;
+
+impl CfgFormat {
+    pub fn render(self, cfg: &ControlFlowGraph, name: &str) -> String {
+        match self {
+            CfgFormat::Text => text(cfg),
+            CfgFormat::Dot => dot(cfg, name),
+        }
+    }
+}
+
+/// the instructions of a block; spaces only count inside strings
+pub(super) fn code(cfg: &ControlFlowGraph, block: &BasicBlock) -> String {
+    block
+        .states
+        .iter()
+        .map(|state| (state, cfg.cell(state.x, state.y)))
+        .filter(|(state, c)| state.string_mode || *c != ' ')
+        .map(|(_, c)| c)
+        .collect()
+}
+
+fn span(block: &BasicBlock) -> String {
+    let (first, last) = (block.states[0], block.states[block.states.len() - 1]);
+    if first == last {
+        format!("({}, {})", first.x, first.y)
+    } else {
+        format!("({}, {})..({}, {})", first.x, first.y, last.x, last.y)
+    }
+}
+
+fn edge_label(kind: EdgeKind) -> &'static str {
+    match kind {
+        EdgeKind::Next => "",
+        EdgeKind::Zero => "zero",
+        EdgeKind::NonZero => "nonzero",
+        EdgeKind::Spawn => "spawn",
+    }
+}
+
+pub(super) fn describe_put(put: &PutTarget) -> String {
+    let value = match put.value.and_then(|v| char::from_u32(v as u32)) {
+        Some(c) => format!("{:?}", c),
+        None => "a computed value".to_string(),
+    };
+    match put.target {
+        Some((x, y)) => format!(
+            "p at ({}, {}) writes {} to ({}, {})",
+            put.at.x, put.at.y, value, x, y
+        ),
+        None => format!(
+            "p at ({}, {}) writes {} to a computed cell",
+            put.at.x, put.at.y, value
+        ),
+    }
+}
+
+/// block index for every state
+fn block_of(blocks: &[BasicBlock]) -> BTreeMap<State, usize> {
+    blocks
+        .iter()
+        .enumerate()
+        .flat_map(|(index, block)| block.states.iter().map(move |state| (*state, index)))
+        .collect()
+}
+
+/// blocks executing the cell at (x, y)
+fn blocks_at(blocks: &[BasicBlock], x: usize, y: usize) -> Vec<usize> {
+    blocks
+        .iter()
+        .enumerate()
+        .filter(|(_, block)| block.states.iter().any(|s| (s.x, s.y) == (x, y)))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+pub fn text(cfg: &ControlFlowGraph) -> String {
+    let blocks = cfg.basic_blocks();
+    let block_of = block_of(&blocks);
+    let mut out = String::new();
+    for (index, block) in blocks.iter().enumerate() {
+        let exits: Vec<String> = block
+            .exits
+            .iter()
+            .map(|edge| {
+                let to = match edge.target {
+                    Target::State(state) => format!("b{}", block_of[&state]),
+                    Target::OffGrid { x, y, .. } => format!("off program at ({}, {})", x, y),
+                };
+                match edge.kind {
+                    EdgeKind::Next => to,
+                    kind => format!("{}: {}", edge_label(kind), to),
+                }
+            })
+            .collect();
+        out.push_str(&format!(
+            "b{:<4} {:<24} {:?} -> {}\n",
+            index,
+            span(block),
+            code(cfg, block),
+            if exits.is_empty() {
+                "end".to_string()
+            } else {
+                exits.join(", ")
+            }
+        ));
+    }
+    for put in cfg.put_targets() {
+        out.push_str(&format!("note: {}\n", describe_put(&put)));
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// a node label: coordinates, then the code wrapped at `LABEL_WIDTH`, left-aligned
+fn block_label(cfg: &ControlFlowGraph, block: &BasicBlock) -> String {
+    let mut label = format!("{} {}\\l", span(block), block.states[0].direction);
+    let code: Vec<char> = code(cfg, block).chars().collect();
+    for line in code.chunks(LABEL_WIDTH) {
+        label.push_str(&escape(&line.iter().collect::<String>()));
+        label.push_str("\\l");
+    }
+    label
+}
+
+pub fn dot(cfg: &ControlFlowGraph, name: &str) -> String {
+    let blocks = cfg.basic_blocks();
+    let block_of = block_of(&blocks);
+    let puts = cfg.put_targets();
+    let reachable = cfg.reachable_cells();
+
+    let mut out = format!("digraph \"{}\" {{\n", escape(name));
+    out.push_str(
+        "    // built from the program as loaded; dotted red edges mark `p` that may rewrite it\n",
+    );
+    out.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+    for (index, start) in cfg.starts.iter().enumerate() {
+        out.push_str(&format!(
+            "    start{} [shape=point];\n    start{} -> b{};\n",
+            index, index, block_of[start]
+        ));
+    }
+
+    let mut off_grid = 0;
+    for (index, block) in blocks.iter().enumerate() {
+        let ends = block.exits.is_empty();
+        let writes = puts.iter().any(|put| block.states.contains(&put.at));
+        let mut attributes = format!("label=\"{}\"", block_label(cfg, block));
+        if ends {
+            attributes.push_str(", peripheries=2");
+        }
+        if writes {
+            attributes.push_str(", color=red");
+        }
+        out.push_str(&format!("    b{} [{}];\n", index, attributes));
+
+        for edge in &block.exits {
+            let to = match edge.target {
+                Target::State(state) => format!("b{}", block_of[&state]),
+                Target::OffGrid { x, y, .. } => {
+                    off_grid += 1;
+                    out.push_str(&format!(
+                        "    off{} [shape=plaintext, label=\"off program\\n({}, {})\"];\n",
+                        off_grid, x, y
+                    ));
+                    format!("off{}", off_grid)
+                }
+            };
+            let attributes = match edge.kind {
+                EdgeKind::Next => String::new(),
+                EdgeKind::Spawn => " [label=\"spawn\", style=dashed]".to_string(),
+                kind => format!(" [label=\"{}\"]", edge_label(kind)),
+            };
+            out.push_str(&format!("    b{} -> {}{};\n", index, to, attributes));
+        }
+    }
+
+    // self-modification: known targets on the graph get an edge, unknown ones a note
+    for (index, put) in puts.iter().enumerate() {
+        let from = block_of[&put.at];
+        match put.target {
+            Some((x, y)) if reachable.contains(&(x, y)) => {
+                for to in blocks_at(&blocks, x, y) {
+                    out.push_str(&format!(
+                        "    b{} -> b{} [label=\"p ({}, {})\", style=dotted, color=red, constraint=false];\n",
+                        from, to, x, y
+                    ));
+                }
+            }
+            Some(_) => out.push_str(&format!(
+                "    // {}, which no IP executes\n",
+                describe_put(put)
+            )),
+            None => out.push_str(&format!(
+                "    p{} [shape=note, color=red, label=\"{}:\\nany cell may change\"];\n    b{} -> p{} [style=dotted, color=red];\n",
+                index,
+                escape(&describe_put(put)),
+                from,
+                index
+            )),
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::testing::grid;
+
+    #[test]
+    fn test_blocks_and_dot() {
+        // `p` turns the branch at (7, 1) into `@`
+        let cfg = ControlFlowGraph::build(&grid(&["\"@\"71p&v", "@,\"B\"  _\"A\",@"]));
+        assert_eq!(
+            text(&cfg),
+            "b0    (0, 0)..(7, 1)           \"\\\"@\\\"71p&v_\" -> zero: b2, nonzero: b1\n\
+             b1    (6, 1)..(0, 1)           \"\\\"B\\\",@\" -> end\n\
+             b2    (8, 1)..(12, 1)          \"\\\"A\\\",@\" -> end\n\
+             note: p at (5, 0) writes '@' to (7, 1)\n"
+        );
+
+        let dot = dot(&cfg, "prog.bf");
+        assert!(dot.starts_with("digraph \"prog.bf\" {\n"));
+        assert!(dot.contains("start0 -> b0;"));
+        assert!(dot.contains("b0 -> b2 [label=\"zero\"];"));
+        assert!(dot.contains("b1 [label=\"(6, 1)..(0, 1) left\\l\\\"B\\\",@\\l\", peripheries=2];"));
+        assert!(dot.contains("b0 -> b0 [label=\"p (7, 1)\", style=dotted, color=red"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
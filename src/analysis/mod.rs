@@ -0,0 +1,7 @@
+// src/analysis/mod.rs
+
+pub mod cfg;
+pub mod check;
+pub mod dataflow;
+pub mod decompile;
+pub mod export;
@@ -0,0 +1,250 @@
+// src/assembler/generate.rs
+
+use crate::assembler::literal::{self, StringOrder};
+use crate::engine::interpreter::Interpreter;
+use crate::errors::InterpreterError;
+use clap::ValueEnum;
+
+/// prints the stack down to the terminator, entered moving right
+const PRINT_LOOP: &str = ">:#,_@";
+
+/// How a generated program is laid out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Shape {
+    /// everything on one row
+    #[default]
+    Line,
+    /// rows run left to right, returning to the left along a blank row
+    Folded,
+    /// rows run left to right and right to left in turn
+    Boustrophedon,
+}
+
+/// A cell of the program in the order the IP runs it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Atom {
+    /// pushed in string mode; quotes are added per row
+    Char(char),
+    Cell(char),
+}
+
+/// the cells of a literal, with the characters between quotes as `Char`
+fn atoms(code: &str) -> Vec<Atom> {
+    let mut quoted = false;
+    let mut atoms = Vec::new();
+    for c in code.chars() {
+        match (c, quoted) {
+            ('"', _) => quoted = !quoted,
+            (c, true) => atoms.push(Atom::Char(c)),
+            (c, false) => atoms.push(Atom::Cell(c)),
+        }
+    }
+    atoms
+}
+
+/// A row being filled, in the order the IP runs it.
+struct Row {
+    cells: Vec<char>,
+    capacity: usize,
+    quoted: bool,
+}
+
+impl Row {
+    fn new(capacity: usize) -> Self {
+        Self {
+            cells: Vec::new(),
+            capacity,
+            quoted: false,
+        }
+    }
+
+    /// adds `atom` if it fits, keeping room to close string mode
+    fn push(&mut self, atom: Atom) -> bool {
+        let (needed, c) = match atom {
+            Atom::Char(c) => (if self.quoted { 1 } else { 2 } + 1, c),
+            Atom::Cell(c) => (if self.quoted { 1 } else { 0 } + 1, c),
+        };
+        if self.cells.len() + needed > self.capacity {
+            return false;
+        }
+        let quoted = matches!(atom, Atom::Char(_));
+        if quoted != self.quoted {
+            self.cells.push('"');
+            self.quoted = quoted;
+        }
+        self.cells.push(c);
+        true
+    }
+
+    fn close(mut self) -> Vec<char> {
+        if self.quoted {
+            self.cells.push('"');
+        }
+        self.cells
+    }
+}
+
+/// A runnable program printing exactly `text`, as lines of a grid.
+///
+/// The text is pushed reversed with a terminator, then printed by `>:#,_@`. `width` is the
+/// most columns a `Folded` or `Boustrophedon` program may use; string mode is closed at the
+/// end of each row, so the turns are run as instructions.
+pub fn generate(text: &str, shape: Shape, width: usize) -> Result<String, InterpreterError> {
+    let code = literal::string(text, StringOrder::Printed, true);
+    let atoms = atoms(&code);
+    let rows = match shape {
+        Shape::Line => {
+            let mut row = Row::new(usize::MAX);
+            for atom in atoms {
+                row.push(atom);
+            }
+            let mut cells = row.close();
+            cells.extend(PRINT_LOOP.chars());
+            vec![cells]
+        }
+        Shape::Folded | Shape::Boustrophedon => {
+            // a turn cell at each end and the print loop
+            if width < PRINT_LOOP.len() + 2 {
+                return Err(InterpreterError::ParseError(format!(
+                    "a width of {} is too narrow, the print loop needs {}",
+                    width,
+                    PRINT_LOOP.len() + 2
+                )));
+            }
+            fold(&atoms, shape, width)
+        }
+    };
+    let height = rows.len();
+    let longest = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if longest > Interpreter::WIDTH || height > Interpreter::HEIGHT {
+        return Err(InterpreterError::ParseError(format!(
+            "{}x{} cells do not fit in Funge-Space",
+            longest, height
+        )));
+    }
+    let mut out = String::new();
+    for row in rows {
+        let line: String = row.into_iter().collect();
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Rows of exactly `width` cells with turns in the first and last column.
+///
+/// Code rows after the first start with `>` (or `<` on the right, for the right-to-left rows
+/// of a boustrophedon) and end with `v`. A folded program returns to the left along a blank
+/// row between code rows. The print loop only runs left to right, so it gets a row of its own
+/// when the last code row runs the other way or is full.
+fn fold(atoms: &[Atom], shape: Shape, width: usize) -> Vec<Vec<char>> {
+    // the rows in the order the IP runs them
+    let mut code_rows: Vec<Vec<char>> = Vec::new();
+    let capacity = width - 2;
+    let mut row = Row::new(capacity);
+    for &atom in atoms {
+        if !row.push(atom) {
+            code_rows.push(std::mem::replace(&mut row, Row::new(capacity)).close());
+            row.push(atom);
+        }
+    }
+    let mut last = row.close();
+    let rightward = |index: usize| shape == Shape::Folded || index.is_multiple_of(2);
+    if !rightward(code_rows.len()) || last.len() + PRINT_LOOP.len() > capacity {
+        code_rows.push(last);
+        last = Vec::new();
+        if !rightward(code_rows.len()) {
+            code_rows.push(Vec::new());
+        }
+    }
+    last.extend(PRINT_LOOP.chars());
+    code_rows.push(last);
+
+    let count = code_rows.len();
+    let mut grid = Vec::new();
+    for (index, cells) in code_rows.into_iter().enumerate() {
+        let mut line = vec![' '; width];
+        let end = index + 1 == count;
+        if rightward(index) {
+            // the first row starts where the IP does, at the left edge
+            let start = if index == 0 { 0 } else { 1 };
+            if index > 0 {
+                line[0] = '>';
+            }
+            line[start..start + cells.len()].copy_from_slice(&cells);
+            if !end {
+                line[width - 1] = 'v';
+            }
+        } else {
+            line[width - 1] = '<';
+            for (i, &c) in cells.iter().enumerate() {
+                line[width - 2 - i] = c;
+            }
+            line[0] = 'v';
+        }
+        grid.push(line);
+        if shape == Shape::Folded && !end {
+            let mut back = vec![' '; width];
+            back[0] = 'v';
+            back[width - 1] = '<';
+            grid.push(back);
+        }
+    }
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::testing;
+
+    const TEXT: &str = "Hello, \"World\"!\nこんにちは 世界 🎉 ← ok\n";
+
+    fn run(program: &str) -> String {
+        testing::run(program, "")
+    }
+
+    #[test]
+    fn test_line() {
+        let program = generate("Hi\n", Shape::Line, 0).unwrap();
+        assert_eq!(program, "0 19+\"iH\">:#,_@\n".replace(' ', ""));
+        assert_eq!(run(&generate(TEXT, Shape::Line, 0).unwrap()), TEXT);
+    }
+
+    #[test]
+    fn test_folded_and_boustrophedon() {
+        for shape in [Shape::Folded, Shape::Boustrophedon] {
+            for width in [8, 9, 13, 40] {
+                let program = generate(TEXT, shape, width).unwrap();
+                assert!(
+                    program.lines().all(|l| l.chars().count() <= width),
+                    "{:?} {}\n{}",
+                    shape,
+                    width,
+                    program
+                );
+                assert_eq!(run(&program), TEXT, "{:?} {}\n{}", shape, width, program);
+            }
+            assert_eq!(run(&generate("", shape, 8).unwrap()), "");
+        }
+    }
+
+    #[test]
+    fn test_boustrophedon_rows() {
+        assert_eq!(
+            generate("abcdefgh", Shape::Boustrophedon, 10).unwrap(),
+            "0\"hgfed\" v\n\
+             v   \"abc\"<\n\
+             >>:#,_@\n"
+        );
+    }
+
+    #[test]
+    fn test_too_narrow() {
+        let error = generate("x", Shape::Folded, 7).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Parse Error: a width of 7 is too narrow, the print loop needs 8"
+        );
+    }
+}
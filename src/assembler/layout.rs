@@ -0,0 +1,149 @@
+// src/assembler/layout.rs
+
+use crate::assembler::Instruction;
+use crate::engine::interpreter::Interpreter;
+use crate::errors::InterpreterError;
+use std::collections::HashMap;
+
+/// A jump routed below the main line: down at `from`, along a lane row, up at `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Lane {
+    from: usize,
+    to: usize,
+    row: usize,
+}
+
+impl Lane {
+    fn span(&self) -> (usize, usize) {
+        (self.from.min(self.to), self.from.max(self.to))
+    }
+}
+
+/// The program as rows of a grid.
+///
+/// Row 0 is the main line, run left to right and ended with `@`. A label is a `>` the IP
+/// passes or comes up into; a jump is a `v` (`#v_` when it pops, so the IP skips the `v`
+/// unless `_` sends it back left) whose path goes down to its own lane and along it to the
+/// label's column. Lanes that do not overlap share a row. Paths only cross on spaces, since
+/// each `v` and `>` is a column of its own and lanes to the same label all turn up with `^`.
+pub fn layout(instructions: &[Instruction]) -> Result<Vec<String>, InterpreterError> {
+    let mut main: Vec<char> = Vec::new();
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    // source column and label of each jump
+    let mut jumps: Vec<(usize, &str)> = Vec::new();
+    for instruction in instructions {
+        match instruction {
+            Instruction::Code(code) => main.extend(code.chars()),
+            Instruction::Label(label) => {
+                if labels.insert(label, main.len()).is_some() {
+                    return Err(parse_error(format!("label {} defined twice", label)));
+                }
+                main.push('>');
+            }
+            Instruction::Jump(label) => {
+                jumps.push((main.len(), label));
+                main.push('v');
+            }
+            Instruction::JumpIfZero(label) => {
+                jumps.push((main.len() + 2, label));
+                main.extend("!#v_".chars());
+            }
+            Instruction::JumpIfNonZero(label) => {
+                jumps.push((main.len() + 1, label));
+                main.extend("#v_".chars());
+            }
+        }
+    }
+    main.push('@');
+    if main.len() > Interpreter::WIDTH {
+        return Err(parse_error(format!(
+            "the main line needs {} cells, more than the {} columns of Funge-Space",
+            main.len(),
+            Interpreter::WIDTH
+        )));
+    }
+
+    let mut lanes = Vec::new();
+    for (from, label) in jumps {
+        let to = *labels
+            .get(label)
+            .ok_or_else(|| parse_error(format!("unknown label {}", label)))?;
+        lanes.push(Lane { from, to, row: 0 });
+    }
+    assign_rows(&mut lanes);
+
+    let height = 1 + lanes.iter().map(|lane| lane.row).max().unwrap_or(0);
+    let mut grid = vec![vec![' '; main.len()]; height];
+    grid[0] = main;
+    for lane in &lanes {
+        grid[lane.row][lane.from] = if lane.to < lane.from { '<' } else { '>' };
+        grid[lane.row][lane.to] = '^';
+    }
+    Ok(grid
+        .into_iter()
+        .map(|row| row.into_iter().collect())
+        .collect())
+}
+
+/// first fit by leftmost column: a lane takes the top row whose lanes all end before it
+fn assign_rows(lanes: &mut [Lane]) {
+    let mut order: Vec<usize> = (0..lanes.len()).collect();
+    order.sort_by_key(|&i| lanes[i].span());
+    // rightmost column used in each lane row
+    let mut ends: Vec<usize> = Vec::new();
+    for i in order {
+        let (left, right) = lanes[i].span();
+        let row = match ends.iter().position(|&end| end < left) {
+            Some(row) => row,
+            None => {
+                ends.push(0);
+                ends.len() - 1
+            }
+        };
+        ends[row] = right;
+        lanes[i].row = row + 1;
+    }
+}
+
+fn parse_error(message: String) -> InterpreterError {
+    InterpreterError::ParseError(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(name: &str) -> Instruction {
+        Instruction::Label(name.to_string())
+    }
+
+    fn code(code: &str) -> Instruction {
+        Instruction::Code(code.to_string())
+    }
+
+    #[test]
+    fn test_layout_lanes() {
+        let rows = layout(&[
+            label("a"),
+            code("1."),
+            Instruction::JumpIfNonZero("a".to_string()),
+            label("b"),
+            Instruction::Jump("c".to_string()),
+            label("c"),
+            Instruction::JumpIfZero("b".to_string()),
+        ])
+        .unwrap();
+        assert_eq!(
+            rows,
+            vec![">1.#v_>v>!#v_@", "^   < ^    <  ", "       >^     ",]
+        );
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let error = layout(&[Instruction::Jump("nowhere".to_string())]).unwrap_err();
+        assert_eq!(error.to_string(), "Parse Error: unknown label nowhere");
+        let error = layout(&[label("a"), label("a")]).unwrap_err();
+        assert_eq!(error.to_string(), "Parse Error: label a defined twice");
+    }
+}
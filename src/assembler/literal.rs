@@ -0,0 +1,354 @@
+// src/assembler/literal.rs
+
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Values up to this get the shortest expression of digits, `+ - * / %`, `:*` (squaring) and
+/// one-character strings. Larger values are built from them and may not be the shortest.
+pub const EXACT_LIMIT: usize = 4096;
+
+/// How the shortest expression for a value ends.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Digit,
+    /// `"c"`, the value is the character
+    Char,
+    /// `a b op`
+    Binary(char, usize, usize),
+    /// `a:*`
+    Square(usize),
+}
+
+struct Table {
+    steps: Vec<Step>,
+    lengths: Vec<usize>,
+}
+
+impl Table {
+    /// breadth-first by length: every value of length `n` comes from shorter ones
+    fn build() -> Self {
+        let mut steps: Vec<Option<Step>> = vec![None; EXACT_LIMIT + 1];
+        let mut lengths = vec![0; EXACT_LIMIT + 1];
+        let mut by_length: Vec<Vec<usize>> = vec![Vec::new()];
+        let mut found = 0;
+        let mut length = 0;
+        while found <= EXACT_LIMIT {
+            length += 1;
+            let mut layer = Vec::new();
+            let mut add = |value: usize, step: Step, layer: &mut Vec<usize>| {
+                if value <= EXACT_LIMIT && steps[value].is_none() {
+                    steps[value] = Some(step);
+                    lengths[value] = length;
+                    layer.push(value);
+                }
+            };
+            if length == 1 {
+                for digit in 0..10 {
+                    add(digit, Step::Digit, &mut layer);
+                }
+            }
+            if length == 3 {
+                for c in (' '..='~').filter(|&c| c != '"') {
+                    add(c as usize, Step::Char, &mut layer);
+                }
+            }
+            if length > 2 {
+                for &a in &by_length[length - 2] {
+                    add(a * a, Step::Square(a), &mut layer);
+                }
+            }
+            for left in 1..length.saturating_sub(1) {
+                let right = length - 1 - left;
+                for &a in &by_length[left] {
+                    for &b in &by_length[right] {
+                        add(a + b, Step::Binary('+', a, b), &mut layer);
+                        add(a * b, Step::Binary('*', a, b), &mut layer);
+                        if a >= b {
+                            add(a - b, Step::Binary('-', a, b), &mut layer);
+                        }
+                        if let (Some(quotient), Some(remainder)) =
+                            (a.checked_div(b), a.checked_rem(b))
+                        {
+                            add(quotient, Step::Binary('/', a, b), &mut layer);
+                            add(remainder, Step::Binary('%', a, b), &mut layer);
+                        }
+                    }
+                }
+            }
+            found += layer.len();
+            by_length.push(layer);
+        }
+        Self {
+            steps: steps.into_iter().map(Option::unwrap).collect(),
+            lengths,
+        }
+    }
+
+    fn write(&self, value: usize, out: &mut String) {
+        match self.steps[value] {
+            Step::Digit => out.push_str(&value.to_string()),
+            Step::Char => {
+                out.push('"');
+                out.push(char::from(value as u8));
+                out.push('"');
+            }
+            Step::Binary(op, a, b) => {
+                self.write(a, out);
+                self.write(b, out);
+                out.push(op);
+            }
+            Step::Square(a) => {
+                self.write(a, out);
+                out.push_str(":*");
+            }
+        }
+    }
+}
+
+fn table() -> &'static Table {
+    static TABLE: OnceLock<Table> = OnceLock::new();
+    TABLE.get_or_init(Table::build)
+}
+
+/// the shortest cells pushing `value` that we know of; negative values wrap like `-`
+pub fn number(value: i64) -> String {
+    if value < 0 {
+        return format!("0{}-", unsigned(value.unsigned_abs()));
+    }
+    unsigned(value as u64)
+}
+
+fn unsigned(value: u64) -> String {
+    let mut large = Large::default();
+    large.length(value);
+    let mut out = String::new();
+    large.write(value, &mut out);
+    out
+}
+
+/// How a value above `EXACT_LIMIT` is built from a smaller one.
+#[derive(Debug, Clone, Copy)]
+enum Split {
+    /// `q k * r +` with q = value / k
+    Down(u64),
+    /// `q k * d -` with q = value / k + 1
+    Up(u64),
+    /// `root :* rest +`
+    Square,
+}
+
+/// Values above `EXACT_LIMIT`, split into smaller ones until the table has them.
+#[derive(Default)]
+struct Large {
+    known: HashMap<u64, (usize, Split)>,
+}
+
+impl Large {
+    /// length of the expression for `value`, remembering how it splits
+    fn length(&mut self, value: u64) -> usize {
+        if value <= EXACT_LIMIT as u64 {
+            return table().lengths[value as usize];
+        }
+        if let Some(&(length, _)) = self.known.get(&value) {
+            return length;
+        }
+        let root = value.isqrt();
+        let rest = value - root * root;
+        let mut best = (self.length(root) + 2, Split::Square);
+        if rest > 0 {
+            best.0 += self.length(rest) + 1;
+        }
+        for k in 2..=9 {
+            let (q, r) = (value / k, value % k);
+            let down = self.length(q) + 2 + if r > 0 { 2 } else { 0 };
+            if down < best.0 {
+                best = (down, Split::Down(k));
+            }
+            // the interpreter multiplies signed values, so nothing may pass i64::MAX
+            if 2 * r > k && (q + 1) * k <= i64::MAX as u64 {
+                let up = self.length(q + 1) + 4;
+                if up < best.0 {
+                    best = (up, Split::Up(k));
+                }
+            }
+        }
+        self.known.insert(value, best);
+        best.0
+    }
+
+    fn write(&self, value: u64, out: &mut String) {
+        if value <= EXACT_LIMIT as u64 {
+            return table().write(value as usize, out);
+        }
+        match self.known[&value].1 {
+            Split::Down(k) => {
+                self.write(value / k, out);
+                out.push_str(&format!("{}*", k));
+                if !value.is_multiple_of(k) {
+                    out.push_str(&format!("{}+", value % k));
+                }
+            }
+            Split::Up(k) => {
+                let q = value / k + 1;
+                self.write(q, out);
+                out.push_str(&format!("{}*{}-", k, q * k - value));
+            }
+            Split::Square => {
+                let root = value.isqrt();
+                self.write(root, out);
+                out.push_str(":*");
+                if value > root * root {
+                    self.write(value - root * root, out);
+                    out.push('+');
+                }
+            }
+        }
+    }
+}
+
+/// The order in which a string literal leaves its characters on the stack.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum StringOrder {
+    /// the first character on top, so that `,` in a loop prints the text
+    #[default]
+    Printed,
+    /// as string mode reads the text: the last character on top
+    Pushed,
+}
+
+/// cells pushing `text`, in string mode where they can and with a 0 below it if `terminator`
+pub fn string(text: &str, order: StringOrder, terminator: bool) -> String {
+    let mut out = String::new();
+    if terminator {
+        out.push('0');
+    }
+    let chars: Vec<char> = match order {
+        StringOrder::Printed => text.chars().rev().collect(),
+        StringOrder::Pushed => text.chars().collect(),
+    };
+    let mut quoted = false;
+    for c in chars {
+        // `"` ends string mode, a row cannot hold control characters and arrows start IPs
+        let literal = c != '"' && !c.is_control() && !matches!(c, '→' | '←' | '↑' | '↓');
+        if literal != quoted {
+            out.push('"');
+            quoted = literal;
+        }
+        match literal {
+            true => out.push(c),
+            false => out.push_str(&unsigned(c as u64)),
+        }
+    }
+    if quoted {
+        out.push('"');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::testing;
+
+    /// output of a one-row program
+    fn run(row: &str) -> String {
+        testing::run(row, "")
+    }
+
+    /// runs `number(v).` for each value, a row at a time
+    fn assert_numbers(values: impl Iterator<Item = i64>) {
+        let mut row = String::new();
+        let mut expected = String::new();
+        for value in values {
+            row.push_str(&number(value));
+            row.push('.');
+            expected.push_str(&format!("{} ", value as usize));
+            if row.len() > 900 {
+                row.push('@');
+                assert_eq!(run(&row), expected);
+                row.clear();
+                expected.clear();
+            }
+        }
+        row.push('@');
+        assert_eq!(run(&row), expected);
+    }
+
+    #[test]
+    fn test_shortest_numbers() {
+        assert_eq!(number(7), "7");
+        assert_eq!(number(81).len(), 3);
+        assert_eq!(number(100), "\"d\"");
+        assert_eq!(number(1234).len(), 7);
+        assert_eq!(number(-1), "01-");
+        for value in [10, 64, 200, 999, 4096] {
+            assert!(number(value).len() <= 7, "{}", value);
+        }
+    }
+
+    /// every expression of up to 5 digits and operators, evaluated
+    fn enumerate(prefix: &mut Vec<char>, stack: Vec<usize>, best: &mut HashMap<usize, usize>) {
+        if let [value] = stack[..] {
+            let length = best.entry(value).or_insert(prefix.len());
+            *length = (*length).min(prefix.len());
+        }
+        if prefix.len() == 5 {
+            return;
+        }
+        for c in "0123456789+-*/%".chars() {
+            let mut next = stack.clone();
+            if let Some(digit) = c.to_digit(10) {
+                next.push(digit as usize);
+            } else {
+                let (Some(a), Some(b)) = (next.pop(), next.pop()) else {
+                    continue;
+                };
+                let value = match c {
+                    '+' => b + a,
+                    '-' if b >= a => b - a,
+                    '*' => b * a,
+                    '/' if a > 0 => b / a,
+                    '%' if a > 0 => b % a,
+                    _ => continue,
+                };
+                next.push(value);
+            }
+            prefix.push(c);
+            enumerate(prefix, next, best);
+            prefix.pop();
+        }
+    }
+
+    #[test]
+    fn test_numbers_are_shortest() {
+        let mut best = HashMap::new();
+        enumerate(&mut Vec::new(), Vec::new(), &mut best);
+        for (value, length) in best {
+            assert!(number(value as i64).len() <= length, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_numbers_run() {
+        assert_numbers((0..=EXACT_LIMIT as i64).step_by(7));
+        assert_numbers(
+            [4097, 65535, 1 << 20, 123_456_789, i64::MAX, -5, -4097]
+                .into_iter()
+                .chain((0..40).map(|i| 1_000_003_i64.pow(2) / (i + 1))),
+        );
+    }
+
+    #[test]
+    fn test_strings_run() {
+        let text = "Hi, \"you\"  \t→!";
+        let printed = string(text, StringOrder::Printed, true);
+        // print until the terminator
+        assert_eq!(run(&format!("{}>:#,_@", printed)), text);
+        let pushed = string(text, StringOrder::Pushed, false);
+        let prints: String = text.chars().map(|_| ',').collect();
+        let reversed: String = text.chars().rev().collect();
+        assert_eq!(run(&format!("{}{}@", pushed, prints)), reversed);
+        assert_eq!(string("ab", StringOrder::Printed, true), "0\"ba\"");
+    }
+}
@@ -0,0 +1,88 @@
+// src/assembler/mod.rs
+
+pub mod generate;
+pub mod layout;
+pub mod literal;
+pub mod parser;
+
+use crate::errors::InterpreterError;
+
+/// A step of a linear program, as the layout places it on the main line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    /// cells run left to right: operators, numbers and strings
+    Code(String),
+    Label(String),
+    Jump(String),
+    /// pops and jumps when the value is zero
+    JumpIfZero(String),
+    /// pops and jumps when the value is not zero
+    JumpIfNonZero(String),
+}
+
+/// a Befunge program running the assembly `source`, one line per row
+pub fn assemble(source: &str) -> Result<String, InterpreterError> {
+    let instructions = parser::parse(source)?;
+    let rows = layout::layout(&instructions)?;
+    let mut out = String::new();
+    for row in rows {
+        out.push_str(row.trim_end());
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::testing;
+
+    /// assembles `source` and runs the grid with `input`
+    fn run(source: &str, input: &str) -> String {
+        testing::run(&assemble(source).unwrap(), input)
+    }
+
+    #[test]
+    fn test_loop_round_trip() {
+        let source = "
+            ; count down from 5
+                push 5
+            loop:
+                dup
+                print
+                push 1
+                sub
+                dup
+                jnz loop
+                drop
+                push '\\n'
+                printc
+        ";
+        assert_eq!(run(source, ""), "5 4 3 2 1 \n");
+    }
+
+    #[test]
+    fn test_macros_and_branches_round_trip() {
+        let source = r#"
+            macro puts text
+                stringz $text
+            next:
+                dup
+                jz done
+                printc
+                jmp next
+            done:
+                drop
+            endmacro
+
+                puts "Say \"hi\"\n"
+                readn
+                jz zero
+                puts "nonzero\n"
+                halt
+            zero: puts "zero\n"
+        "#;
+        assert_eq!(run(source, "0\n"), "Say \"hi\"\nzero\n");
+        assert_eq!(run(source, "7\n"), "Say \"hi\"\nnonzero\n");
+    }
+}
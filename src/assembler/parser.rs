@@ -0,0 +1,398 @@
+// src/assembler/parser.rs
+
+use crate::assembler::literal::{number, string, StringOrder};
+use crate::assembler::Instruction;
+use crate::errors::InterpreterError;
+use std::collections::HashMap;
+
+/// mnemonics of single instructions
+const MNEMONICS: &[(&str, char)] = &[
+    ("add", '+'),
+    ("sub", '-'),
+    ("mul", '*'),
+    ("div", '/'),
+    ("mod", '%'),
+    ("not", '!'),
+    ("gt", '`'),
+    ("dup", ':'),
+    ("swap", '\\'),
+    ("drop", '$'),
+    ("print", '.'),
+    ("printc", ','),
+    ("readn", '&'),
+    ("readc", '~'),
+    ("get", 'g'),
+    ("put", 'p'),
+    ("halt", '@'),
+];
+
+/// macros calling macros deeper than this are taken as recursion
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    /// a "quoted" string, escapes resolved
+    Text(String),
+}
+
+/// One line: an optional label and an optional operation with its arguments.
+#[derive(Debug, Clone)]
+struct Statement {
+    line: usize,
+    label: Option<String>,
+    operation: Option<(String, Vec<Token>)>,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Statement>,
+}
+
+fn error(line: usize, message: impl Into<String>) -> InterpreterError {
+    InterpreterError::ParseError(format!("line {}: {}", line, message.into()))
+}
+
+/// the instructions of an assembly program, macros expanded
+pub fn parse(source: &str) -> Result<Vec<Instruction>, InterpreterError> {
+    let mut statements = Vec::new();
+    let mut macros: HashMap<String, Macro> = HashMap::new();
+    // the macro being defined: name, line and the macro so far
+    let mut defining: Option<(String, usize, Macro)> = None;
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let tokens = tokenize(text, line)?;
+        let Some(statement) = statement(tokens, line)? else {
+            continue;
+        };
+        let keyword = statement.operation.as_ref().map(|(op, _)| op.as_str());
+        match (keyword, &mut defining) {
+            (Some("macro"), Some(_)) => return Err(error(line, "nested macro definition")),
+            (Some("macro"), None) => {
+                let (_, args) = statement.operation.unwrap();
+                let mut words = args.into_iter().map(|token| match token {
+                    Token::Word(word) => Ok(word),
+                    Token::Text(_) => Err(error(line, "macro parameters are names")),
+                });
+                let name = words
+                    .next()
+                    .ok_or_else(|| error(line, "macro without a name"))??;
+                let params = words.collect::<Result<_, _>>()?;
+                let body = Vec::new();
+                defining = Some((name, line, Macro { params, body }));
+            }
+            (Some("endmacro"), None) => return Err(error(line, "endmacro without macro")),
+            (Some("endmacro"), Some(_)) => {
+                let (name, _, definition) = defining.take().unwrap();
+                macros.insert(name, definition);
+            }
+            (_, Some((_, _, definition))) => definition.body.push(statement),
+            (_, None) => statements.push(statement),
+        }
+    }
+    if let Some((name, line, _)) = defining {
+        return Err(error(line, format!("macro {} has no endmacro", name)));
+    }
+
+    let mut expander = Expander {
+        macros: &macros,
+        expansions: 0,
+        out: Vec::new(),
+    };
+    for statement in statements {
+        expander.statement(statement, 0)?;
+    }
+    Ok(expander.out)
+}
+
+fn tokenize(text: &str, line: usize) -> Result<Vec<Token>, InterpreterError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            ';' => break,
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => text.push(escape(chars.next(), line)?),
+                        Some(c) => text.push(c),
+                        None => return Err(error(line, "unterminated string")),
+                    }
+                }
+                tokens.push(Token::Text(text));
+            }
+            '\'' => {
+                // a character literal is its code point
+                chars.next();
+                let value = match chars.next() {
+                    Some('\\') => escape(chars.next(), line)?,
+                    Some(c) => c,
+                    None => return Err(error(line, "unterminated character")),
+                };
+                if chars.next() != Some('\'') {
+                    return Err(error(line, "unterminated character"));
+                }
+                tokens.push(Token::Word((value as u32).to_string()));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn escape(c: Option<char>, line: usize) -> Result<char, InterpreterError> {
+    match c {
+        Some('n') => Ok('\n'),
+        Some('t') => Ok('\t'),
+        Some('0') => Ok('\0'),
+        Some(c @ ('\\' | '"' | '\'')) => Ok(c),
+        Some(c) => Err(error(line, format!("unknown escape \\{}", c))),
+        None => Err(error(line, "escape at the end of the line")),
+    }
+}
+
+fn statement(mut tokens: Vec<Token>, line: usize) -> Result<Option<Statement>, InterpreterError> {
+    let label = match tokens.first() {
+        Some(Token::Word(word)) if word.ends_with(':') => {
+            let name = word.trim_end_matches(':').to_string();
+            if !is_name(&name) {
+                return Err(error(line, format!("bad label {:?}", name)));
+            }
+            tokens.remove(0);
+            Some(name)
+        }
+        _ => None,
+    };
+    let operation = match tokens.is_empty() {
+        true => None,
+        false => match tokens.remove(0) {
+            Token::Word(word) => Some((word, tokens)),
+            Token::Text(_) => return Err(error(line, "a string is not an instruction")),
+        },
+    };
+    Ok(
+        (label.is_some() || operation.is_some()).then_some(Statement {
+            line,
+            label,
+            operation,
+        }),
+    )
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+struct Expander<'a> {
+    macros: &'a HashMap<String, Macro>,
+    /// numbers the labels of each expansion apart
+    expansions: usize,
+    out: Vec<Instruction>,
+}
+
+impl Expander<'_> {
+    fn statement(&mut self, statement: Statement, depth: usize) -> Result<(), InterpreterError> {
+        let line = statement.line;
+        if let Some(label) = statement.label {
+            self.out.push(Instruction::Label(label));
+        }
+        let Some((operation, args)) = statement.operation else {
+            return Ok(());
+        };
+        if let Some(definition) = self.macros.get(&operation) {
+            return self.expand(&operation, definition, args, line, depth);
+        }
+        if let Some(&(_, c)) = MNEMONICS.iter().find(|(name, _)| *name == operation) {
+            no_arguments(&operation, &args, line)?;
+            self.out.push(Instruction::Code(c.to_string()));
+            return Ok(());
+        }
+        match operation.as_str() {
+            "push" => {
+                if args.is_empty() {
+                    return Err(error(line, "push needs a number"));
+                }
+                for arg in args {
+                    let value = match arg {
+                        Token::Word(word) => word.parse::<i64>().ok(),
+                        Token::Text(_) => None,
+                    };
+                    let value = value.ok_or_else(|| error(line, "push takes numbers"))?;
+                    self.out.push(Instruction::Code(number(value)));
+                }
+            }
+            "string" | "stringz" => {
+                let [Token::Text(text)] = args.as_slice() else {
+                    return Err(error(line, format!("{} takes one string", operation)));
+                };
+                let terminator = operation == "stringz";
+                let code = string(text, StringOrder::Printed, terminator);
+                self.out.push(Instruction::Code(code));
+            }
+            "jmp" | "jz" | "jnz" => {
+                let [Token::Word(label)] = args.as_slice() else {
+                    return Err(error(line, format!("{} takes one label", operation)));
+                };
+                let label = label.clone();
+                self.out.push(match operation.as_str() {
+                    "jmp" => Instruction::Jump(label),
+                    "jz" => Instruction::JumpIfZero(label),
+                    _ => Instruction::JumpIfNonZero(label),
+                });
+            }
+            _ => return Err(error(line, format!("unknown instruction {}", operation))),
+        }
+        Ok(())
+    }
+
+    fn expand(
+        &mut self,
+        name: &str,
+        definition: &Macro,
+        args: Vec<Token>,
+        line: usize,
+        depth: usize,
+    ) -> Result<(), InterpreterError> {
+        if depth >= MAX_DEPTH {
+            return Err(error(line, format!("macro {} expands too deep", name)));
+        }
+        if args.len() != definition.params.len() {
+            return Err(error(
+                line,
+                format!(
+                    "macro {} takes {} arguments, got {}",
+                    name,
+                    definition.params.len(),
+                    args.len()
+                ),
+            ));
+        }
+        self.expansions += 1;
+        // labels defined in the body are local to this expansion
+        let suffix = format!("@{}", self.expansions);
+        let local: Vec<&String> = definition
+            .body
+            .iter()
+            .filter_map(|s| s.label.as_ref())
+            .collect();
+        let rename = |word: &String| match local.contains(&word) {
+            true => format!("{}{}", word, suffix),
+            false => word.clone(),
+        };
+        for statement in &definition.body {
+            let operation = statement.operation.as_ref().map(|(operation, body_args)| {
+                let body_args = body_args
+                    .iter()
+                    .map(|token| match token {
+                        Token::Word(word) => match word
+                            .strip_prefix('$')
+                            .and_then(|p| definition.params.iter().position(|q| q == p))
+                        {
+                            Some(i) => args[i].clone(),
+                            None => Token::Word(rename(word)),
+                        },
+                        text => text.clone(),
+                    })
+                    .collect();
+                (operation.clone(), body_args)
+            });
+            let expanded = Statement {
+                line,
+                label: statement.label.as_ref().map(&rename),
+                operation,
+            };
+            self.statement(expanded, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+fn no_arguments(operation: &str, args: &[Token], line: usize) -> Result<(), InterpreterError> {
+    match args.is_empty() {
+        true => Ok(()),
+        false => Err(error(line, format!("{} takes no arguments", operation))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(source: &str) -> Vec<Instruction> {
+        parse(source).unwrap()
+    }
+
+    #[test]
+    fn test_parse_instructions() {
+        assert_eq!(
+            code("start: push 1 -2 'A' ; comment\n  add\nstring \"a\\\"b\\n\"\njnz start"),
+            vec![
+                Instruction::Label("start".to_string()),
+                Instruction::Code("1".to_string()),
+                Instruction::Code("02-".to_string()),
+                Instruction::Code("\"A\"".to_string()),
+                Instruction::Code("+".to_string()),
+                Instruction::Code("19+\"b\"1\"!\"+\"a\"".to_string()),
+                Instruction::JumpIfNonZero("start".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_macro_labels_are_local() {
+        let source = "macro twice x\nagain: push $x\njmp again\nendmacro\ntwice 1\ntwice 2";
+        assert_eq!(
+            code(source),
+            vec![
+                Instruction::Label("again@1".to_string()),
+                Instruction::Code("1".to_string()),
+                Instruction::Jump("again@1".to_string()),
+                Instruction::Label("again@2".to_string()),
+                Instruction::Code("2".to_string()),
+                Instruction::Jump("again@2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let message = |source: &str| parse(source).unwrap_err().to_string();
+        assert_eq!(
+            message("push 1\nfoo"),
+            "Parse Error: line 2: unknown instruction foo"
+        );
+        assert_eq!(
+            message("add 1"),
+            "Parse Error: line 1: add takes no arguments"
+        );
+        assert_eq!(
+            message("macro m\nm\nendmacro\nm"),
+            "Parse Error: line 4: macro m expands too deep"
+        );
+        assert_eq!(
+            message("macro m"),
+            "Parse Error: line 1: macro m has no endmacro"
+        );
+    }
+}
@@ -0,0 +1,563 @@
+// src/compiler/c.rs
+
+use crate::analysis::cfg::{ControlFlowGraph, State};
+use crate::compiler::{
+    direction_index, runtime_grid, segment_cells, segments, state_key, straight_line, Segment,
+    Syntax,
+};
+
+/// Stack, IPs, IO and the generic one-cell-at-a-time loop. Expects `WIDTH`, `HEIGHT`,
+/// `grid` and the generated `segment_at`, `run_segment` and `invalidate` around it.
+const RUNTIME: &str = r#"
+typedef struct {
+    size_t *data;
+    size_t len;
+    size_t cap;
+} Stack;
+
+typedef struct {
+    long x;
+    long y;
+    int dir; /* 0 right, 1 left, 2 up, 3 down */
+    int string_mode;
+    int alive;
+    Stack stack;
+} IP;
+
+static const int DX[4] = {1, -1, 0, 0};
+static const int DY[4] = {0, 0, -1, 1};
+
+static IP *ips;
+static size_t ip_count;
+static size_t ip_cap;
+static int division_warned;
+
+static void out_of_memory(void) {
+    fprintf(stderr, "out of memory\n");
+    exit(2);
+}
+
+static void push(IP *ip, size_t value) {
+    Stack *s = &ip->stack;
+    if (s->len == s->cap) {
+        s->cap = s->cap ? s->cap * 2 : 16;
+        s->data = realloc(s->data, s->cap * sizeof(size_t));
+        if (!s->data) out_of_memory();
+    }
+    s->data[s->len++] = value;
+}
+
+/* an empty stack pops 0 */
+static size_t pop(IP *ip) {
+    return ip->stack.len ? ip->stack.data[--ip->stack.len] : 0;
+}
+
+/* the grid wraps at 1024 like the interpreter's */
+static void move_ip(IP *ip) {
+    ip->x = (ip->x + DX[ip->dir] + 1024) % 1024;
+    ip->y = (ip->y + DY[ip->dir] + 1024) % 1024;
+}
+
+static void fail(IP *ip, const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s (ip at (%ld, %ld))\n", message, ip->x, ip->y);
+    ip->alive = 0;
+}
+
+static uint32_t to_char(size_t value) {
+    uint32_t c = (uint32_t)value;
+    if (c > 0x10FFFF || (c >= 0xD800 && c <= 0xDFFF)) return 0xFFFD;
+    return c;
+}
+
+static void print_char(size_t value) {
+    uint32_t c = to_char(value);
+    if (c < 0x80) {
+        putchar((int)c);
+    } else if (c < 0x800) {
+        putchar((int)(0xC0 | (c >> 6)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    } else if (c < 0x10000) {
+        putchar((int)(0xE0 | (c >> 12)));
+        putchar((int)(0x80 | ((c >> 6) & 0x3F)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    } else {
+        putchar((int)(0xF0 | (c >> 18)));
+        putchar((int)(0x80 | ((c >> 12) & 0x3F)));
+        putchar((int)(0x80 | ((c >> 6) & 0x3F)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    }
+}
+
+static void print_number(size_t value) {
+    printf("%zu ", value);
+}
+
+/* signed, like the interpreter; division by zero pushes 0 and warns once */
+static size_t divide(size_t b, size_t a, int modulo, long x, long y) {
+    int64_t sa = (int64_t)a;
+    int64_t sb = (int64_t)b;
+    if (a == 0) {
+        if (!division_warned) {
+            division_warned = 1;
+            fflush(stdout);
+            fprintf(stderr, "warning: division by zero at (%ld, %ld)\n", x, y);
+        }
+        return 0;
+    }
+    if (sa == -1) return modulo ? 0 : (size_t)0 - b;
+    return (size_t)(modulo ? sb % sa : sb / sa);
+}
+
+/* one line; 0 unless it is a plain decimal number. Returns 0 at end of input. */
+static int read_number(size_t *value) {
+    char line[256];
+    size_t len = 0;
+    int c;
+    int any = 0;
+    while ((c = getchar()) != EOF) {
+        any = 1;
+        if (c == '\n') break;
+        if (len + 1 < sizeof line) line[len++] = (char)c;
+    }
+    if (!any) return 0;
+    line[len] = '\0';
+    {
+        char *start = line;
+        char *end = line + len;
+        size_t result = 0;
+        int digits = 0;
+        while (start < end && (*start == ' ' || *start == '\t' || *start == '\r')) start++;
+        while (end > start && (end[-1] == ' ' || end[-1] == '\t' || end[-1] == '\r')) end--;
+        if (start < end && *start == '+') start++;
+        for (; start < end; start++) {
+            size_t digit;
+            if (*start < '0' || *start > '9') break;
+            digit = (size_t)(*start - '0');
+            if (result > (SIZE_MAX - digit) / 10) break;
+            result = result * 10 + digit;
+            digits++;
+        }
+        *value = (digits > 0 && start == end) ? result : 0;
+    }
+    return 1;
+}
+
+/* one byte. Returns 0 at end of input. */
+static int read_char(size_t *value) {
+    int c = getchar();
+    if (c == EOF) return 0;
+    *value = (size_t)(unsigned char)c;
+    return 1;
+}
+
+static int get_cell(IP *ip, size_t x, size_t y, size_t *value) {
+    char message[96];
+    if (y < HEIGHT && x < WIDTH) {
+        *value = grid[y][x];
+        return 1;
+    }
+    sprintf(message, "attempt to get value out of bounds at (%zu, %zu)", x, y);
+    fail(ip, message);
+    return 0;
+}
+
+static void invalidate(size_t x, size_t y);
+
+static int put_cell(IP *ip, size_t x, size_t y, size_t value) {
+    char message[96];
+    if (y < HEIGHT && x < WIDTH) {
+        uint32_t c = to_char(value);
+        if (grid[y][x] != c) {
+            grid[y][x] = c;
+            invalidate(x, y);
+        }
+        return 1;
+    }
+    sprintf(message, "attempt to set value out of bounds at (%zu, %zu)", x, y);
+    fail(ip, message);
+    return 0;
+}
+
+static void start(long x, long y, int dir) {
+    IP *ip;
+    if (ip_count == ip_cap) {
+        ip_cap = ip_cap ? ip_cap * 2 : 4;
+        ips = realloc(ips, ip_cap * sizeof(IP));
+        if (!ips) out_of_memory();
+    }
+    ip = &ips[ip_count++];
+    memset(ip, 0, sizeof(IP));
+    ip->x = x;
+    ip->y = y;
+    ip->dir = dir;
+    ip->alive = 1;
+}
+
+/* `t`: the new IP copies the stack and moves once; bad deltas keep the parent's direction */
+static void spawn(size_t parent, size_t dx, size_t dy) {
+    IP *child;
+    int64_t sx = (int64_t)dx;
+    int64_t sy = (int64_t)dy;
+    int dir = ips[parent].dir;
+    if (sx == 1 && sy == 0) dir = 0;
+    else if (sx == -1 && sy == 0) dir = 1;
+    else if (sx == 0 && sy == -1) dir = 2;
+    else if (sx == 0 && sy == 1) dir = 3;
+    start(ips[parent].x, ips[parent].y, dir);
+    child = &ips[ip_count - 1];
+    child->string_mode = ips[parent].string_mode;
+    child->stack.len = ips[parent].stack.len;
+    child->stack.cap = ips[parent].stack.len;
+    if (child->stack.cap) {
+        child->stack.data = malloc(child->stack.cap * sizeof(size_t));
+        if (!child->stack.data) out_of_memory();
+        memcpy(child->stack.data, ips[parent].stack.data, child->stack.len * sizeof(size_t));
+    }
+    move_ip(child);
+}
+
+/* the interpreter's step: fetch, execute, move */
+static void step(size_t i) {
+    IP *ip = &ips[i];
+    /* Funge-Space outside the program is empty */
+    uint32_t c = ip->x < WIDTH && ip->y < HEIGHT ? grid[ip->y][ip->x] : ' ';
+    if (ip->string_mode) {
+        if (c == '"') ip->string_mode = 0;
+        else push(ip, c);
+        move_ip(ip);
+        return;
+    }
+    switch (c) {
+    case '0': case '1': case '2': case '3': case '4':
+    case '5': case '6': case '7': case '8': case '9':
+        push(ip, c - '0');
+        break;
+    case '+': { size_t a = pop(ip); size_t b = pop(ip); push(ip, b + a); break; }
+    case '-': { size_t a = pop(ip); size_t b = pop(ip); push(ip, b - a); break; }
+    case '*': { size_t a = pop(ip); size_t b = pop(ip); push(ip, b * a); break; }
+    case '/': { size_t a = pop(ip); size_t b = pop(ip); push(ip, divide(b, a, 0, ip->x, ip->y)); break; }
+    case '%': { size_t a = pop(ip); size_t b = pop(ip); push(ip, divide(b, a, 1, ip->x, ip->y)); break; }
+    case '`': { size_t a = pop(ip); size_t b = pop(ip); push(ip, b > a); break; }
+    case '!': push(ip, pop(ip) == 0); break;
+    case ':': { size_t a = pop(ip); push(ip, a); push(ip, a); break; }
+    case '\\': { size_t a = pop(ip); size_t b = pop(ip); push(ip, a); push(ip, b); break; }
+    case '$': pop(ip); break;
+    case '.': print_number(pop(ip)); break;
+    case ',': print_char(pop(ip)); break;
+    case '>': ip->dir = 0; break;
+    case '<': ip->dir = 1; break;
+    case '^': ip->dir = 2; break;
+    case 'v': ip->dir = 3; break;
+    case '_': ip->dir = pop(ip) == 0 ? 0 : 1; break;
+    case '|': ip->dir = pop(ip) == 0 ? 3 : 2; break;
+    case '"': ip->string_mode = 1; break;
+    case '#': move_ip(ip); break;
+    case '@': ip->alive = 0; return;
+    case '&': {
+        size_t value;
+        if (!read_number(&value)) { fail(ip, "unexpected end of input"); return; }
+        push(ip, value);
+        break;
+    }
+    case '~': {
+        size_t value;
+        if (!read_char(&value)) { fail(ip, "unexpected end of input"); return; }
+        push(ip, value);
+        break;
+    }
+    case 'g': {
+        size_t y = pop(ip);
+        size_t x = pop(ip);
+        size_t value;
+        if (!get_cell(ip, x, y, &value)) return;
+        push(ip, value);
+        break;
+    }
+    case 'p': {
+        size_t y = pop(ip);
+        size_t x = pop(ip);
+        size_t value = pop(ip);
+        if (!put_cell(ip, x, y, value)) return;
+        break;
+    }
+    case 't': {
+        size_t dy = pop(ip);
+        size_t dx = pop(ip);
+        spawn(i, dx, dy);
+        ip = &ips[i];
+        break;
+    }
+    default:
+        break;
+    }
+    move_ip(ip);
+}
+"#;
+
+/// runs the IPs in turn until all have ended
+const MAIN: &str = r#"
+int main(void) {
+    start_ips();
+    while (ip_count > 0) {
+        size_t i;
+        size_t live = 0;
+        for (i = 0; i < ip_count; i++) {
+            int segment;
+            if (!ips[i].alive) continue;
+            segment = segment_at(&ips[i]);
+            if (segment >= 0 && segment_valid[segment]) run_segment(segment, &ips[i]);
+            else step(i);
+        }
+        for (i = 0; i < ip_count; i++) {
+            if (ips[i].alive) ips[live++] = ips[i];
+            else free(ips[i].stack.data);
+        }
+        ip_count = live;
+    }
+    fflush(stdout);
+    return 0;
+}
+"#;
+
+/// text safe inside a C comment
+fn comment(text: &str) -> String {
+    text.replace("*/", "* /").replace("??", "?\u{200B}?")
+}
+
+fn fail_at(state: &State) -> String {
+    format!("ip->x = {}; ip->y = {};", state.x, state.y)
+}
+
+struct CSyntax;
+
+impl Syntax for CSyntax {
+    fn local(&self, name: &str, init: &str) -> String {
+        format!("size_t {} = {};", name, init)
+    }
+
+    fn pop(&self) -> String {
+        "pop(ip)".to_string()
+    }
+
+    fn binary(&self, op: char, b: &str, a: &str, at: &State) -> String {
+        match op {
+            '+' => format!("{} + {}", b, a),
+            '-' => format!("{} - {}", b, a),
+            '*' => format!("{} * {}", b, a),
+            '`' => format!("(size_t)({} > {})", b, a),
+            _ => format!(
+                "divide({}, {}, {}, {}, {})",
+                b,
+                a,
+                (op == '%') as u8,
+                at.x,
+                at.y
+            ),
+        }
+    }
+
+    fn not(&self, a: &str) -> String {
+        format!("(size_t)({} == 0)", a)
+    }
+
+    fn discard(&self, value: &str) -> Option<String> {
+        Some(format!("(void){};", value))
+    }
+
+    fn print(&self, op: char, a: &str) -> String {
+        if op == '.' {
+            format!("print_number({});", a)
+        } else {
+            format!("print_char({});", a)
+        }
+    }
+
+    fn read(&self, op: char, name: &str, at: &State) -> Vec<String> {
+        let read = if op == '&' {
+            "read_number"
+        } else {
+            "read_char"
+        };
+        vec![
+            self.local(name, "0"),
+            format!(
+                "if (!{}(&{})) {{ {} fail(ip, \"unexpected end of input\"); return; }}",
+                read,
+                name,
+                fail_at(at)
+            ),
+        ]
+    }
+
+    fn get(&self, x: &str, y: &str, name: &str, at: &State) -> Vec<String> {
+        vec![
+            self.local(name, "0"),
+            format!(
+                "if (!get_cell(ip, {}, {}, &{})) {{ {} return; }}",
+                x,
+                y,
+                name,
+                fail_at(at)
+            ),
+        ]
+    }
+
+    fn push(&self, value: &str) -> String {
+        format!("push(ip, {});", value)
+    }
+
+    fn jump(&self, last: &State) -> String {
+        format!(
+            "ip->x = {}; ip->y = {}; ip->dir = {}; ip->string_mode = {};",
+            last.x,
+            last.y,
+            direction_index(last.direction),
+            last.string_mode as u8
+        )
+    }
+}
+
+fn segment_function(cfg: &ControlFlowGraph, index: usize, segment: &Segment) -> String {
+    let code: String = segment
+        .body
+        .iter()
+        .map(|s| cfg.cell(s.x, s.y))
+        .filter(|c| *c != ' ')
+        .collect();
+    let entry = segment.entry();
+    let mut out = format!(
+        "/* ({}, {}) {}: {} */\nstatic void segment_{}(IP *ip) {{\n",
+        entry.x,
+        entry.y,
+        entry.direction,
+        comment(&format!("{:?}", code)),
+        index
+    );
+    for line in straight_line(&CSyntax, cfg, segment).lines {
+        out.push_str(&format!("    {}\n", line));
+    }
+    out.push_str("}\n\n");
+    out
+}
+
+pub fn compile(grid: &[Vec<char>], name: &str) -> String {
+    let cfg = ControlFlowGraph::build(grid);
+    let runtime = runtime_grid(grid);
+    let segments = segments(&cfg);
+    let (width, height) = (cfg.width, cfg.height);
+
+    let mut out = format!(
+        "/* {}, compiled by befunge_interpreter */\n\
+         #include <stdint.h>\n#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n\n",
+        comment(name)
+    );
+    out.push_str(&format!(
+        "#define WIDTH {}\n#define HEIGHT {}\n#define SEGMENTS {}\n\n",
+        width,
+        height,
+        segments.len()
+    ));
+
+    // the grid, for `p` and `g` and for cells run one at a time
+    out.push_str(&format!(
+        "static uint32_t grid[{}][{}] = {{\n",
+        height.max(1),
+        width.max(1)
+    ));
+    for row in &runtime {
+        let cells: Vec<String> = row.iter().map(|&c| (c as u32).to_string()).collect();
+        out.push_str(&format!("    {{{}}},\n", cells.join(", ")));
+    }
+    out.push_str("};\n");
+    out.push_str(&format!(
+        "static unsigned char segment_valid[{}] = {{{}}};\n",
+        segments.len().max(1),
+        vec!["1"; segments.len().max(1)].join(", ")
+    ));
+    out.push_str(RUNTIME);
+
+    // segments are dropped as soon as `p` changes one of their cells
+    let cells = segment_cells(&segments);
+    out.push_str("\nstatic void invalidate(size_t x, size_t y) {\n    switch (y * WIDTH + x) {\n");
+    for ((y, x), owners) in &cells {
+        let drops: Vec<String> = owners
+            .iter()
+            .map(|index| format!("segment_valid[{}] = 0;", index))
+            .collect();
+        out.push_str(&format!(
+            "    case {}: {} break;\n",
+            y * width + x,
+            drops.join(" ")
+        ));
+    }
+    out.push_str("    default: break;\n    }\n}\n\n");
+
+    for (index, segment) in segments.iter().enumerate() {
+        out.push_str(&segment_function(&cfg, index, segment));
+    }
+
+    out.push_str("static int segment_at(const IP *ip) {\n    if (ip->x >= WIDTH || ip->y >= HEIGHT) return -1;\n    switch (((ip->y * WIDTH + ip->x) * 4 + ip->dir) * 2 + ip->string_mode) {\n");
+    for (index, segment) in segments.iter().enumerate() {
+        out.push_str(&format!(
+            "    case {}: return {};\n",
+            state_key(&segment.entry(), width),
+            index
+        ));
+    }
+    out.push_str("    default: return -1;\n    }\n}\n\n");
+
+    out.push_str("static void run_segment(int segment, IP *ip) {\n    switch (segment) {\n");
+    for index in 0..segments.len() {
+        out.push_str(&format!(
+            "    case {}: segment_{}(ip); break;\n",
+            index, index
+        ));
+    }
+    out.push_str("    default: break;\n    }\n}\n\n");
+
+    out.push_str("static void start_ips(void) {\n");
+    for start in &cfg.starts {
+        out.push_str(&format!(
+            "    start({}, {}, {});\n",
+            start.x,
+            start.y,
+            direction_index(start.direction)
+        ));
+    }
+    out.push_str("}\n");
+    out.push_str(MAIN);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::tests::{assert_matches_interpreter, run};
+    use std::process::Command;
+    use std::{env, fs};
+
+    /// compile with the local C compiler and run; `None` without one
+    fn compile_and_run(grid: &[Vec<char>], input: &str, name: &str) -> Option<String> {
+        let dir = env::temp_dir().join(format!("befunge-c-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("prog.c");
+        let binary = dir.join("prog");
+        fs::write(&source, compile(grid, name)).unwrap();
+        let status = Command::new("cc")
+            .args(["-std=c99", "-O1", "-Wall", "-Werror", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .status()
+            .ok()?;
+        assert!(status.success(), "cc failed on {}", source.display());
+        let output = run(&binary, input);
+        fs::remove_dir_all(&dir).ok();
+        Some(output)
+    }
+
+    #[test]
+    fn test_compiled_output_matches_interpreter() {
+        assert_matches_interpreter(compile_and_run);
+    }
+}
@@ -0,0 +1,349 @@
+// src/compiler/mod.rs
+
+pub mod c;
+pub mod rust;
+pub mod wat;
+
+use crate::analysis::cfg::{ControlFlowGraph, State};
+use crate::entities::Direction;
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Target {
+    /// C99 with stdio only
+    C,
+    /// a single Rust file, std only
+    Rust,
+    /// WebAssembly text, a WASI command
+    Wat,
+}
+
+impl Target {
+    /// translate `grid`, loaded from `name`, into a standalone program
+    pub fn compile(self, grid: &[Vec<char>], name: &str) -> String {
+        match self {
+            Target::C => c::compile(grid, name),
+            Target::Rust => rust::compile(grid, name),
+            Target::Wat => wat::compile(grid, name),
+        }
+    }
+}
+
+/// A straight-line run of states compiled into plain statements.
+///
+/// `body` runs without looking at the grid, so it is only valid while `p` has not rewritten
+/// any of its cells. `last` is left to the generic interpreter loop: it may branch, spawn,
+/// end the IP or, for a `p`, invalidate other segments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub body: Vec<State>,
+    pub last: State,
+}
+
+impl Segment {
+    pub fn entry(&self) -> State {
+        self.body[0]
+    }
+}
+
+fn is_put(cfg: &ControlFlowGraph, state: &State) -> bool {
+    !state.string_mode && cfg.cell(state.x, state.y) == 'p'
+}
+
+/// the basic blocks, cut after every `p` so that a write is seen before the next segment
+pub fn segments(cfg: &ControlFlowGraph) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for block in cfg.basic_blocks() {
+        let mut run: Vec<State> = Vec::new();
+        for state in block.states {
+            run.push(state);
+            if is_put(cfg, &state) {
+                segments.extend(segment(std::mem::take(&mut run)));
+            }
+        }
+        segments.extend(segment(run));
+    }
+    segments
+}
+
+fn segment(mut run: Vec<State>) -> Option<Segment> {
+    let last = run.pop()?;
+    (!run.is_empty()).then_some(Segment { body: run, last })
+}
+
+/// the segments each cell belongs to, keyed by `(y, x)`: a `p` there drops all of them
+pub fn segment_cells(segments: &[Segment]) -> BTreeMap<(usize, usize), Vec<usize>> {
+    let mut cells: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+    for (index, segment) in segments.iter().enumerate() {
+        for state in &segment.body {
+            let owners = cells.entry((state.y, state.x)).or_default();
+            if !owners.contains(&index) {
+                owners.push(index);
+            }
+        }
+    }
+    cells
+}
+
+/// 0 right, 1 left, 2 up, 3 down in the generated code
+pub fn direction_index(direction: Direction) -> usize {
+    match direction {
+        Direction::Right => 0,
+        Direction::Left => 1,
+        Direction::Up => 2,
+        Direction::Down => 3,
+        Direction::High | Direction::Low => {
+            unreachable!("compiled programs are Befunge, which has no layers")
+        }
+    }
+}
+
+/// number identifying an IP state in the generated `segment_at`
+pub fn state_key(state: &State, width: usize) -> usize {
+    ((state.y * width + state.x) * 4 + direction_index(state.direction)) * 2
+        + state.string_mode as usize
+}
+
+/// How a target language spells the statements of a segment body.
+pub trait Syntax {
+    fn literal(&self, value: usize) -> String {
+        value.to_string()
+    }
+    /// reading the local `name`
+    fn variable(&self, name: &str) -> String {
+        name.to_string()
+    }
+    /// a new local initialized to `init`
+    fn local(&self, name: &str, init: &str) -> String;
+    /// an expression popping the IP's real stack
+    fn pop(&self) -> String;
+    /// `b op a` for `+ - * / % \``, executed at `at`
+    fn binary(&self, op: char, b: &str, a: &str, at: &State) -> String;
+    /// `!a`
+    fn not(&self, a: &str) -> String;
+    /// a statement using a dropped value, if the language warns about unused locals
+    fn discard(&self, value: &str) -> Option<String>;
+    /// `.` or `,`
+    fn print(&self, op: char, a: &str) -> String;
+    /// `&` or `~` into a new local `name`, ending the IP at `at` on end of input
+    fn read(&self, op: char, name: &str, at: &State) -> Vec<String>;
+    /// `g` into a new local `name`, ending the IP at `at` off the grid
+    fn get(&self, x: &str, y: &str, name: &str, at: &State) -> Vec<String>;
+    fn push(&self, value: &str) -> String;
+    /// move the IP to `last`, which the dispatch loop runs next
+    fn jump(&self, last: &State) -> String;
+}
+
+/// Statements for a segment body, and how many locals `v0`, `v1`, ... they use.
+pub struct StraightLine {
+    pub lines: Vec<String>,
+    pub locals: usize,
+}
+
+/// the statements of `segment`, keeping values pushed in it in locals where it can
+pub fn straight_line(
+    syntax: &impl Syntax,
+    cfg: &ControlFlowGraph,
+    segment: &Segment,
+) -> StraightLine {
+    let mut writer = Writer {
+        syntax,
+        lines: Vec::new(),
+        stack: Vec::new(),
+        locals: 0,
+    };
+    for state in &segment.body {
+        writer.instruction(state, cfg.cell(state.x, state.y));
+    }
+    for value in std::mem::take(&mut writer.stack) {
+        let line = syntax.push(&value);
+        writer.lines.push(line);
+    }
+    writer.lines.push(syntax.jump(&segment.last));
+    StraightLine {
+        lines: writer.lines,
+        locals: writer.locals,
+    }
+}
+
+struct Writer<'a, S: Syntax> {
+    syntax: &'a S,
+    lines: Vec<String>,
+    /// values pushed in this segment and not popped yet, as expressions
+    stack: Vec<String>,
+    locals: usize,
+}
+
+impl<S: Syntax> Writer<'_, S> {
+    fn name(&mut self) -> String {
+        self.locals += 1;
+        format!("v{}", self.locals - 1)
+    }
+
+    fn local(&mut self, init: &str) -> String {
+        let name = self.name();
+        self.lines.push(self.syntax.local(&name, init));
+        self.syntax.variable(&name)
+    }
+
+    /// a value pushed earlier in the segment, or one from the real stack
+    fn pop(&mut self) -> String {
+        match self.stack.pop() {
+            Some(expression) => expression,
+            None => self.local(&self.syntax.pop()),
+        }
+    }
+
+    fn instruction(&mut self, state: &State, c: char) {
+        if state.string_mode {
+            if c != '"' {
+                self.stack.push(self.syntax.literal(c as usize));
+            }
+            return;
+        }
+        match c {
+            '0'..='9' => self
+                .stack
+                .push(self.syntax.literal(c as usize - '0' as usize)),
+            '+' | '-' | '*' | '`' | '/' | '%' => {
+                let a = self.pop();
+                let b = self.pop();
+                let name = self.local(&self.syntax.binary(c, &b, &a, state));
+                self.stack.push(name);
+            }
+            '!' => {
+                let a = self.pop();
+                let name = self.local(&self.syntax.not(&a));
+                self.stack.push(name);
+            }
+            ':' => {
+                let a = self.pop();
+                self.stack.push(a.clone());
+                self.stack.push(a);
+            }
+            '\\' => {
+                let a = self.pop();
+                let b = self.pop();
+                self.stack.push(a);
+                self.stack.push(b);
+            }
+            '$' => {
+                let a = self.pop();
+                self.lines.extend(self.syntax.discard(&a));
+            }
+            '.' | ',' => {
+                let a = self.pop();
+                self.lines.push(self.syntax.print(c, &a));
+            }
+            '&' | '~' => {
+                let name = self.name();
+                self.lines.extend(self.syntax.read(c, &name, state));
+                self.stack.push(self.syntax.variable(&name));
+            }
+            'g' => {
+                let y = self.pop();
+                let x = self.pop();
+                let name = self.name();
+                self.lines.extend(self.syntax.get(&x, &y, &name, state));
+                self.stack.push(self.syntax.variable(&name));
+            }
+            // direction changes, `#`, `"` and no-ops are in the segment's layout already
+            _ => {}
+        }
+    }
+}
+
+/// the grid as an IP sees it: start markers become spaces when the run starts
+pub fn runtime_grid(grid: &[Vec<char>]) -> Vec<Vec<char>> {
+    grid.iter()
+        .map(|row| {
+            row.iter()
+                .map(|&c| match c {
+                    '→' | '←' | '↑' | '↓' => ' ',
+                    c => c,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::testing;
+    use std::io::Write;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    /// programs every target must run like the interpreter: name, lines, input
+    const PROGRAMS: &[(&str, &[&str], &str)] = &[
+        (
+            "hello",
+            &[
+                "v",
+                ">           v",
+                " v \"Hello \" <",
+                " 0",
+                "v_ , v",
+                "     :     \"<- print loop\"",
+                " ^ ! <",
+                " 0",
+                ">  \"@\"07p   v",
+                " ^  \"Wolrd\" <",
+            ],
+            "",
+        ),
+        // countdown from a number read with `&`, then a char read with `~`
+        ("countdown", &["&>:.1-:v", " ^     _$~,55+,@"], "5\nxyz"),
+        // arithmetic, signed division, `g`, `#` and `\`
+        (
+            "arith",
+            &["95*.92/.0 5-2/.73%.65`.56`.0 2-3%. 10g,#@ 12\\..55+,@"],
+            "",
+        ),
+        // `p` rewrites two cells of a straight-line loop, the second pass differs
+        ("selfmod", &[">  1.         v", "^ p03\"2\"p05\"@\"<"], ""),
+        // the new IP prints the stack it copied
+        ("threads", &["901t$@", "   >.@"], ""),
+        // off the left edge, across the empty Funge-Space and back in on the right
+        ("wrap", &["<@.1"], ""),
+    ];
+
+    /// stdout of `binary` fed `input`
+    pub(super) fn run(binary: &Path, input: &str) -> String {
+        run_command(Command::new(binary), input)
+    }
+
+    pub(super) fn run_command(mut command: Command, input: &str) -> String {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// `compile_and_run(grid, input, name)` returns `None` when the toolchain is missing
+    pub(super) fn assert_matches_interpreter(
+        compile_and_run: impl Fn(&[Vec<char>], &str, &str) -> Option<String>,
+    ) {
+        for (name, lines, input) in PROGRAMS {
+            let grid = testing::grid(lines);
+            let expected = testing::run(&lines.join("\n"), input);
+            let Some(actual) = compile_and_run(&grid, input, name) else {
+                eprintln!("no compiler for the target, skipping");
+                return;
+            };
+            assert_eq!(actual, expected, "{}", name);
+            assert!(!expected.is_empty(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,505 @@
+// src/compiler/rust.rs
+
+use crate::analysis::cfg::{ControlFlowGraph, State};
+use crate::compiler::{
+    direction_index, runtime_grid, segment_cells, segments, state_key, straight_line, Segment,
+    Syntax,
+};
+
+/// Stack, IPs, IO and the generic one-cell-at-a-time loop, with the default policies of
+/// `CommandRegistry`. Expects `WIDTH`, `HEIGHT`, `SOURCE`, `STARTS`, `SEGMENTS` and the
+/// generated `segment_at` and `invalidate` around it.
+const RUNTIME: &str = r#"
+const DX: [isize; 4] = [1, -1, 0, 0];
+const DY: [isize; 4] = [0, 0, -1, 1];
+
+struct Ip {
+    x: usize,
+    y: usize,
+    /// 0 right, 1 left, 2 up, 3 down
+    dir: usize,
+    string_mode: bool,
+    alive: bool,
+    stack: Vec<usize>,
+}
+
+impl Ip {
+    /// the grid wraps at 1024 like the interpreter's
+    fn advance(&mut self) {
+        self.x = (self.x as isize + DX[self.dir]).rem_euclid(1024) as usize;
+        self.y = (self.y as isize + DY[self.dir]).rem_euclid(1024) as usize;
+    }
+}
+
+struct Machine {
+    grid: Vec<Vec<char>>,
+    /// segments whose cells no `p` has changed yet
+    valid: Vec<bool>,
+    ips: Vec<Ip>,
+    out: BufWriter<Stdout>,
+    input: StdinLock<'static>,
+    division_warned: bool,
+}
+
+fn to_char(value: usize) -> char {
+    char::from_u32(value as u32).unwrap_or('\u{FFFD}')
+}
+
+impl Machine {
+    fn new() -> Self {
+        let ips = STARTS
+            .iter()
+            .map(|&(x, y, dir)| Ip {
+                x,
+                y,
+                dir,
+                string_mode: false,
+                alive: true,
+                stack: Vec::new(),
+            })
+            .collect();
+        Machine {
+            grid: SOURCE.iter().map(|row| row.chars().collect()).collect(),
+            valid: vec![true; SEGMENTS.len()],
+            ips,
+            out: BufWriter::new(io::stdout()),
+            input: io::stdin().lock(),
+            division_warned: false,
+        }
+    }
+
+    fn push(&mut self, i: usize, value: usize) {
+        self.ips[i].stack.push(value);
+    }
+
+    /// an empty stack pops 0
+    fn pop(&mut self, i: usize) -> usize {
+        self.ips[i].stack.pop().unwrap_or(0)
+    }
+
+    fn fail(&mut self, i: usize, message: &str) {
+        let _ = self.out.flush();
+        let ip = &mut self.ips[i];
+        eprintln!("error: {} (ip at ({}, {}))", message, ip.x, ip.y);
+        ip.alive = false;
+    }
+
+    fn print_number(&mut self, value: usize) {
+        let _ = write!(self.out, "{} ", value);
+    }
+
+    fn print_char(&mut self, value: usize) {
+        let _ = write!(self.out, "{}", to_char(value));
+    }
+
+    /// signed, like the interpreter; division by zero pushes 0 and warns once
+    fn divide(&mut self, b: usize, a: usize, modulo: bool, x: usize, y: usize) -> usize {
+        if a == 0 {
+            if !self.division_warned {
+                self.division_warned = true;
+                let _ = self.out.flush();
+                eprintln!("warning: division by zero at ({}, {})", x, y);
+            }
+            return 0;
+        }
+        let (b, a) = (b as isize, a as isize);
+        let value = if modulo { b.wrapping_rem(a) } else { b.wrapping_div(a) };
+        value as usize
+    }
+
+    /// one line, 0 unless it is a number; at the end of input the IP at `at` fails
+    fn read_number(&mut self, i: usize, at: (usize, usize)) -> Option<usize> {
+        let _ = self.out.flush();
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(n) if n > 0 => Some(line.trim().parse().unwrap_or(0)),
+            _ => self.end_of_input(i, at),
+        }
+    }
+
+    /// one byte; at the end of input the IP at `at` fails
+    fn read_char(&mut self, i: usize, at: (usize, usize)) -> Option<usize> {
+        let _ = self.out.flush();
+        let mut byte = [0u8];
+        match self.input.read(&mut byte) {
+            Ok(1) => Some(byte[0] as usize),
+            _ => self.end_of_input(i, at),
+        }
+    }
+
+    fn end_of_input(&mut self, i: usize, (x, y): (usize, usize)) -> Option<usize> {
+        self.ips[i].x = x;
+        self.ips[i].y = y;
+        self.fail(i, "unexpected end of input");
+        None
+    }
+
+    fn get_cell(&mut self, i: usize, at: (usize, usize), x: usize, y: usize) -> Option<usize> {
+        if y < HEIGHT && x < WIDTH {
+            return Some(self.grid[y][x] as usize);
+        }
+        self.ips[i].x = at.0;
+        self.ips[i].y = at.1;
+        self.fail(i, &format!("attempt to get value out of bounds at ({}, {})", x, y));
+        None
+    }
+
+    fn put_cell(&mut self, i: usize, x: usize, y: usize, value: usize) -> bool {
+        if y < HEIGHT && x < WIDTH {
+            let c = to_char(value);
+            if self.grid[y][x] != c {
+                self.grid[y][x] = c;
+                invalidate(&mut self.valid, x, y);
+            }
+            return true;
+        }
+        self.fail(i, &format!("attempt to set value out of bounds at ({}, {})", x, y));
+        false
+    }
+
+    /// `t`: the new IP copies the stack and moves once; bad deltas keep the parent's direction
+    fn spawn(&mut self, parent: usize, dx: usize, dy: usize) {
+        let parent = &self.ips[parent];
+        let dir = match (dx as isize, dy as isize) {
+            (1, 0) => 0,
+            (-1, 0) => 1,
+            (0, -1) => 2,
+            (0, 1) => 3,
+            _ => parent.dir,
+        };
+        let mut child = Ip {
+            x: parent.x,
+            y: parent.y,
+            dir,
+            string_mode: parent.string_mode,
+            alive: true,
+            stack: parent.stack.clone(),
+        };
+        child.advance();
+        self.ips.push(child);
+    }
+
+    /// the interpreter's step: fetch, execute, move
+    fn step(&mut self, i: usize) {
+        let (x, y) = (self.ips[i].x, self.ips[i].y);
+        // Funge-Space outside the program is empty
+        let c = if x < WIDTH && y < HEIGHT {
+            self.grid[y][x]
+        } else {
+            ' '
+        };
+        if self.ips[i].string_mode {
+            if c == '"' {
+                self.ips[i].string_mode = false;
+            } else {
+                self.push(i, c as usize);
+            }
+            self.ips[i].advance();
+            return;
+        }
+        match c {
+            '0'..='9' => self.push(i, c as usize - '0' as usize),
+            '+' | '-' | '*' | '/' | '%' | '`' => {
+                let a = self.pop(i);
+                let b = self.pop(i);
+                let value = match c {
+                    '+' => b.wrapping_add(a),
+                    '-' => b.wrapping_sub(a),
+                    '*' => b.wrapping_mul(a),
+                    '`' => (b > a) as usize,
+                    _ => self.divide(b, a, c == '%', x, y),
+                };
+                self.push(i, value);
+            }
+            '!' => {
+                let a = self.pop(i);
+                self.push(i, (a == 0) as usize);
+            }
+            ':' => {
+                let a = self.pop(i);
+                self.push(i, a);
+                self.push(i, a);
+            }
+            '\\' => {
+                let a = self.pop(i);
+                let b = self.pop(i);
+                self.push(i, a);
+                self.push(i, b);
+            }
+            '$' => {
+                self.pop(i);
+            }
+            '.' => {
+                let a = self.pop(i);
+                self.print_number(a);
+            }
+            ',' => {
+                let a = self.pop(i);
+                self.print_char(a);
+            }
+            '>' => self.ips[i].dir = 0,
+            '<' => self.ips[i].dir = 1,
+            '^' => self.ips[i].dir = 2,
+            'v' => self.ips[i].dir = 3,
+            '_' => self.ips[i].dir = if self.pop(i) == 0 { 0 } else { 1 },
+            '|' => self.ips[i].dir = if self.pop(i) == 0 { 3 } else { 2 },
+            '"' => self.ips[i].string_mode = true,
+            '#' => self.ips[i].advance(),
+            '@' => {
+                self.ips[i].alive = false;
+                return;
+            }
+            '&' | '~' => {
+                let value = if c == '&' {
+                    self.read_number(i, (x, y))
+                } else {
+                    self.read_char(i, (x, y))
+                };
+                let Some(value) = value else { return };
+                self.push(i, value);
+            }
+            'g' => {
+                let cy = self.pop(i);
+                let cx = self.pop(i);
+                let Some(value) = self.get_cell(i, (x, y), cx, cy) else { return };
+                self.push(i, value);
+            }
+            'p' => {
+                let cy = self.pop(i);
+                let cx = self.pop(i);
+                let value = self.pop(i);
+                if !self.put_cell(i, cx, cy, value) {
+                    return;
+                }
+            }
+            't' => {
+                let dy = self.pop(i);
+                let dx = self.pop(i);
+                self.spawn(i, dx, dy);
+            }
+            _ => {}
+        }
+        self.ips[i].advance();
+    }
+}
+"#;
+
+/// runs the IPs in turn until all have ended
+const MAIN: &str = r#"
+fn main() {
+    let mut m = Machine::new();
+    while !m.ips.is_empty() {
+        // IPs spawned on the way run in the same turn
+        let mut i = 0;
+        while i < m.ips.len() {
+            if m.ips[i].alive {
+                match segment_at(&m.ips[i]) {
+                    Some(segment) if m.valid[segment] => SEGMENTS[segment](&mut m, i),
+                    _ => m.step(i),
+                }
+            }
+            i += 1;
+        }
+        m.ips.retain(|ip| ip.alive);
+    }
+    let _ = m.out.flush();
+}
+"#;
+
+struct RustSyntax;
+
+impl Syntax for RustSyntax {
+    fn local(&self, name: &str, init: &str) -> String {
+        format!("let {}: usize = {};", name, init)
+    }
+
+    fn pop(&self) -> String {
+        "m.pop(i)".to_string()
+    }
+
+    // literals are untyped, so methods are called through `usize::`
+    fn binary(&self, op: char, b: &str, a: &str, at: &State) -> String {
+        match op {
+            '+' => format!("usize::wrapping_add({}, {})", b, a),
+            '-' => format!("usize::wrapping_sub({}, {})", b, a),
+            '*' => format!("usize::wrapping_mul({}, {})", b, a),
+            '`' => format!("({} > {}) as usize", b, a),
+            _ => format!("m.divide({}, {}, {}, {}, {})", b, a, op == '%', at.x, at.y),
+        }
+    }
+
+    fn not(&self, a: &str) -> String {
+        format!("({} == 0) as usize", a)
+    }
+
+    fn discard(&self, value: &str) -> Option<String> {
+        Some(format!("let _ = {};", value))
+    }
+
+    fn print(&self, op: char, a: &str) -> String {
+        if op == '.' {
+            format!("m.print_number({});", a)
+        } else {
+            format!("m.print_char({});", a)
+        }
+    }
+
+    fn read(&self, op: char, name: &str, at: &State) -> Vec<String> {
+        let read = if op == '&' {
+            "read_number"
+        } else {
+            "read_char"
+        };
+        vec![format!(
+            "let Some({}) = m.{}(i, ({}, {})) else {{ return }};",
+            name, read, at.x, at.y
+        )]
+    }
+
+    fn get(&self, x: &str, y: &str, name: &str, at: &State) -> Vec<String> {
+        vec![format!(
+            "let Some({}) = m.get_cell(i, ({}, {}), {}, {}) else {{ return }};",
+            name, at.x, at.y, x, y
+        )]
+    }
+
+    fn push(&self, value: &str) -> String {
+        format!("m.push(i, {});", value)
+    }
+
+    fn jump(&self, last: &State) -> String {
+        format!(
+            "let ip = &mut m.ips[i]; ip.x = {}; ip.y = {}; ip.dir = {}; ip.string_mode = {};",
+            last.x,
+            last.y,
+            direction_index(last.direction),
+            last.string_mode
+        )
+    }
+}
+
+fn segment_function(cfg: &ControlFlowGraph, index: usize, segment: &Segment) -> String {
+    let code: String = segment
+        .body
+        .iter()
+        .map(|s| cfg.cell(s.x, s.y))
+        .filter(|c| *c != ' ')
+        .collect();
+    let entry = segment.entry();
+    let mut out = format!(
+        "/// ({}, {}) {}: {:?}\nfn segment_{}(m: &mut Machine, i: usize) {{\n",
+        entry.x, entry.y, entry.direction, code, index
+    );
+    for line in straight_line(&RustSyntax, cfg, segment).lines {
+        out.push_str(&format!("    {}\n", line));
+    }
+    out.push_str("}\n\n");
+    out
+}
+
+pub fn compile(grid: &[Vec<char>], name: &str) -> String {
+    let cfg = ControlFlowGraph::build(grid);
+    let runtime = runtime_grid(grid);
+    let segments = segments(&cfg);
+    let (width, height) = (cfg.width, cfg.height);
+
+    let mut out = format!(
+        "// {}, compiled by befunge_interpreter\n\n\
+         use std::io::{{self, BufRead, BufWriter, Read, StdinLock, Stdout, Write}};\n\n",
+        name.replace('\n', " ")
+    );
+    out.push_str(&format!(
+        "const WIDTH: usize = {};\nconst HEIGHT: usize = {};\n\n",
+        width, height
+    ));
+
+    // the grid, for `p` and `g` and for cells run one at a time
+    out.push_str("const SOURCE: &[&str] = &[\n");
+    for row in &runtime {
+        out.push_str(&format!("    {:?},\n", row.iter().collect::<String>()));
+    }
+    out.push_str("];\n\n");
+    let starts: Vec<String> = cfg
+        .starts
+        .iter()
+        .map(|s| format!("({}, {}, {})", s.x, s.y, direction_index(s.direction)))
+        .collect();
+    out.push_str(&format!(
+        "const STARTS: &[(usize, usize, usize)] = &[{}];\n\n",
+        starts.join(", ")
+    ));
+    let functions: Vec<String> = (0..segments.len())
+        .map(|index| format!("segment_{}", index))
+        .collect();
+    out.push_str(&format!(
+        "const SEGMENTS: &[fn(&mut Machine, usize)] = &[{}];\n",
+        functions.join(", ")
+    ));
+    out.push_str(RUNTIME);
+
+    // segments are dropped as soon as `p` changes one of their cells
+    let cells = segment_cells(&segments);
+    out.push_str(
+        "\nfn invalidate(valid: &mut [bool], x: usize, y: usize) {\n    match y * WIDTH + x {\n",
+    );
+    for ((y, x), owners) in &cells {
+        let drops: Vec<String> = owners
+            .iter()
+            .map(|index| format!("valid[{}] = false;", index))
+            .collect();
+        out.push_str(&format!(
+            "        {} => {{ {} }}\n",
+            y * width + x,
+            drops.join(" ")
+        ));
+    }
+    out.push_str("        _ => {}\n    }\n}\n\n");
+
+    for (index, segment) in segments.iter().enumerate() {
+        out.push_str(&segment_function(&cfg, index, segment));
+    }
+
+    out.push_str("fn segment_at(ip: &Ip) -> Option<usize> {\n    if ip.x >= WIDTH || ip.y >= HEIGHT {\n        return None;\n    }\n    match ((ip.y * WIDTH + ip.x) * 4 + ip.dir) * 2 + ip.string_mode as usize {\n");
+    for (index, segment) in segments.iter().enumerate() {
+        out.push_str(&format!(
+            "        {} => Some({}),\n",
+            state_key(&segment.entry(), width),
+            index
+        ));
+    }
+    out.push_str("        _ => None,\n    }\n}\n");
+    out.push_str(MAIN);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::tests::{assert_matches_interpreter, run};
+    use std::process::Command;
+    use std::{env, fs};
+
+    /// compile with rustc and run; `None` without it
+    fn compile_and_run(grid: &[Vec<char>], input: &str, name: &str) -> Option<String> {
+        let dir = env::temp_dir().join(format!("befunge-rs-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("prog.rs");
+        let binary = dir.join("prog");
+        fs::write(&source, compile(grid, name)).unwrap();
+        let status = Command::new("rustc")
+            .args(["--edition", "2021", "-O", "-D", "warnings", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .status()
+            .ok()?;
+        assert!(status.success(), "rustc failed on {}", source.display());
+        let output = run(&binary, input);
+        fs::remove_dir_all(&dir).ok();
+        Some(output)
+    }
+
+    #[test]
+    fn test_compiled_output_matches_interpreter() {
+        assert_matches_interpreter(compile_and_run);
+    }
+}
@@ -0,0 +1,981 @@
+// src/compiler/wat.rs
+
+use crate::analysis::cfg::{ControlFlowGraph, State};
+use crate::compiler::{
+    direction_index, runtime_grid, segment_cells, segments, state_key, straight_line, Segment,
+    Syntax,
+};
+
+/// Funge-Space starts here in linear memory; below it are the IO buffers and messages
+const GRID: usize = 8192;
+/// messages for stderr, as an i32 length and the bytes
+const MESSAGES_AT: usize = 64;
+const MESSAGES: &[(&str, &str)] = &[
+    ("error", "error: "),
+    ("ip_at", " (ip at ("),
+    ("comma", ", "),
+    ("ip_at_end", "))\n"),
+    ("close", ")"),
+    ("close_line", ")\n"),
+    ("end_of_input", "unexpected end of input"),
+    ("get", "attempt to get value out of bounds at ("),
+    ("put", "attempt to set value out of bounds at ("),
+    ("division", "warning: division by zero at ("),
+    ("out_of_memory", "out of memory\n"),
+];
+
+/// Stack, IPs, IO and the generic one-cell-at-a-time loop, with the default policies of
+/// `CommandRegistry`. Values are i64; an IP is 32 bytes in the `$ips` table:
+/// x, y, dir (0 right, 1 left, 2 up, 3 down), string mode, alive, stack data, length and
+/// capacity. Expects the layout globals, the messages and the generated `$segment_at`,
+/// `$invalidate` and `$start_ips` around it.
+const RUNTIME: &str = r##"
+  (type $segment (func (param i32)))
+
+  ;; linear memory past the static data is bumped and never freed
+  (func $alloc (param $size i32) (result i32)
+    (local $ptr i32) (local $end i32)
+    (local.set $ptr (global.get $heap))
+    (local.set $end
+      (i32.and (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 7)) (i32.const -8)))
+    (if (i32.gt_u (local.get $end) (i32.mul (memory.size) (i32.const 65536)))
+      (then
+        (if (i32.eq
+              (memory.grow
+                (i32.sub (i32.shr_u (i32.add (local.get $end) (i32.const 65535)) (i32.const 16))
+                         (memory.size)))
+              (i32.const -1))
+          (then
+            (call $flush)
+            (call $err_text (global.get $m_out_of_memory))
+            (call $proc_exit (i32.const 2))))))
+    (global.set $heap (local.get $end))
+    (local.get $ptr))
+
+  (func $write_fd (param $fd i32) (param $ptr i32) (param $len i32)
+    (block $done
+      (loop $more
+        (br_if $done (i32.eqz (local.get $len)))
+        (i32.store (i32.const 0) (local.get $ptr))
+        (i32.store (i32.const 4) (local.get $len))
+        (br_if $done (call $fd_write (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8)))
+        (br_if $done (i32.eqz (i32.load (i32.const 8))))
+        (local.set $ptr (i32.add (local.get $ptr) (i32.load (i32.const 8))))
+        (local.set $len (i32.sub (local.get $len) (i32.load (i32.const 8))))
+        (br $more))))
+
+  ;; stdout is buffered at 1024..5120, flushed before input, stderr and the end
+  (func $flush
+    (call $write_fd (i32.const 1) (i32.const 1024) (global.get $out_len))
+    (global.set $out_len (i32.const 0)))
+
+  (func $out_byte (param $b i32)
+    (if (i32.eq (global.get $out_len) (i32.const 4096)) (then (call $flush)))
+    (i32.store8 (i32.add (i32.const 1024) (global.get $out_len)) (local.get $b))
+    (global.set $out_len (i32.add (global.get $out_len) (i32.const 1))))
+
+  ;; the decimal digits of $v, ending at 48; returns where they start
+  (func $format (param $v i64) (result i32)
+    (local $p i32)
+    (local.set $p (i32.const 48))
+    (loop $digit
+      (local.set $p (i32.sub (local.get $p) (i32.const 1)))
+      (i32.store8 (local.get $p)
+        (i32.add (i32.const 48) (i32.wrap_i64 (i64.rem_u (local.get $v) (i64.const 10)))))
+      (local.set $v (i64.div_u (local.get $v) (i64.const 10)))
+      (br_if $digit (i64.ne (local.get $v) (i64.const 0))))
+    (local.get $p))
+
+  (func $err_text (param $message i32)
+    (call $write_fd (i32.const 2)
+      (i32.add (local.get $message) (i32.const 4)) (i32.load (local.get $message))))
+
+  (func $err_number (param $v i64)
+    (local $p i32)
+    (local.set $p (call $format (local.get $v)))
+    (call $write_fd (i32.const 2) (local.get $p) (i32.sub (i32.const 48) (local.get $p))))
+
+  (func $print_number (param $v i64)
+    (local $p i32)
+    (local.set $p (call $format (local.get $v)))
+    (loop $copy
+      (call $out_byte (i32.load8_u (local.get $p)))
+      (local.set $p (i32.add (local.get $p) (i32.const 1)))
+      (br_if $copy (i32.lt_u (local.get $p) (i32.const 48))))
+    (call $out_byte (i32.const 32)))
+
+  (func $to_char (param $v i64) (result i32)
+    (local $c i32)
+    (local.set $c (i32.wrap_i64 (local.get $v)))
+    (if (result i32)
+      (i32.or (i32.gt_u (local.get $c) (i32.const 0x10FFFF))
+              (i32.lt_u (i32.sub (local.get $c) (i32.const 0xD800)) (i32.const 0x800)))
+      (then (i32.const 0xFFFD))
+      (else (local.get $c))))
+
+  ;; UTF-8
+  (func $print_char (param $v i64)
+    (local $c i32)
+    (local.set $c (call $to_char (local.get $v)))
+    (if (i32.lt_u (local.get $c) (i32.const 0x80))
+      (then (call $out_byte (local.get $c)) (return)))
+    (if (i32.lt_u (local.get $c) (i32.const 0x800))
+      (then
+        (call $out_byte (i32.or (i32.const 0xC0) (i32.shr_u (local.get $c) (i32.const 6))))
+        (call $out_byte (i32.or (i32.const 0x80) (i32.and (local.get $c) (i32.const 0x3F))))
+        (return)))
+    (if (i32.lt_u (local.get $c) (i32.const 0x10000))
+      (then
+        (call $out_byte (i32.or (i32.const 0xE0) (i32.shr_u (local.get $c) (i32.const 12))))
+        (call $out_byte
+          (i32.or (i32.const 0x80) (i32.and (i32.shr_u (local.get $c) (i32.const 6)) (i32.const 0x3F))))
+        (call $out_byte (i32.or (i32.const 0x80) (i32.and (local.get $c) (i32.const 0x3F))))
+        (return)))
+    (call $out_byte (i32.or (i32.const 0xF0) (i32.shr_u (local.get $c) (i32.const 18))))
+    (call $out_byte
+      (i32.or (i32.const 0x80) (i32.and (i32.shr_u (local.get $c) (i32.const 12)) (i32.const 0x3F))))
+    (call $out_byte
+      (i32.or (i32.const 0x80) (i32.and (i32.shr_u (local.get $c) (i32.const 6)) (i32.const 0x3F))))
+    (call $out_byte (i32.or (i32.const 0x80) (i32.and (local.get $c) (i32.const 0x3F)))))
+
+  (func $ip (param $i i32) (result i32)
+    (i32.add (global.get $ips) (i32.shl (local.get $i) (i32.const 5))))
+
+  (func $push (param $ip i32) (param $v i64)
+    (local $len i32) (local $cap i32) (local $data i32)
+    (local.set $len (i32.load offset=24 (local.get $ip)))
+    (local.set $cap (i32.load offset=28 (local.get $ip)))
+    (if (i32.eq (local.get $len) (local.get $cap))
+      (then
+        (local.set $cap
+          (select (i32.shl (local.get $cap) (i32.const 1)) (i32.const 16) (local.get $cap)))
+        (local.set $data (call $alloc (i32.shl (local.get $cap) (i32.const 3))))
+        (memory.copy (local.get $data) (i32.load offset=20 (local.get $ip))
+          (i32.shl (local.get $len) (i32.const 3)))
+        (i32.store offset=20 (local.get $ip) (local.get $data))
+        (i32.store offset=28 (local.get $ip) (local.get $cap))))
+    (i64.store
+      (i32.add (i32.load offset=20 (local.get $ip)) (i32.shl (local.get $len) (i32.const 3)))
+      (local.get $v))
+    (i32.store offset=24 (local.get $ip) (i32.add (local.get $len) (i32.const 1))))
+
+  ;; an empty stack pops 0
+  (func $pop (param $ip i32) (result i64)
+    (local $len i32)
+    (local.set $len (i32.load offset=24 (local.get $ip)))
+    (if (result i64) (i32.eqz (local.get $len))
+      (then (i64.const 0))
+      (else
+        (local.set $len (i32.sub (local.get $len) (i32.const 1)))
+        (i32.store offset=24 (local.get $ip) (local.get $len))
+        (i64.load
+          (i32.add (i32.load offset=20 (local.get $ip)) (i32.shl (local.get $len) (i32.const 3)))))))
+
+  ;; the grid wraps at 1024 like the interpreter's
+  (func $move (param $ip i32)
+    (local $dir i32)
+    (local.set $dir (i32.load offset=8 (local.get $ip)))
+    (i32.store (local.get $ip)
+      (i32.and
+        (i32.add (i32.load (local.get $ip))
+          (select (i32.const 1)
+            (select (i32.const -1) (i32.const 0) (i32.eq (local.get $dir) (i32.const 1)))
+            (i32.eqz (local.get $dir))))
+        (i32.const 1023)))
+    (i32.store offset=4 (local.get $ip)
+      (i32.and
+        (i32.add (i32.load offset=4 (local.get $ip))
+          (select (i32.const -1)
+            (select (i32.const 1) (i32.const 0) (i32.eq (local.get $dir) (i32.const 3)))
+            (i32.eq (local.get $dir) (i32.const 2))))
+        (i32.const 1023))))
+
+  (func $jump (param $ip i32) (param $x i32) (param $y i32) (param $dir i32) (param $string_mode i32)
+    (i32.store (local.get $ip) (local.get $x))
+    (i32.store offset=4 (local.get $ip) (local.get $y))
+    (i32.store offset=8 (local.get $ip) (local.get $dir))
+    (i32.store offset=12 (local.get $ip) (local.get $string_mode)))
+
+  (func $fail_begin
+    (call $flush)
+    (call $err_text (global.get $m_error)))
+
+  (func $fail_end (param $ip i32)
+    (call $err_text (global.get $m_ip_at))
+    (call $err_number (i64.extend_i32_u (i32.load (local.get $ip))))
+    (call $err_text (global.get $m_comma))
+    (call $err_number (i64.extend_i32_u (i32.load offset=4 (local.get $ip))))
+    (call $err_text (global.get $m_ip_at_end))
+    (i32.store offset=16 (local.get $ip) (i32.const 0)))
+
+  ;; signed, like the interpreter; division by zero pushes 0 and warns once
+  (func $divide (param $b i64) (param $a i64) (param $modulo i32) (param $x i32) (param $y i32)
+    (result i64)
+    (if (i64.eqz (local.get $a))
+      (then
+        (if (i32.eqz (global.get $division_warned))
+          (then
+            (global.set $division_warned (i32.const 1))
+            (call $flush)
+            (call $err_text (global.get $m_division))
+            (call $err_number (i64.extend_i32_u (local.get $x)))
+            (call $err_text (global.get $m_comma))
+            (call $err_number (i64.extend_i32_u (local.get $y)))
+            (call $err_text (global.get $m_close_line))))
+        (return (i64.const 0))))
+    (if (i64.eq (local.get $a) (i64.const -1))
+      (then
+        (return
+          (select (i64.const 0) (i64.sub (i64.const 0) (local.get $b)) (local.get $modulo)))))
+    (if (result i64) (local.get $modulo)
+      (then (i64.rem_s (local.get $b) (local.get $a)))
+      (else (i64.div_s (local.get $b) (local.get $a)))))
+
+  ;; one byte of stdin, -1 at the end
+  (func $read_byte (result i32)
+    (i32.store (i32.const 0) (i32.const 12))
+    (i32.store (i32.const 4) (i32.const 1))
+    (if (i32.or (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8))
+                (i32.eqz (i32.load (i32.const 8))))
+      (then (return (i32.const -1))))
+    (i32.load8_u (i32.const 12)))
+
+  ;; the IP fails at the (x, y) that read; 0 for the caller to stop
+  (func $end_of_input (param $ip i32) (param $x i32) (param $y i32) (result i32)
+    (i32.store (local.get $ip) (local.get $x))
+    (i32.store offset=4 (local.get $ip) (local.get $y))
+    (call $fail_begin)
+    (call $err_text (global.get $m_end_of_input))
+    (call $fail_end (local.get $ip))
+    (i32.const 0))
+
+  ;; what `str::trim` removes
+  (func $is_space (param $c i32) (result i32)
+    (i32.or (i32.eq (local.get $c) (i32.const 32))
+            (i32.lt_u (i32.sub (local.get $c) (i32.const 9)) (i32.const 5))))
+
+  ;; one line into $value, 0 unless it is a number; 0 at the end of input
+  (func $read_number (param $ip i32) (param $x i32) (param $y i32) (result i32)
+    (local $c i32) (local $any i32) (local $phase i32) (local $bad i32) (local $n i64) (local $d i64)
+    ;; phase 0: leading spaces, 1: after `+`, 2: digits, 3: trailing spaces
+    (call $flush)
+    (block $line
+      (loop $next
+        (local.set $c (call $read_byte))
+        (br_if $line (i32.eq (local.get $c) (i32.const -1)))
+        (local.set $any (i32.const 1))
+        (br_if $line (i32.eq (local.get $c) (i32.const 10)))
+        (if (call $is_space (local.get $c))
+          (then
+            (if (i32.eq (local.get $phase) (i32.const 1)) (then (local.set $bad (i32.const 1))))
+            (if (i32.eq (local.get $phase) (i32.const 2)) (then (local.set $phase (i32.const 3)))))
+          (else
+            (if (i32.and (i32.eq (local.get $c) (i32.const 43)) (i32.eqz (local.get $phase)))
+              (then (local.set $phase (i32.const 1)))
+              (else
+                (if (i32.and (i32.lt_u (i32.sub (local.get $c) (i32.const 48)) (i32.const 10))
+                             (i32.lt_u (local.get $phase) (i32.const 3)))
+                  (then
+                    (local.set $d (i64.extend_i32_u (i32.sub (local.get $c) (i32.const 48))))
+                    (if (i64.gt_u (local.get $n)
+                          (i64.div_u (i64.sub (i64.const -1) (local.get $d)) (i64.const 10)))
+                      (then (local.set $bad (i32.const 1))))
+                    (local.set $n
+                      (i64.add (i64.mul (local.get $n) (i64.const 10)) (local.get $d)))
+                    (local.set $phase (i32.const 2)))
+                  (else (local.set $bad (i32.const 1))))))))
+        (br $next)))
+    (if (i32.eqz (local.get $any))
+      (then (return (call $end_of_input (local.get $ip) (local.get $x) (local.get $y)))))
+    (global.set $value
+      (select (local.get $n) (i64.const 0)
+        (i32.and (i32.eqz (local.get $bad)) (i32.ge_u (local.get $phase) (i32.const 2)))))
+    (i32.const 1))
+
+  ;; one byte into $value; 0 at the end of input
+  (func $read_char (param $ip i32) (param $x i32) (param $y i32) (result i32)
+    (local $c i32)
+    (call $flush)
+    (local.set $c (call $read_byte))
+    (if (i32.eq (local.get $c) (i32.const -1))
+      (then (return (call $end_of_input (local.get $ip) (local.get $x) (local.get $y)))))
+    (global.set $value (i64.extend_i32_u (local.get $c)))
+    (i32.const 1))
+
+  ;; the address of a cell, 0 outside the grid
+  (func $cell (param $x i64) (param $y i64) (result i32)
+    (if (i32.or (i64.ge_u (local.get $x) (i64.extend_i32_u (global.get $width)))
+                (i64.ge_u (local.get $y) (i64.extend_i32_u (global.get $height))))
+      (then (return (i32.const 0))))
+    (i32.add (i32.const 8192)
+      (i32.shl
+        (i32.wrap_i64
+          (i64.add (i64.mul (local.get $y) (i64.extend_i32_u (global.get $width))) (local.get $x)))
+        (i32.const 2))))
+
+  (func $out_of_bounds (param $ip i32) (param $message i32) (param $x i64) (param $y i64)
+    (call $fail_begin)
+    (call $err_text (local.get $message))
+    (call $err_number (local.get $x))
+    (call $err_text (global.get $m_comma))
+    (call $err_number (local.get $y))
+    (call $err_text (global.get $m_close))
+    (call $fail_end (local.get $ip)))
+
+  ;; `g` at (x, y) into $value
+  (func $get_cell (param $ip i32) (param $x i32) (param $y i32) (param $cx i64) (param $cy i64)
+    (result i32)
+    (local $cell i32)
+    (local.set $cell (call $cell (local.get $cx) (local.get $cy)))
+    (if (local.get $cell)
+      (then
+        (global.set $value (i64.load32_u (local.get $cell)))
+        (return (i32.const 1))))
+    (i32.store (local.get $ip) (local.get $x))
+    (i32.store offset=4 (local.get $ip) (local.get $y))
+    (call $out_of_bounds (local.get $ip) (global.get $m_get) (local.get $cx) (local.get $cy))
+    (i32.const 0))
+
+  (func $put_cell (param $ip i32) (param $cx i64) (param $cy i64) (param $v i64) (result i32)
+    (local $cell i32) (local $c i32)
+    (local.set $cell (call $cell (local.get $cx) (local.get $cy)))
+    (if (i32.eqz (local.get $cell))
+      (then
+        (call $out_of_bounds (local.get $ip) (global.get $m_put) (local.get $cx) (local.get $cy))
+        (return (i32.const 0))))
+    (local.set $c (call $to_char (local.get $v)))
+    (if (i32.ne (i32.load (local.get $cell)) (local.get $c))
+      (then
+        (i32.store (local.get $cell) (local.get $c))
+        (call $invalidate (i32.wrap_i64 (local.get $cx)) (i32.wrap_i64 (local.get $cy)))))
+    (i32.const 1))
+
+  ;; a new IP record; the table may move
+  (func $start (param $x i32) (param $y i32) (param $dir i32) (result i32)
+    (local $ip i32) (local $table i32)
+    (if (i32.eq (global.get $ip_count) (global.get $ip_cap))
+      (then
+        (global.set $ip_cap
+          (select (i32.shl (global.get $ip_cap) (i32.const 1)) (i32.const 4) (global.get $ip_cap)))
+        (local.set $table (call $alloc (i32.shl (global.get $ip_cap) (i32.const 5))))
+        (memory.copy (local.get $table) (global.get $ips)
+          (i32.shl (global.get $ip_count) (i32.const 5)))
+        (global.set $ips (local.get $table))))
+    (local.set $ip (call $ip (global.get $ip_count)))
+    (global.set $ip_count (i32.add (global.get $ip_count) (i32.const 1)))
+    (memory.fill (local.get $ip) (i32.const 0) (i32.const 32))
+    (i32.store (local.get $ip) (local.get $x))
+    (i32.store offset=4 (local.get $ip) (local.get $y))
+    (i32.store offset=8 (local.get $ip) (local.get $dir))
+    (i32.store offset=16 (local.get $ip) (i32.const 1))
+    (local.get $ip))
+
+  ;; `t`: the new IP copies the stack and moves once; bad deltas keep the parent's direction
+  (func $spawn (param $parent i32) (param $dx i64) (param $dy i64)
+    (local $from i32) (local $dir i32) (local $child i32) (local $len i32)
+    (local.set $from (call $ip (local.get $parent)))
+    (local.set $dir (i32.load offset=8 (local.get $from)))
+    (if (i64.eqz (local.get $dy))
+      (then
+        (if (i64.eq (local.get $dx) (i64.const 1)) (then (local.set $dir (i32.const 0))))
+        (if (i64.eq (local.get $dx) (i64.const -1)) (then (local.set $dir (i32.const 1))))))
+    (if (i64.eqz (local.get $dx))
+      (then
+        (if (i64.eq (local.get $dy) (i64.const -1)) (then (local.set $dir (i32.const 2))))
+        (if (i64.eq (local.get $dy) (i64.const 1)) (then (local.set $dir (i32.const 3))))))
+    (local.set $child
+      (call $start (i32.load (local.get $from)) (i32.load offset=4 (local.get $from)) (local.get $dir)))
+    (local.set $from (call $ip (local.get $parent)))
+    (i32.store offset=12 (local.get $child) (i32.load offset=12 (local.get $from)))
+    (local.set $len (i32.load offset=24 (local.get $from)))
+    (if (local.get $len)
+      (then
+        (i32.store offset=20 (local.get $child) (call $alloc (i32.shl (local.get $len) (i32.const 3))))
+        (memory.copy (i32.load offset=20 (local.get $child)) (i32.load offset=20 (local.get $from))
+          (i32.shl (local.get $len) (i32.const 3)))
+        (i32.store offset=24 (local.get $child) (local.get $len))
+        (i32.store offset=28 (local.get $child) (local.get $len))))
+    (call $move (local.get $child)))
+
+  ;; the interpreter's step: fetch, execute, move
+  (func $step (param $i i32)
+    (local $ip i32) (local $x i32) (local $y i32) (local $c i32)
+    (local $a i64) (local $b i64) (local $v i64)
+    (local.set $ip (call $ip (local.get $i)))
+    (local.set $x (i32.load (local.get $ip)))
+    (local.set $y (i32.load offset=4 (local.get $ip)))
+    ;; Funge-Space outside the program is empty
+    (local.set $c
+      (if (result i32)
+        (i32.or (i32.ge_u (local.get $x) (global.get $width))
+                (i32.ge_u (local.get $y) (global.get $height)))
+        (then (i32.const 32))
+        (else
+          (i32.load
+            (i32.add (i32.const 8192)
+              (i32.shl (i32.add (i32.mul (local.get $y) (global.get $width)) (local.get $x))
+                (i32.const 2)))))))
+    (if (i32.load offset=12 (local.get $ip))
+      (then
+        (if (i32.eq (local.get $c) (i32.const 34))
+          (then (i32.store offset=12 (local.get $ip) (i32.const 0)))
+          (else (call $push (local.get $ip) (i64.extend_i32_u (local.get $c)))))
+        (call $move (local.get $ip))
+        (return)))
+    (block $done
+      ;; 0-9
+      (if (i32.lt_u (i32.sub (local.get $c) (i32.const 48)) (i32.const 10))
+        (then
+          (call $push (local.get $ip) (i64.extend_i32_u (i32.sub (local.get $c) (i32.const 48))))
+          (br $done)))
+      ;; + - * / % ` pop a, then b
+      (if (i32.or (i32.or (i32.eq (local.get $c) (i32.const 43)) (i32.eq (local.get $c) (i32.const 45)))
+            (i32.or (i32.or (i32.eq (local.get $c) (i32.const 42)) (i32.eq (local.get $c) (i32.const 47)))
+              (i32.or (i32.eq (local.get $c) (i32.const 37)) (i32.eq (local.get $c) (i32.const 96)))))
+        (then
+          (local.set $a (call $pop (local.get $ip)))
+          (local.set $b (call $pop (local.get $ip)))
+          (if (i32.eq (local.get $c) (i32.const 43))
+            (then (local.set $v (i64.add (local.get $b) (local.get $a)))))
+          (if (i32.eq (local.get $c) (i32.const 45))
+            (then (local.set $v (i64.sub (local.get $b) (local.get $a)))))
+          (if (i32.eq (local.get $c) (i32.const 42))
+            (then (local.set $v (i64.mul (local.get $b) (local.get $a)))))
+          (if (i32.eq (local.get $c) (i32.const 96))
+            (then (local.set $v (i64.extend_i32_u (i64.gt_u (local.get $b) (local.get $a))))))
+          (if (i32.or (i32.eq (local.get $c) (i32.const 47)) (i32.eq (local.get $c) (i32.const 37)))
+            (then
+              (local.set $v
+                (call $divide (local.get $b) (local.get $a)
+                  (i32.eq (local.get $c) (i32.const 37)) (local.get $x) (local.get $y)))))
+          (call $push (local.get $ip) (local.get $v))
+          (br $done)))
+      ;; !
+      (if (i32.eq (local.get $c) (i32.const 33))
+        (then
+          (call $push (local.get $ip) (i64.extend_i32_u (i64.eqz (call $pop (local.get $ip)))))
+          (br $done)))
+      ;; :
+      (if (i32.eq (local.get $c) (i32.const 58))
+        (then
+          (local.set $a (call $pop (local.get $ip)))
+          (call $push (local.get $ip) (local.get $a))
+          (call $push (local.get $ip) (local.get $a))
+          (br $done)))
+      ;; \
+      (if (i32.eq (local.get $c) (i32.const 92))
+        (then
+          (local.set $a (call $pop (local.get $ip)))
+          (local.set $b (call $pop (local.get $ip)))
+          (call $push (local.get $ip) (local.get $a))
+          (call $push (local.get $ip) (local.get $b))
+          (br $done)))
+      ;; $
+      (if (i32.eq (local.get $c) (i32.const 36))
+        (then (drop (call $pop (local.get $ip))) (br $done)))
+      ;; .
+      (if (i32.eq (local.get $c) (i32.const 46))
+        (then (call $print_number (call $pop (local.get $ip))) (br $done)))
+      ;; ,
+      (if (i32.eq (local.get $c) (i32.const 44))
+        (then (call $print_char (call $pop (local.get $ip))) (br $done)))
+      ;; > < ^ v
+      (if (i32.eq (local.get $c) (i32.const 62))
+        (then (i32.store offset=8 (local.get $ip) (i32.const 0)) (br $done)))
+      (if (i32.eq (local.get $c) (i32.const 60))
+        (then (i32.store offset=8 (local.get $ip) (i32.const 1)) (br $done)))
+      (if (i32.eq (local.get $c) (i32.const 94))
+        (then (i32.store offset=8 (local.get $ip) (i32.const 2)) (br $done)))
+      (if (i32.eq (local.get $c) (i32.const 118))
+        (then (i32.store offset=8 (local.get $ip) (i32.const 3)) (br $done)))
+      ;; _ |
+      (if (i32.eq (local.get $c) (i32.const 95))
+        (then
+          (i32.store offset=8 (local.get $ip)
+            (select (i32.const 1) (i32.const 0) (i64.ne (call $pop (local.get $ip)) (i64.const 0))))
+          (br $done)))
+      (if (i32.eq (local.get $c) (i32.const 124))
+        (then
+          (i32.store offset=8 (local.get $ip)
+            (select (i32.const 2) (i32.const 3) (i64.ne (call $pop (local.get $ip)) (i64.const 0))))
+          (br $done)))
+      ;; "
+      (if (i32.eq (local.get $c) (i32.const 34))
+        (then (i32.store offset=12 (local.get $ip) (i32.const 1)) (br $done)))
+      ;; #
+      (if (i32.eq (local.get $c) (i32.const 35))
+        (then (call $move (local.get $ip)) (br $done)))
+      ;; @
+      (if (i32.eq (local.get $c) (i32.const 64))
+        (then (i32.store offset=16 (local.get $ip) (i32.const 0)) (return)))
+      ;; & ~
+      (if (i32.eq (local.get $c) (i32.const 38))
+        (then
+          (if (i32.eqz (call $read_number (local.get $ip) (local.get $x) (local.get $y))) (then (return)))
+          (call $push (local.get $ip) (global.get $value))
+          (br $done)))
+      (if (i32.eq (local.get $c) (i32.const 126))
+        (then
+          (if (i32.eqz (call $read_char (local.get $ip) (local.get $x) (local.get $y))) (then (return)))
+          (call $push (local.get $ip) (global.get $value))
+          (br $done)))
+      ;; g p
+      (if (i32.eq (local.get $c) (i32.const 103))
+        (then
+          (local.set $b (call $pop (local.get $ip)))
+          (local.set $a (call $pop (local.get $ip)))
+          (if (i32.eqz
+                (call $get_cell (local.get $ip) (local.get $x) (local.get $y) (local.get $a) (local.get $b)))
+            (then (return)))
+          (call $push (local.get $ip) (global.get $value))
+          (br $done)))
+      (if (i32.eq (local.get $c) (i32.const 112))
+        (then
+          (local.set $b (call $pop (local.get $ip)))
+          (local.set $a (call $pop (local.get $ip)))
+          (local.set $v (call $pop (local.get $ip)))
+          (if (i32.eqz (call $put_cell (local.get $ip) (local.get $a) (local.get $b) (local.get $v)))
+            (then (return)))
+          (br $done)))
+      ;; t
+      (if (i32.eq (local.get $c) (i32.const 116))
+        (then
+          (local.set $b (call $pop (local.get $ip)))
+          (local.set $a (call $pop (local.get $ip)))
+          (call $spawn (local.get $i) (local.get $a) (local.get $b)))))
+    (call $move (call $ip (local.get $i))))
+
+  ;; runs the IPs in turn until all have ended
+  (func $main (export "_start")
+    (local $i i32) (local $ip i32) (local $segment i32) (local $live i32)
+    (call $start_ips)
+    (block $finished
+      (loop $round
+        (br_if $finished (i32.eqz (global.get $ip_count)))
+        ;; IPs spawned on the way run in the same turn
+        (local.set $i (i32.const 0))
+        (block $ran
+          (loop $each
+            (br_if $ran (i32.ge_u (local.get $i) (global.get $ip_count)))
+            (local.set $ip (call $ip (local.get $i)))
+            (if (i32.load offset=16 (local.get $ip))
+              (then
+                (local.set $segment (call $segment_at (local.get $ip)))
+                (if (i32.ge_s (local.get $segment) (i32.const 0))
+                  (then
+                    (if (i32.load8_u (i32.add (global.get $valid) (local.get $segment)))
+                      (then (call_indirect (type $segment) (local.get $ip) (local.get $segment)))
+                      (else (call $step (local.get $i)))))
+                  (else (call $step (local.get $i))))))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br $each)))
+        ;; keep the IPs still alive
+        (local.set $live (i32.const 0))
+        (local.set $i (i32.const 0))
+        (block $kept
+          (loop $keep
+            (br_if $kept (i32.ge_u (local.get $i) (global.get $ip_count)))
+            (local.set $ip (call $ip (local.get $i)))
+            (if (i32.load offset=16 (local.get $ip))
+              (then
+                (memory.copy (call $ip (local.get $live)) (local.get $ip) (i32.const 32))
+                (local.set $live (i32.add (local.get $live) (i32.const 1)))))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br $keep)))
+        (global.set $ip_count (local.get $live))
+        (br $round)))
+    (call $flush))
+
+  (func $segment_at (param $ip i32) (result i32)
+    (local $x i32) (local $y i32)
+    (local.set $x (i32.load (local.get $ip)))
+    (local.set $y (i32.load offset=4 (local.get $ip)))
+    (if (i32.or (i32.ge_u (local.get $x) (global.get $width))
+                (i32.ge_u (local.get $y) (global.get $height)))
+      (then (return (i32.const -1))))
+    ;; the index holds segment + 1 per IP state
+    (i32.sub
+      (i32.load
+        (i32.add (global.get $index)
+          (i32.shl
+            (i32.add
+              (i32.shl
+                (i32.add
+                  (i32.shl (i32.add (i32.mul (local.get $y) (global.get $width)) (local.get $x))
+                    (i32.const 2))
+                  (i32.load offset=8 (local.get $ip)))
+                (i32.const 1))
+              (i32.load offset=12 (local.get $ip)))
+            (i32.const 2))))
+      (i32.const 1)))
+"##;
+
+/// a WAT string of `bytes`
+fn data(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in bytes {
+        if (0x20..0x7F).contains(&b) && b != b'"' && b != b'\\' {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("\\{:02x}", b));
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn align(value: usize, to: usize) -> usize {
+    value.div_ceil(to) * to
+}
+
+struct WatSyntax;
+
+impl Syntax for WatSyntax {
+    fn literal(&self, value: usize) -> String {
+        format!("(i64.const {})", value)
+    }
+
+    fn variable(&self, name: &str) -> String {
+        format!("(local.get ${})", name)
+    }
+
+    fn local(&self, name: &str, init: &str) -> String {
+        format!("(local.set ${} {})", name, init)
+    }
+
+    fn pop(&self) -> String {
+        "(call $pop (local.get $ip))".to_string()
+    }
+
+    fn binary(&self, op: char, b: &str, a: &str, at: &State) -> String {
+        match op {
+            '+' => format!("(i64.add {} {})", b, a),
+            '-' => format!("(i64.sub {} {})", b, a),
+            '*' => format!("(i64.mul {} {})", b, a),
+            '`' => format!("(i64.extend_i32_u (i64.gt_u {} {}))", b, a),
+            _ => format!(
+                "(call $divide {} {} (i32.const {}) (i32.const {}) (i32.const {}))",
+                b,
+                a,
+                (op == '%') as u8,
+                at.x,
+                at.y
+            ),
+        }
+    }
+
+    fn not(&self, a: &str) -> String {
+        format!("(i64.extend_i32_u (i64.eqz {}))", a)
+    }
+
+    /// values are locals or constants, dropping them needs no code
+    fn discard(&self, _value: &str) -> Option<String> {
+        None
+    }
+
+    fn print(&self, op: char, a: &str) -> String {
+        if op == '.' {
+            format!("(call $print_number {})", a)
+        } else {
+            format!("(call $print_char {})", a)
+        }
+    }
+
+    fn read(&self, op: char, name: &str, at: &State) -> Vec<String> {
+        let read = if op == '&' {
+            "read_number"
+        } else {
+            "read_char"
+        };
+        vec![
+            format!(
+                "(if (i32.eqz (call ${} (local.get $ip) (i32.const {}) (i32.const {}))) (then (return)))",
+                read, at.x, at.y
+            ),
+            self.local(name, "(global.get $value)"),
+        ]
+    }
+
+    fn get(&self, x: &str, y: &str, name: &str, at: &State) -> Vec<String> {
+        vec![
+            format!(
+                "(if (i32.eqz (call $get_cell (local.get $ip) (i32.const {}) (i32.const {}) {} {})) (then (return)))",
+                at.x, at.y, x, y
+            ),
+            self.local(name, "(global.get $value)"),
+        ]
+    }
+
+    fn push(&self, value: &str) -> String {
+        format!("(call $push (local.get $ip) {})", value)
+    }
+
+    fn jump(&self, last: &State) -> String {
+        format!(
+            "(call $jump (local.get $ip) (i32.const {}) (i32.const {}) (i32.const {}) (i32.const {}))",
+            last.x,
+            last.y,
+            direction_index(last.direction),
+            last.string_mode as u8
+        )
+    }
+}
+
+/// text safe inside a WAT line comment
+fn comment(text: &str) -> String {
+    text.replace(['\n', '\r'], " ")
+}
+
+fn segment_function(cfg: &ControlFlowGraph, index: usize, segment: &Segment) -> String {
+    let code: String = segment
+        .body
+        .iter()
+        .map(|s| cfg.cell(s.x, s.y))
+        .filter(|c| *c != ' ')
+        .collect();
+    let entry = segment.entry();
+    let body = straight_line(&WatSyntax, cfg, segment);
+    let mut out = format!(
+        "  ;; ({}, {}) {}: {}\n  (func $segment_{} (type $segment) (param $ip i32)",
+        entry.x,
+        entry.y,
+        entry.direction,
+        comment(&format!("{:?}", code)),
+        index
+    );
+    for local in 0..body.locals {
+        out.push_str(&format!(" (local $v{} i64)", local));
+    }
+    out.push('\n');
+    for line in body.lines {
+        out.push_str(&format!("    {}\n", line));
+    }
+    out.truncate(out.len() - 1);
+    out.push_str(")\n\n");
+    out
+}
+
+pub fn compile(grid: &[Vec<char>], name: &str) -> String {
+    let cfg = ControlFlowGraph::build(grid);
+    let runtime = runtime_grid(grid);
+    let segments = segments(&cfg);
+    let (width, height) = (cfg.width, cfg.height);
+
+    // layout: buffers and messages, the grid, segment flags, the segment index, the heap
+    let valid = GRID + width * height * 4;
+    let index = align(valid + segments.len(), 4);
+    let heap = align(index + width * height * 8 * 4, 8);
+
+    let mut out = format!(
+        ";; {}, compiled by befunge_interpreter\n\
+         ;; a WASI command: `wasmtime prog.wat < input.txt`\n\
+         (module\n",
+        comment(name)
+    );
+    for (function, signature) in [
+        ("fd_write", "(param i32 i32 i32 i32) (result i32)"),
+        ("fd_read", "(param i32 i32 i32 i32) (result i32)"),
+        ("proc_exit", "(param i32)"),
+    ] {
+        out.push_str(&format!(
+            "  (import \"wasi_snapshot_preview1\" \"{}\" (func ${} {}))\n",
+            function, function, signature
+        ));
+    }
+    out.push_str(&format!(
+        "\n  (memory (export \"memory\") {})\n",
+        heap / 65536 + 1
+    ));
+    out.push_str(&format!(
+        "  (global $width i32 (i32.const {}))\n  (global $height i32 (i32.const {}))\n",
+        width, height
+    ));
+    out.push_str(&format!(
+        "  (global $valid i32 (i32.const {}))\n  (global $index i32 (i32.const {}))\n",
+        valid, index
+    ));
+    out.push_str(&format!(
+        "  (global $heap (mut i32) (i32.const {}))\n",
+        heap
+    ));
+    out.push_str(
+        "  (global $ips (mut i32) (i32.const 0))\n\
+         \x20 (global $ip_count (mut i32) (i32.const 0))\n\
+         \x20 (global $ip_cap (mut i32) (i32.const 0))\n\
+         \x20 (global $out_len (mut i32) (i32.const 0))\n\
+         \x20 (global $division_warned (mut i32) (i32.const 0))\n\
+         \x20 ;; what `&`, `~` and `g` read\n\
+         \x20 (global $value (mut i64) (i64.const 0))\n\n",
+    );
+
+    let mut at = MESSAGES_AT;
+    for (label, text) in MESSAGES {
+        let mut bytes = (text.len() as u32).to_le_bytes().to_vec();
+        bytes.extend(text.as_bytes());
+        out.push_str(&format!(
+            "  (global $m_{} i32 (i32.const {}))\n  (data (i32.const {}) {})\n",
+            label,
+            at,
+            at,
+            data(&bytes)
+        ));
+        at = align(at + bytes.len(), 4);
+    }
+
+    // the grid as i32 code points, for `p` and `g` and for cells run one at a time
+    out.push_str("\n  ;; the grid\n");
+    for (y, row) in runtime.iter().enumerate() {
+        let bytes: Vec<u8> = row.iter().flat_map(|&c| (c as u32).to_le_bytes()).collect();
+        out.push_str(&format!(
+            "  (data (i32.const {}) {})\n",
+            GRID + y * width * 4,
+            data(&bytes)
+        ));
+    }
+    out.push_str(&format!(
+        "  (data (i32.const {}) {})\n",
+        valid,
+        data(&vec![1; segments.len()])
+    ));
+    out.push_str("  ;; segment_at\n");
+    for (number, segment) in segments.iter().enumerate() {
+        out.push_str(&format!(
+            "  (data (i32.const {}) {})\n",
+            index + state_key(&segment.entry(), width) * 4,
+            data(&(number as u32 + 1).to_le_bytes())
+        ));
+    }
+    out.push_str(RUNTIME);
+
+    // segments are dropped as soon as `p` changes one of their cells
+    let cells = segment_cells(&segments);
+    out.push_str(
+        "\n  (func $invalidate (param $x i32) (param $y i32)\n    (local $cell i32)\n    \
+         (local.set $cell (i32.add (i32.mul (local.get $y) (global.get $width)) (local.get $x)))",
+    );
+    for ((y, x), owners) in &cells {
+        let drops: Vec<String> = owners
+            .iter()
+            .map(|number| format!("(i32.store8 (i32.const {}) (i32.const 0))", valid + number))
+            .collect();
+        out.push_str(&format!(
+            "\n    (if (i32.eq (local.get $cell) (i32.const {})) (then {}))",
+            y * width + x,
+            drops.join(" ")
+        ));
+    }
+    out.push_str(")\n\n");
+
+    for (number, segment) in segments.iter().enumerate() {
+        out.push_str(&segment_function(&cfg, number, segment));
+    }
+    let functions: Vec<String> = (0..segments.len())
+        .map(|number| format!("$segment_{}", number))
+        .collect();
+    out.push_str(&format!(
+        "  (table funcref (elem {}))\n\n",
+        functions.join(" ")
+    ));
+
+    out.push_str("  (func $start_ips");
+    for start in &cfg.starts {
+        out.push_str(&format!(
+            "\n    (drop (call $start (i32.const {}) (i32.const {}) (i32.const {})))",
+            start.x,
+            start.y,
+            direction_index(start.direction)
+        ));
+    }
+    out.push_str(")\n)\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::tests::{assert_matches_interpreter, run_command};
+    use crate::engine::testing::grid;
+    use std::process::Command;
+    use std::{env, fs};
+
+    /// parentheses outside strings and comments
+    fn balanced(wat: &str) -> bool {
+        let mut depth = 0i64;
+        for line in wat.lines() {
+            let mut in_string = false;
+            let mut escaped = false;
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                if in_string {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => in_string = false,
+                        _ => {}
+                    }
+                    continue;
+                }
+                match c {
+                    '"' => in_string = true,
+                    ';' if chars.peek() == Some(&';') => break,
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                if depth < 0 {
+                    return false;
+                }
+            }
+        }
+        depth == 0
+    }
+
+    #[test]
+    fn test_module_structure() {
+        // `p` rewrites (3, 0) inside the first segment
+        let wat = compile(&grid(&["\"@\"30p  1.@"]), "prog.bf");
+        assert!(wat.starts_with(";; prog.bf, compiled by befunge_interpreter\n"));
+        assert!(balanced(&wat), "unbalanced parentheses");
+        for expected in [
+            "(import \"wasi_snapshot_preview1\" \"fd_write\" (func $fd_write",
+            "(import \"wasi_snapshot_preview1\" \"fd_read\" (func $fd_read",
+            "(memory (export \"memory\") 1)",
+            "(func $main (export \"_start\")",
+            // the grid as little-endian code points from 8192
+            "(data (i32.const 8192) \"\\22\\00\\00\\00@\\00\\00\\00\\22\\00\\00\\00",
+            // `"@"30` kept on a local stack and flushed before the `p`
+            "(func $segment_0 (type $segment) (param $ip i32)\n    \
+             (call $push (local.get $ip) (i64.const 64))\n    \
+             (call $push (local.get $ip) (i64.const 3))\n    \
+             (call $push (local.get $ip) (i64.const 0))\n    \
+             (call $jump (local.get $ip) (i32.const 5) (i32.const 0) (i32.const 0) (i32.const 0)))",
+            "(func $segment_1 (type $segment) (param $ip i32)\n    \
+             (call $print_number (i64.const 1))",
+            "(table funcref (elem $segment_0 $segment_1))",
+            "(drop (call $start (i32.const 0) (i32.const 0) (i32.const 0)))",
+        ] {
+            assert!(wat.contains(expected), "missing {:?} in\n{}", expected, wat);
+        }
+        // the `p` target drops the segment running through it
+        assert!(wat.contains("(if (i32.eq (local.get $cell) (i32.const 3)) (then (i32.store8"));
+    }
+
+    /// run with a local `wasmtime`; `None` without one
+    fn compile_and_run(grid: &[Vec<char>], input: &str, name: &str) -> Option<String> {
+        Command::new("wasmtime").arg("--version").output().ok()?;
+        let dir = env::temp_dir().join(format!("befunge-wat-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("prog.wat");
+        fs::write(&source, compile(grid, name)).unwrap();
+        let mut command = Command::new("wasmtime");
+        command.arg(&source);
+        let output = run_command(command, input);
+        fs::remove_dir_all(&dir).ok();
+        Some(output)
+    }
+
+    #[test]
+    fn test_compiled_output_matches_interpreter() {
+        assert_matches_interpreter(compile_and_run);
+    }
+}
@@ -1,6 +1,6 @@
 // src/usecases/commands/add_command.rs
 
-use super::command::{Command, CommandGrid};
+use super::command::{Command, CommandGrid, StackEffect};
 use crate::interfaces::IOHandle;
 use crate::{entities::IPState, errors::InterpreterError};
 use std::sync::{Arc, Mutex};
@@ -8,14 +8,18 @@ use std::sync::{Arc, Mutex};
 pub struct AddCommand;
 
 impl Command for AddCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(2, 1)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
         interpreter: &dyn CommandGrid,
-        _io_handler: Arc<dyn IOHandle + Send + Sync>,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
-        let a = interpreter.pop(ip.clone())? as isize;
-        let b = interpreter.pop(ip.clone())? as isize;
+        let a = interpreter.pop(ip.clone(), io_handler.clone())? as isize;
+        let b = interpreter.pop(ip.clone(), io_handler.clone())? as isize;
         let mut ip_locked = ip
             .lock()
             .map_err(|_| InterpreterError::LockError("Failed to lock IPState".to_string()))?;
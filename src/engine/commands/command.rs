@@ -1,11 +1,31 @@
 // src/usecases/commands/command.rs
 
+use crate::engine::semantics::Semantics;
 use crate::entities::ip_state::IPState;
-use crate::errors::InterpreterError;
+use crate::entities::{Dimensions, Position};
+use crate::errors::{InterpreterError, RuntimeErrorKind};
 use crate::interfaces::IOHandle;
 use std::sync::{Arc, Mutex};
 
+/// What an instruction takes from and leaves on the stack, for tools that read programs
+/// without running them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackEffect {
+    pub pops: usize,
+    pub pushes: usize,
+}
+
+impl StackEffect {
+    pub const fn new(pops: usize, pushes: usize) -> Self {
+        Self { pops, pushes }
+    }
+}
+
 pub trait Command {
+    /// movement and no-op commands leave the stack alone
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(0, 0)
+    }
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
@@ -15,7 +35,31 @@ pub trait Command {
 }
 
 pub trait CommandGrid {
-    fn pop(&self, ip: Arc<Mutex<IPState>>) -> Result<usize, InterpreterError>;
+    /// pop a value, applying the stack underflow policy on an empty stack
+    fn pop(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<usize, InterpreterError>;
+    fn semantics(&self) -> &Semantics;
+
+    /// pop the coordinates of a cell, last coordinate on top
+    fn pop_position(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        dimensions: Dimensions,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<Position, InterpreterError> {
+        let mut at = Position::default();
+        if dimensions == Dimensions::Trefunge {
+            at.z = self.pop(ip.clone(), io_handler.clone())?;
+        }
+        if dimensions != Dimensions::Unefunge {
+            at.y = self.pop(ip.clone(), io_handler.clone())?;
+        }
+        at.x = self.pop(ip, io_handler)?;
+        Ok(at)
+    }
     fn move_ip(&self, ip: Arc<Mutex<IPState>>) -> Result<(), InterpreterError>;
 
     fn add_ip(
@@ -24,12 +68,39 @@ pub trait CommandGrid {
         io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError>;
 
+    // lets callers get the concrete grid back; nothing in the crate downcasts yet
+    #[allow(dead_code)]
     fn as_any(&self) -> &dyn std::any::Any;
     fn run_ip(
         self: Arc<Self>,
         ip: Arc<Mutex<IPState>>,
         io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError>;
-    fn set_value(&self, x: usize, y: usize, value: char) -> Result<(), InterpreterError>;
-    fn get_value(&self, x: usize, y: usize) -> Result<char, InterpreterError>;
+    fn set_value(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        at: Position,
+        value: char,
+    ) -> Result<(), InterpreterError>;
+    fn get_value(&self, ip: Arc<Mutex<IPState>>, at: Position) -> Result<char, InterpreterError>;
+
+    /// build a runtime error carrying the current state of `ip`
+    ///
+    /// reads the instruction without `get_value`, whose errors are built here
+    fn runtime_error(&self, ip: Arc<Mutex<IPState>>, kind: RuntimeErrorKind) -> InterpreterError;
+
+    /// report a non-fatal runtime problem at `ip`
+    fn warn(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        kind: RuntimeErrorKind,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<(), InterpreterError> {
+        match self.runtime_error(ip, kind) {
+            InterpreterError::Runtime(error) => {
+                io_handler.write_error(&format!("warning: {}", error))
+            }
+            other => Err(other),
+        }
+    }
 }
@@ -1,6 +1,6 @@
 // src/usecases/commands/digit_command.rs
 
-use super::command::Command;
+use super::command::{Command, StackEffect};
 use crate::engine::commands::command::CommandGrid;
 use crate::entities::ip_state::IPState;
 use crate::errors::InterpreterError;
@@ -18,6 +18,10 @@ impl DigitCommand {
 }
 
 impl Command for DigitCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(0, 1)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
@@ -1,6 +1,6 @@
 // src/usecases/commands/divide_command.rs
 
-use super::command::Command;
+use super::command::{Command, StackEffect};
 use crate::engine::commands::command::CommandGrid;
 use crate::entities::ip_state::IPState;
 use crate::errors::InterpreterError;
@@ -10,25 +10,30 @@ use std::sync::{Arc, Mutex};
 pub struct DivideCommand;
 
 impl Command for DivideCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(2, 1)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
         interpreter: &dyn CommandGrid,
         io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
-        let a = interpreter.pop(ip.clone())? as isize;
-        let b = interpreter.pop(ip.clone())? as isize;
-        if a == 0 {
-            io_handler.write_error("Division by zero.")?;
-            let mut ip_locked = ip
-                .lock()
-                .map_err(|_| InterpreterError::LockError("Failed to lock IPState".to_string()))?;
-            ip_locked.stk.push(0);
+        let a = interpreter.pop(ip.clone(), io_handler.clone())? as isize;
+        let b = interpreter.pop(ip.clone(), io_handler.clone())? as isize;
+        let result = if a == 0 {
+            interpreter
+                .semantics()
+                .on_division_by_zero(ip.clone(), interpreter, io_handler)?
         } else {
+            Some((b / a) as usize)
+        };
+        if let Some(value) = result {
             let mut ip_locked = ip
                 .lock()
                 .map_err(|_| InterpreterError::LockError("Failed to lock IPState".to_string()))?;
-            ip_locked.stk.push((b / a) as usize);
+            ip_locked.stk.push(value);
         }
         Ok(())
     }
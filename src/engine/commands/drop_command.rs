@@ -1,6 +1,6 @@
 // src/usecases/commands/drop_command.rs
 
-use super::command::Command;
+use super::command::{Command, StackEffect};
 use crate::engine::commands::command::CommandGrid;
 use crate::entities::ip_state::IPState;
 use crate::errors::InterpreterError;
@@ -10,16 +10,17 @@ use std::sync::{Arc, Mutex};
 pub struct DropCommand;
 
 impl Command for DropCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(1, 0)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
-        _interpreter: &dyn CommandGrid,
-        _io_handler: Arc<dyn IOHandle + Send + Sync>,
+        interpreter: &dyn CommandGrid,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
-        let mut ip_locked = ip
-            .lock()
-            .map_err(|_| InterpreterError::LockError("Failed to lock IPState".to_string()))?;
-        ip_locked.stk.pop();
+        interpreter.pop(ip, io_handler)?;
         Ok(())
     }
 }
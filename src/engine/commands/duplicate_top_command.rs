@@ -1,6 +1,6 @@
 // src/usecases/commands/duplicate_top_command.rs
 
-use super::command::Command;
+use super::command::{Command, StackEffect};
 use crate::engine::commands::command::CommandGrid;
 use crate::entities::ip_state::IPState;
 use crate::errors::InterpreterError;
@@ -10,21 +10,22 @@ use std::sync::{Arc, Mutex};
 pub struct DuplicateTopCommand;
 
 impl Command for DuplicateTopCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(1, 2)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
-        _interpreter: &dyn CommandGrid,
-        _io_handler: Arc<dyn IOHandle + Send + Sync>,
+        interpreter: &dyn CommandGrid,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
+        let value = interpreter.pop(ip.clone(), io_handler)?;
         let mut ip_locked = ip
             .lock()
             .map_err(|_| InterpreterError::LockError("Failed to lock IPState".to_string()))?;
-        if let Some(&value) = ip_locked.stk.last() {
-            ip_locked.stk.push(value);
-        } else {
-            ip_locked.stk.push(0);
-            ip_locked.stk.push(0);
-        }
+        ip_locked.stk.push(value);
+        ip_locked.stk.push(value);
         Ok(())
     }
 }
@@ -1,26 +1,39 @@
-// src/usecases/commands/logical_not_command.rs
+// src/usecases/commands/get_command.rs
 
-use super::command::Command;
+use super::command::{Command, StackEffect};
 use crate::engine::commands::command::CommandGrid;
 use crate::entities::ip_state::IPState;
+use crate::entities::Dimensions;
 use crate::errors::InterpreterError;
 use crate::interfaces::IOHandle;
 use std::sync::{Arc, Mutex};
 
-pub struct GetCommand;
+/// `g` pops one coordinate per dimension
+pub struct GetCommand {
+    dimensions: Dimensions,
+}
+
+impl GetCommand {
+    pub fn new(dimensions: Dimensions) -> Self {
+        Self { dimensions }
+    }
+}
 
 impl Command for GetCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(self.dimensions.count(), 1)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
         interpreter: &dyn CommandGrid,
-        _io_handler: Arc<dyn IOHandle + Send + Sync>,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
-        let y = interpreter.pop(ip.clone())?;
-        let x = interpreter.pop(ip.clone())?;
+        let at = interpreter.pop_position(ip.clone(), self.dimensions, io_handler)?;
 
         // 指定位置の値を取得してスタックにプッシュ
-        let value = interpreter.get_value(x, y)?;
+        let value = interpreter.get_value(ip.clone(), at)?;
         let mut ip_locked = ip
             .lock()
             .map_err(|_| InterpreterError::ThreadError("Failed to lock IPState".to_string()))?;
@@ -1,4 +1,4 @@
-use super::command::{Command, CommandGrid};
+use super::command::{Command, CommandGrid, StackEffect};
 use crate::entities::ip_state::IPState;
 use crate::errors::InterpreterError;
 use crate::interfaces::IOHandle;
@@ -7,17 +7,21 @@ use std::sync::{Arc, Mutex};
 pub struct GraterThanCommand;
 
 impl Command for GraterThanCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(2, 1)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
-        _interpreter: &dyn CommandGrid,
-        _io_handler: Arc<dyn IOHandle + Send + Sync>,
+        interpreter: &dyn CommandGrid,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
+        let a = interpreter.pop(ip.clone(), io_handler.clone())?;
+        let b = interpreter.pop(ip.clone(), io_handler)?;
         let mut ip_locked = ip
             .lock()
             .map_err(|_| InterpreterError::ThreadError("Failed to lock IPState".to_string()))?;
-        let a = ip_locked.stk.pop();
-        let b = ip_locked.stk.pop();
         ip_locked.stk.push(if b > a { 1 } else { 0 });
         Ok(())
     }
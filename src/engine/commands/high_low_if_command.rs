@@ -0,0 +1,34 @@
+// src/usecases/commands/high_low_if_command.rs
+
+use super::command::{Command, StackEffect};
+use crate::engine::commands::command::CommandGrid;
+use crate::entities::{ip_state::IPState, Direction};
+use crate::errors::InterpreterError;
+use crate::interfaces::IOHandle;
+use std::sync::{Arc, Mutex};
+
+pub struct HighLowIfCommand;
+
+impl Command for HighLowIfCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(1, 0)
+    }
+
+    fn execute(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        interpreter: &dyn CommandGrid,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<(), InterpreterError> {
+        let a = interpreter.pop(ip.clone(), io_handler.clone())?;
+        let mut ip_locked = ip
+            .lock()
+            .map_err(|_| InterpreterError::LockError("Failed to lock IPState".to_string()))?;
+        if a == 0 {
+            ip_locked.direction = Direction::Low
+        } else {
+            ip_locked.direction = Direction::High
+        };
+        Ok(())
+    }
+}
@@ -9,12 +9,16 @@ pub mod drop_command;
 pub mod duplicate_top_command;
 pub mod get_command;
 pub mod greater_than_command;
+pub mod high_command;
+pub mod high_low_if_command;
 pub mod horizontal_if_command;
 pub mod left_command;
 pub mod logical_not_command;
+pub mod low_command;
 pub mod modulo_command;
 pub mod move_command;
 pub mod multiply_command;
+pub mod nop_command;
 pub mod print_char_command;
 pub mod print_number_command;
 pub mod put_command;
@@ -0,0 +1,21 @@
+// src/usecases/commands/nop_command.rs
+
+use super::command::{Command, CommandGrid};
+use crate::entities::ip_state::IPState;
+use crate::errors::InterpreterError;
+use crate::interfaces::IOHandle;
+use std::sync::{Arc, Mutex};
+
+/// Does nothing. Used for blank cells and the initial-direction arrows.
+pub struct NopCommand;
+
+impl Command for NopCommand {
+    fn execute(
+        &self,
+        _ip: Arc<Mutex<IPState>>,
+        _interpreter: &dyn CommandGrid,
+        _io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<(), InterpreterError> {
+        Ok(())
+    }
+}
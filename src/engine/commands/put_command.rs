@@ -1,26 +1,39 @@
 // src/usecases/commands/put_command.rs
 
-use super::command::Command;
+use super::command::{Command, StackEffect};
 use crate::engine::commands::command::CommandGrid;
 use crate::entities::ip_state::IPState;
+use crate::entities::Dimensions;
 use crate::errors::InterpreterError;
 use crate::interfaces::IOHandle;
 use std::sync::{Arc, Mutex};
 
-pub struct PutCommand;
+/// `p` pops one coordinate per dimension
+pub struct PutCommand {
+    dimensions: Dimensions,
+}
+
+impl PutCommand {
+    pub fn new(dimensions: Dimensions) -> Self {
+        Self { dimensions }
+    }
+}
 
 impl Command for PutCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(self.dimensions.count() + 1, 0)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
         interpreter: &dyn CommandGrid,
-        _io_handler: Arc<dyn IOHandle + Send + Sync>,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
-        let y = interpreter.pop(ip.clone())?;
-        let x = interpreter.pop(ip.clone())?;
-        let v = interpreter.pop(ip.clone())? as usize;
+        let at = interpreter.pop_position(ip.clone(), self.dimensions, io_handler.clone())?;
+        let v = interpreter.pop(ip.clone(), io_handler.clone())?;
         let value = std::char::from_u32(v as u32).unwrap_or('\u{FFFD}');
-        interpreter.set_value(x, y, value)?;
+        interpreter.set_value(ip.clone(), at, value)?;
         Ok(())
     }
 }
@@ -1,24 +1,34 @@
-use super::command::{Command, CommandGrid};
+use super::command::{Command, CommandGrid, StackEffect};
 use crate::entities::ip_state::IPState;
-use crate::errors::InterpreterError;
+use crate::errors::{InterpreterError, RuntimeErrorKind};
 use crate::interfaces::IOHandle;
+use std::io::ErrorKind;
 use std::sync::{Arc, Mutex};
 
 pub struct ReadCharacterCommand;
 
 impl Command for ReadCharacterCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(0, 1)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
-        _interpreter: &dyn CommandGrid,
+        interpreter: &dyn CommandGrid,
         io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
+        let value = match io_handler.read_char() {
+            Ok(c) => c,
+            Err(InterpreterError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                return Err(interpreter.runtime_error(ip, RuntimeErrorKind::InputEof));
+            }
+            Err(_) => char::default(),
+        };
         let mut ip_locked = ip
             .lock()
             .map_err(|_| InterpreterError::ThreadError("Failed to lock IPState".to_string()))?;
-        ip_locked
-            .stk
-            .push(io_handler.read_char().unwrap_or_default() as usize);
+        ip_locked.stk.push(value as usize);
         Ok(())
     }
 }
@@ -1,6 +1,6 @@
 // src/usecases/commands/subtract_command.rs
 
-use super::command::Command;
+use super::command::{Command, StackEffect};
 use crate::engine::commands::command::CommandGrid;
 use crate::entities::ip_state::IPState;
 use crate::errors::InterpreterError;
@@ -10,14 +10,18 @@ use std::sync::{Arc, Mutex};
 pub struct SubtractCommand;
 
 impl Command for SubtractCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(2, 1)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
         interpreter: &dyn CommandGrid,
-        _io_handler: Arc<dyn IOHandle + Send + Sync>,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
-        let b = interpreter.pop(ip.clone())? as isize;
-        let a = interpreter.pop(ip.clone())? as isize;
+        let b = interpreter.pop(ip.clone(), io_handler.clone())? as isize;
+        let a = interpreter.pop(ip.clone(), io_handler.clone())? as isize;
         let mut ip_locked = ip
             .lock()
             .map_err(|_| InterpreterError::LockError("Failed to lock IPState".to_string()))?;
@@ -1,6 +1,6 @@
 // src/usecases/commands/swap_command.rs
 
-use super::command::Command;
+use super::command::{Command, StackEffect};
 use crate::engine::commands::command::CommandGrid;
 use crate::entities::ip_state::IPState;
 use crate::errors::InterpreterError;
@@ -10,28 +10,23 @@ use std::sync::{Arc, Mutex};
 pub struct SwapCommand;
 
 impl Command for SwapCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(2, 2)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
-        _interpreter: &dyn CommandGrid,
-        _io_handler: Arc<dyn IOHandle + Send + Sync>,
+        interpreter: &dyn CommandGrid,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
+        let a = interpreter.pop(ip.clone(), io_handler.clone())?;
+        let b = interpreter.pop(ip.clone(), io_handler)?;
         let mut ip_locked = ip
             .lock()
             .map_err(|_| InterpreterError::LockError("Failed to lock IPState".to_string()))?;
-        if ip_locked.stk.len() >= 2 {
-            let a = ip_locked.stk.pop().unwrap();
-            let b = ip_locked.stk.pop().unwrap();
-            ip_locked.stk.push(a);
-            ip_locked.stk.push(b);
-        } else if ip_locked.stk.len() == 1 {
-            let a = ip_locked.stk.pop().unwrap();
-            ip_locked.stk.push(a);
-            ip_locked.stk.push(0);
-        } else {
-            ip_locked.stk.push(0);
-            ip_locked.stk.push(0);
-        }
+        ip_locked.stk.push(a);
+        ip_locked.stk.push(b);
         Ok(())
     }
 }
@@ -1,6 +1,6 @@
 // src/usecases/commands/vertical_if_command.rs
 
-use super::command::Command;
+use super::command::{Command, StackEffect};
 use crate::engine::commands::command::CommandGrid;
 use crate::entities::{ip_state::IPState, Direction};
 use crate::errors::InterpreterError;
@@ -10,13 +10,17 @@ use std::sync::{Arc, Mutex};
 pub struct VerticalIfCommand;
 
 impl Command for VerticalIfCommand {
+    fn stack_effect(&self) -> StackEffect {
+        StackEffect::new(1, 0)
+    }
+
     fn execute(
         &self,
         ip: Arc<Mutex<IPState>>,
         interpreter: &dyn CommandGrid,
-        _io_handler: Arc<dyn IOHandle + Send + Sync>,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
-        let a = interpreter.pop(ip.clone())?;
+        let a = interpreter.pop(ip.clone(), io_handler.clone())?;
         let mut ip_locked = ip
             .lock()
             .map_err(|_| InterpreterError::LockError("Failed to lock IPState".to_string()))?;
@@ -0,0 +1,607 @@
+// src/engine/control.rs
+
+use crate::engine::history::{History, InputEvent};
+use crate::engine::session::Sequencer;
+use crate::errors::{Diagnostic, InterpreterError};
+use crate::interfaces::IOHandle;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// Instructions that may still run while paused.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+enum Budget {
+    #[default]
+    Exhausted,
+    /// `n` instructions of one IP, even a frozen one
+    Ip(usize, usize),
+    /// one instruction of each listed IP (lockstep tick)
+    Tick(BTreeSet<usize>),
+}
+
+#[derive(Debug, Default)]
+struct ControlState {
+    /// IPs stop at the next instruction unless the budget lets them run
+    paused: bool,
+    budget: Budget,
+    /// pause again after the next write to stdout
+    stop_on_output: bool,
+    /// IPs that stay parked even while running
+    frozen: BTreeSet<usize>,
+    /// IP threads that are running or parked
+    live: BTreeSet<usize>,
+    /// IP threads waiting in `checkpoint`
+    parked: BTreeSet<usize>,
+    /// IPs undone out of existence, released so their threads can exit
+    retired: BTreeSet<usize>,
+    /// IP that hit the last breakpoint
+    focus: Option<usize>,
+    /// a debugger keeps the program open after the last IP stops
+    attached: bool,
+    started: bool,
+    finished: bool,
+    /// one instruction at a time, in a recorded or replayed order (sessions only)
+    sequencer: Option<Sequencer>,
+}
+
+impl ControlState {
+    fn may_run(&self, id: usize) -> bool {
+        if self.retired.contains(&id) {
+            return true;
+        }
+        if let Some(sequencer) = &self.sequencer {
+            if !sequencer.allows(id) {
+                return false;
+            }
+        }
+        match &self.budget {
+            Budget::Ip(target, count) => *target == id && *count > 0,
+            Budget::Tick(pending) => pending.contains(&id),
+            Budget::Exhausted => !self.paused && !self.frozen.contains(&id),
+        }
+    }
+
+    fn consume(&mut self, id: usize) {
+        if let Some(sequencer) = &mut self.sequencer {
+            sequencer.begin_turn(id);
+        }
+        match &mut self.budget {
+            Budget::Ip(_, count) => *count -= 1,
+            Budget::Tick(pending) => {
+                pending.remove(&id);
+            }
+            Budget::Exhausted => {}
+        }
+    }
+
+    /// every live IP is parked and none of them may run
+    fn is_stopped(&self) -> bool {
+        self.started
+            && !self.live.is_empty()
+            && self
+                .live
+                .iter()
+                .all(|id| self.parked.contains(id) && !self.may_run(*id))
+    }
+}
+
+/// Start/stop control shared by the IP threads and a debugger.
+///
+/// IP threads call [`ExecutionControl::checkpoint`] before every instruction; the debugger
+/// pauses, steps, freezes and resumes them and waits with [`ExecutionControl::wait_until_stopped`].
+#[derive(Debug, Default)]
+pub struct ExecutionControl {
+    state: Mutex<ControlState>,
+    changed: Condvar,
+}
+
+impl ExecutionControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// let IPs execute one instruction at a time, in the order `sequencer` records or replays
+    pub(crate) fn with_sequencer(sequencer: Sequencer) -> Self {
+        Self {
+            state: Mutex::new(ControlState {
+                sequencer: Some(sequencer),
+                ..ControlState::default()
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, ControlState>, InterpreterError> {
+        self.state
+            .lock()
+            .map_err(|_| InterpreterError::ThreadError("Failed to lock control".to_string()))
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ControlState)) -> Result<(), InterpreterError> {
+        let mut state = self.lock()?;
+        f(&mut state);
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// stop every IP before its next instruction
+    pub fn pause(&self) -> Result<(), InterpreterError> {
+        self.update(|state| {
+            state.paused = true;
+            state.budget = Budget::Exhausted;
+        })
+    }
+
+    /// pause because IP `id` hit a breakpoint
+    pub fn break_at(&self, id: usize) -> Result<(), InterpreterError> {
+        self.update(|state| {
+            state.paused = true;
+            state.budget = Budget::Exhausted;
+            state.focus = Some(id);
+        })
+    }
+
+    /// run every IP that is not frozen until the next breakpoint
+    pub fn resume(&self) -> Result<(), InterpreterError> {
+        self.update(|state| {
+            state.paused = false;
+            state.budget = Budget::Exhausted;
+            state.stop_on_output = false;
+        })
+    }
+
+    /// let IP `id` run `count` more instructions while the others stay parked
+    pub fn step_ip(&self, id: usize, count: usize) -> Result<(), InterpreterError> {
+        self.update(|state| {
+            state.paused = true;
+            state.budget = Budget::Ip(id, count);
+        })
+    }
+
+    /// let every IP that is not frozen run exactly one instruction
+    pub fn tick(&self) -> Result<(), InterpreterError> {
+        self.update(|state| {
+            state.paused = true;
+            state.budget = Budget::Tick(state.live.difference(&state.frozen).copied().collect());
+        })
+    }
+
+    /// run freely until something is written to stdout
+    pub fn run_until_output(&self) -> Result<(), InterpreterError> {
+        self.update(|state| {
+            state.paused = false;
+            state.budget = Budget::Exhausted;
+            state.stop_on_output = true;
+        })
+    }
+
+    /// keep IP `id` parked until it is thawed (or stepped on its own)
+    pub fn freeze(&self, id: usize) -> Result<(), InterpreterError> {
+        self.update(|state| {
+            state.frozen.insert(id);
+        })
+    }
+
+    pub fn thaw(&self, id: usize) -> Result<(), InterpreterError> {
+        self.update(|state| {
+            state.frozen.remove(&id);
+        })
+    }
+
+    pub fn is_frozen(&self, id: usize) -> Result<bool, InterpreterError> {
+        Ok(self.lock()?.frozen.contains(&id))
+    }
+
+    /// let IP `id` leave `checkpoint` regardless of the budget, so that its thread can end
+    pub fn retire(&self, id: usize) -> Result<(), InterpreterError> {
+        self.update(|state| {
+            state.retired.insert(id);
+        })
+    }
+
+    /// whether IP `id` still has a thread
+    pub fn is_live(&self, id: usize) -> Result<bool, InterpreterError> {
+        Ok(self.lock()?.live.contains(&id))
+    }
+
+    /// keep `Interpreter::run` waiting after the last IP stops, so that IPs can be revived
+    pub fn attach(&self) -> Result<(), InterpreterError> {
+        self.update(|state| state.attached = true)
+    }
+
+    pub fn detach(&self) -> Result<(), InterpreterError> {
+        self.update(|state| state.attached = false)
+    }
+
+    /// IP that hit the last breakpoint, cleared by reading it
+    pub fn take_focus(&self) -> Result<Option<usize>, InterpreterError> {
+        Ok(self.lock()?.focus.take())
+    }
+
+    /// block until every live IP is parked and none may run, or no IP is left,
+    /// returns false once no IP is left
+    pub fn wait_until_stopped(&self) -> Result<bool, InterpreterError> {
+        let mut state = self.lock()?;
+        loop {
+            if state.finished || (state.started && state.live.is_empty()) {
+                return Ok(false);
+            }
+            if state.is_stopped() {
+                // e.g. every remaining IP is frozen: keep them stopped until told otherwise
+                state.paused = true;
+                state.budget = Budget::Exhausted;
+                return Ok(true);
+            }
+            state = self
+                .changed
+                .wait(state)
+                .map_err(|_| InterpreterError::ThreadError("Failed to wait".to_string()))?;
+        }
+    }
+
+    /// called by IP `id` before each instruction, blocks until it may run;
+    /// the IP executes the instruction while holding the returned turn
+    pub fn checkpoint(&self, id: usize) -> Result<Turn<'_>, InterpreterError> {
+        let mut state = self.lock()?;
+        loop {
+            if state.retired.contains(&id) {
+                return Ok(Turn {
+                    control: self,
+                    id: None,
+                });
+            }
+            if state.may_run(id) {
+                state.consume(id);
+                return Ok(Turn {
+                    control: self,
+                    id: Some(id),
+                });
+            }
+            state.parked.insert(id);
+            self.changed.notify_all();
+            state = self
+                .changed
+                .wait(state)
+                .map_err(|_| InterpreterError::ThreadError("Failed to wait".to_string()))?;
+            state.parked.remove(&id);
+        }
+    }
+
+    /// called by `Interpreter::run` once no IP is left; while a debugger is attached,
+    /// waits for a revived IP (true) or the debugger to detach (false)
+    pub(crate) fn wait_for_revival(&self) -> Result<bool, InterpreterError> {
+        let mut state = self.lock()?;
+        loop {
+            if !state.attached {
+                return Ok(false);
+            }
+            if !state.live.is_empty() {
+                return Ok(true);
+            }
+            state = self
+                .changed
+                .wait(state)
+                .map_err(|_| InterpreterError::ThreadError("Failed to wait".to_string()))?;
+        }
+    }
+
+    pub(crate) fn on_output(&self) -> Result<(), InterpreterError> {
+        let mut state = self.lock()?;
+        if state.stop_on_output {
+            state.stop_on_output = false;
+            state.paused = true;
+            state.budget = Budget::Exhausted;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn ip_started(&self, id: usize) -> Result<(), InterpreterError> {
+        self.update(|state| {
+            state.live.insert(id);
+            state.retired.remove(&id);
+        })
+    }
+
+    /// true when IP `id` ended before the session being replayed expected it to
+    pub(crate) fn ip_finished(&self, id: usize) -> Result<bool, InterpreterError> {
+        let mut diverged = false;
+        self.update(|state| {
+            state.live.remove(&id);
+            if let Some(sequencer) = &mut state.sequencer {
+                diverged = sequencer.ip_ended(id);
+            }
+            if let Budget::Tick(pending) = &mut state.budget {
+                pending.remove(&id);
+            }
+        })?;
+        Ok(diverged)
+    }
+
+    pub(crate) fn set_started(&self) -> Result<(), InterpreterError> {
+        self.update(|state| state.started = true)
+    }
+
+    pub(crate) fn set_finished(&self) -> Result<(), InterpreterError> {
+        self.update(|state| state.finished = true)
+    }
+
+    /// scheduling order recorded so far, when recording a session
+    pub(crate) fn recorded_schedule(
+        &self,
+    ) -> Result<Option<Vec<(usize, usize)>>, InterpreterError> {
+        Ok(self
+            .lock()?
+            .sequencer
+            .as_ref()
+            .and_then(Sequencer::recorded))
+    }
+}
+
+/// An IP executing one instruction; with a sequencer the other IPs wait until it is dropped.
+pub struct Turn<'a> {
+    control: &'a ExecutionControl,
+    id: Option<usize>,
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            // a poisoned lock has already been reported by the thread that poisoned it
+            let _ = self.control.update(|state| {
+                if let Some(sequencer) = &mut state.sequencer {
+                    sequencer.end_turn(id);
+                }
+            });
+        }
+    }
+}
+
+/// Forwards to another `IOHandle`, tells the control about output and
+/// records input and output into the history.
+pub struct ObservedIO {
+    inner: Arc<dyn IOHandle + Send + Sync>,
+    control: Arc<ExecutionControl>,
+    history: Option<Arc<History>>,
+}
+
+impl ObservedIO {
+    pub fn new(
+        inner: Arc<dyn IOHandle + Send + Sync>,
+        control: Arc<ExecutionControl>,
+        history: Option<Arc<History>>,
+    ) -> Self {
+        Self {
+            inner,
+            control,
+            history,
+        }
+    }
+}
+
+impl IOHandle for ObservedIO {
+    fn write_output(&self, output: &str) -> Result<(), InterpreterError> {
+        self.inner.write_output(output)?;
+        History::record_output(output);
+        self.control.on_output()
+    }
+
+    fn write_error(&self, error: &str) -> Result<(), InterpreterError> {
+        self.inner.write_error(error)
+    }
+
+    fn read_number(&self) -> Result<usize, InterpreterError> {
+        // input handed back by undone steps comes first
+        let unread = match &self.history {
+            Some(history) => history.next_unread_number()?,
+            None => None,
+        };
+        let number = match unread {
+            Some(number) => number,
+            None => self.inner.read_number()?,
+        };
+        History::record_input(InputEvent::Number(number));
+        Ok(number)
+    }
+
+    fn read_char(&self) -> Result<char, InterpreterError> {
+        let unread = match &self.history {
+            Some(history) => history.next_unread_char()?,
+            None => None,
+        };
+        let c = match unread {
+            Some(c) => c,
+            None => self.inner.read_char()?,
+        };
+        History::record_input(InputEvent::Char(c));
+        Ok(c)
+    }
+
+    fn display_stack(&self, stack: &[usize]) -> Result<(), InterpreterError> {
+        self.inner.display_stack(stack)
+    }
+
+    fn display_grid(
+        &self,
+        grid: &[Vec<char>],
+        ip_x: usize,
+        ip_y: usize,
+        layer: Option<usize>,
+    ) -> Result<(), InterpreterError> {
+        self.inner.display_grid(grid, ip_x, ip_y, layer)
+    }
+
+    fn display_diagnostic(&self, diagnostic: &Diagnostic) -> Result<(), InterpreterError> {
+        self.inner.display_diagnostic(diagnostic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::history::StepRecord;
+    use crate::engine::testing;
+    use crate::interfaces::memory_io_handler::MemoryIOHandler;
+    use crate::interfaces::StepObserver;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    /// fake IP thread `id` passing `total` checkpoints, counting them in `executed`
+    fn spawn_ip(
+        control: Arc<ExecutionControl>,
+        id: usize,
+        total: usize,
+        executed: Arc<AtomicUsize>,
+    ) -> thread::JoinHandle<Result<(), InterpreterError>> {
+        control.ip_started(id).unwrap();
+        thread::spawn(move || {
+            for _ in 0..total {
+                control.checkpoint(id)?;
+                executed.fetch_add(1, Ordering::SeqCst);
+            }
+            control.ip_finished(id).map(|_| ())
+        })
+    }
+
+    /// instructions executed by each IP
+    #[derive(Default)]
+    struct Counter(Mutex<BTreeMap<usize, usize>>);
+
+    impl Counter {
+        fn counts(&self) -> (usize, usize) {
+            let counts = self.0.lock().unwrap();
+            let count = |id| counts.get(&id).copied().unwrap_or(0);
+            (count(0), count(1))
+        }
+    }
+
+    impl StepObserver for Counter {
+        fn on_step(&self, record: &StepRecord) -> Result<(), InterpreterError> {
+            *self.0.lock()?.entry(record.ip_id).or_default() += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_step_budget_and_resume() {
+        let control = Arc::new(ExecutionControl::new());
+        control.pause().unwrap();
+        let executed = Arc::new(AtomicUsize::new(0));
+        let handle = spawn_ip(control.clone(), 0, 10, executed.clone());
+        control.set_started().unwrap();
+
+        assert!(control.wait_until_stopped().unwrap());
+        control.step_ip(0, 3).unwrap();
+        assert!(control.wait_until_stopped().unwrap());
+        assert!(control.lock().unwrap().paused);
+        assert_eq!(executed.load(Ordering::SeqCst), 3);
+
+        control.resume().unwrap();
+        handle.join().unwrap().unwrap();
+        control.set_finished().unwrap();
+        assert!(!control.wait_until_stopped().unwrap());
+    }
+
+    #[test]
+    fn test_step_one_ip_tick_and_freeze() {
+        let control = Arc::new(ExecutionControl::new());
+        control.pause().unwrap();
+        let (a, b) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let handles = [
+            spawn_ip(control.clone(), 0, 10, a.clone()),
+            spawn_ip(control.clone(), 1, 10, b.clone()),
+        ];
+        control.set_started().unwrap();
+        assert!(control.wait_until_stopped().unwrap());
+
+        // only IP 1 advances
+        control.step_ip(1, 3).unwrap();
+        assert!(control.wait_until_stopped().unwrap());
+        assert_eq!((a.load(Ordering::SeqCst), b.load(Ordering::SeqCst)), (0, 3));
+
+        // lockstep: one instruction each
+        control.tick().unwrap();
+        assert!(control.wait_until_stopped().unwrap());
+        assert_eq!((a.load(Ordering::SeqCst), b.load(Ordering::SeqCst)), (1, 4));
+
+        // a frozen IP stays behind, even while running
+        control.freeze(0).unwrap();
+        control.resume().unwrap();
+        assert!(control.wait_until_stopped().unwrap());
+        assert_eq!(
+            (a.load(Ordering::SeqCst), b.load(Ordering::SeqCst)),
+            (1, 10)
+        );
+
+        control.thaw(0).unwrap();
+        control.resume().unwrap();
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+        control.set_finished().unwrap();
+        assert!(!control.wait_until_stopped().unwrap());
+    }
+
+    #[test]
+    fn test_control_two_interpreter_ips() {
+        // `t` starts IP 1 next to IP 0, both walk right onto `@`
+        let counter = Arc::new(Counter::default());
+        let interpreter =
+            Arc::new(testing::interpreter("10t          @").with_observer(counter.clone()));
+        let control = interpreter.control();
+        control.pause().unwrap();
+        let running = interpreter.clone();
+        let handle = thread::spawn(move || running.run(Arc::new(MemoryIOHandler::new(""))));
+        assert!(control.wait_until_stopped().unwrap());
+
+        // IP 0 runs up to the `t`
+        control.step_ip(0, 3).unwrap();
+        assert!(control.wait_until_stopped().unwrap());
+        assert_eq!(counter.counts(), (3, 0));
+
+        // only IP 1 advances
+        control.step_ip(1, 2).unwrap();
+        assert!(control.wait_until_stopped().unwrap());
+        assert_eq!(counter.counts(), (3, 2));
+
+        // lockstep: one instruction each
+        control.tick().unwrap();
+        assert!(control.wait_until_stopped().unwrap());
+        assert_eq!(counter.counts(), (4, 3));
+
+        // IP 0 stays behind while IP 1 runs onto `@`
+        control.freeze(0).unwrap();
+        control.resume().unwrap();
+        assert!(control.wait_until_stopped().unwrap());
+        let (frozen, finished) = counter.counts();
+        assert_eq!(frozen, 4);
+        assert!(
+            interpreter
+                .ip(1)
+                .unwrap()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .terminated
+        );
+
+        control.thaw(0).unwrap();
+        control.resume().unwrap();
+        handle.join().unwrap().unwrap();
+        assert!(counter.counts().0 > frozen);
+        assert_eq!(counter.counts().1, finished);
+    }
+
+    #[test]
+    fn test_output_pauses_run_until_output() {
+        let control = ExecutionControl::new();
+        control.run_until_output().unwrap();
+        assert!(!control.lock().unwrap().paused);
+        control.on_output().unwrap();
+        assert!(control.lock().unwrap().paused);
+
+        // plain output does not pause
+        control.resume().unwrap();
+        control.on_output().unwrap();
+        assert!(!control.lock().unwrap().paused);
+    }
+}
@@ -0,0 +1,421 @@
+// src/engine/coverage.rs
+
+use crate::engine::history::StepRecord;
+use crate::errors::InterpreterError;
+use crate::interfaces::StepObserver;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Which ways a `_` or `|` went: `zero` is right/down, `nonzero` is left/up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchCount {
+    pub instruction: char,
+    pub zero: usize,
+    pub nonzero: usize,
+}
+
+/// Visited cells and branch directions, merged over any number of runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub runs: usize,
+    /// (x, y) -> executions
+    pub visits: BTreeMap<(usize, usize), usize>,
+    pub branches: BTreeMap<(usize, usize), BranchCount>,
+}
+
+/// Collects coverage of one run.
+pub struct CoverageCollector {
+    coverage: Mutex<Coverage>,
+}
+
+impl CoverageCollector {
+    pub fn new() -> Self {
+        Self {
+            coverage: Mutex::new(Coverage {
+                runs: 1,
+                ..Coverage::default()
+            }),
+        }
+    }
+
+    pub fn coverage(&self) -> Result<Coverage, InterpreterError> {
+        Ok(self.coverage.lock()?.clone())
+    }
+}
+
+impl StepObserver for CoverageCollector {
+    fn on_step(&self, record: &StepRecord) -> Result<(), InterpreterError> {
+        let mut coverage = self.coverage.lock()?;
+        *coverage.visits.entry((record.x, record.y)).or_default() += 1;
+        if !record.string_mode && matches!(record.instruction, '_' | '|') {
+            let branch = coverage
+                .branches
+                .entry((record.x, record.y))
+                .or_insert(BranchCount {
+                    instruction: record.instruction,
+                    ..BranchCount::default()
+                });
+            // an empty stack pops as zero
+            match record.popped.last() {
+                Some(value) if *value != 0 => branch.nonzero += 1,
+                _ => branch.zero += 1,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Machine-readable coverage, also the file runs are merged into.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub runs: usize,
+    pub summary: CoverageSummary,
+    pub visited: Vec<CellVisits>,
+    pub branches: Vec<BranchCoverage>,
+    /// non-blank cells of the program that never ran
+    pub unvisited: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoverageSummary {
+    /// non-blank cells of the program
+    pub cells: usize,
+    pub visited: usize,
+    pub percent: f64,
+    pub branches: usize,
+    pub both_ways: usize,
+    pub one_way: usize,
+    pub never: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellVisits {
+    pub x: usize,
+    pub y: usize,
+    pub count: usize,
+}
+
+/// `zero` counts right (`_`) or down (`|`), `nonzero` left or up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BranchCoverage {
+    pub x: usize,
+    pub y: usize,
+    #[serde(rename = "char")]
+    pub instruction: char,
+    pub zero: usize,
+    pub nonzero: usize,
+}
+
+impl Coverage {
+    pub fn merge(&mut self, other: &Coverage) {
+        self.runs += other.runs;
+        for (cell, count) in &other.visits {
+            *self.visits.entry(*cell).or_default() += count;
+        }
+        for (cell, branch) in &other.branches {
+            let merged = self.branches.entry(*cell).or_insert(BranchCount {
+                instruction: branch.instruction,
+                ..BranchCount::default()
+            });
+            merged.zero += branch.zero;
+            merged.nonzero += branch.nonzero;
+        }
+    }
+
+    /// `program` is the grid as loaded, before any `p`
+    fn branch_at(&self, program: &[Vec<char>], x: usize, y: usize) -> Option<BranchCount> {
+        match self.branches.get(&(x, y)) {
+            Some(branch) => Some(*branch),
+            None => match cell_at(program, x, y) {
+                c @ ('_' | '|') => Some(BranchCount {
+                    instruction: c,
+                    ..BranchCount::default()
+                }),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn report(&self, program: &[Vec<char>]) -> CoverageReport {
+        let mut cells = 0;
+        let mut unvisited = Vec::new();
+        let mut branches = Vec::new();
+        for (y, row) in program.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                if *c == ' ' {
+                    continue;
+                }
+                cells += 1;
+                if !self.visits.contains_key(&(x, y)) {
+                    unvisited.push((x, y));
+                }
+            }
+        }
+        // branches in the program, plus any written by `p` that ran
+        let mut branch_cells: Vec<(usize, usize)> = self.branches.keys().copied().collect();
+        for (y, row) in program.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                if matches!(c, '_' | '|') && !self.branches.contains_key(&(x, y)) {
+                    branch_cells.push((x, y));
+                }
+            }
+        }
+        branch_cells.sort_by_key(|(x, y)| (*y, *x));
+        for (x, y) in branch_cells {
+            if let Some(branch) = self.branch_at(program, x, y) {
+                branches.push(BranchCoverage {
+                    x,
+                    y,
+                    instruction: branch.instruction,
+                    zero: branch.zero,
+                    nonzero: branch.nonzero,
+                });
+            }
+        }
+
+        let visited = cells - unvisited.len();
+        let taken = |b: &&BranchCoverage| (b.zero > 0) as usize + (b.nonzero > 0) as usize;
+        let summary = CoverageSummary {
+            cells,
+            visited,
+            percent: if cells == 0 {
+                100.0
+            } else {
+                (1000.0 * visited as f64 / cells as f64).round() / 10.0
+            },
+            branches: branches.len(),
+            both_ways: branches.iter().filter(|b| taken(b) == 2).count(),
+            one_way: branches.iter().filter(|b| taken(b) == 1).count(),
+            never: branches.iter().filter(|b| taken(b) == 0).count(),
+        };
+        CoverageReport {
+            runs: self.runs,
+            summary,
+            visited: self
+                .visits
+                .iter()
+                .map(|((x, y), count)| CellVisits {
+                    x: *x,
+                    y: *y,
+                    count: *count,
+                })
+                .collect(),
+            branches,
+            unvisited,
+        }
+    }
+
+    /// the program with unexecuted cells and one-way branches highlighted,
+    /// or next to a marker map without color
+    pub fn overlay(&self, program: &[Vec<char>], color: bool) -> String {
+        let mut out = String::new();
+        for (y, row) in program.iter().enumerate() {
+            let mut code = String::new();
+            let mut marks = String::new();
+            for (x, c) in row.iter().enumerate() {
+                let visited = self.visits.contains_key(&(x, y));
+                let mark = match self.branch_at(program, x, y) {
+                    Some(branch) if branch.zero > 0 && branch.nonzero > 0 => '+',
+                    // the only way the branch went
+                    Some(branch) if branch.zero > 0 || branch.nonzero > 0 => {
+                        match (branch.instruction, branch.zero > 0) {
+                            ('_', true) => '>',
+                            ('_', false) => '<',
+                            (_, true) => 'v',
+                            (_, false) => '^',
+                        }
+                    }
+                    _ if visited => '+',
+                    _ if *c != ' ' => '!',
+                    _ => ' ',
+                };
+                if color {
+                    let paint = match mark {
+                        '!' => Some(MISSED),
+                        '<' | '>' | '^' | 'v' => Some(ONE_WAY),
+                        _ => None,
+                    };
+                    match paint {
+                        Some(paint) => code.push_str(&format!("{} {} {}", paint, c, RESET)),
+                        None => code.push_str(&format!(" {} ", c)),
+                    }
+                } else {
+                    code.push_str(&format!(" {} ", c));
+                    marks.push(mark);
+                }
+            }
+            if color {
+                out.push_str(&format!("{:5} {}\n", y, code));
+            } else {
+                out.push_str(&format!("{:5} {} | {}\n", y, code, marks.trim_end()));
+            }
+        }
+        if color {
+            out.push_str(&format!(
+                "      {} never executed {}  {} branch taken one way only {}\n",
+                MISSED, RESET, ONE_WAY, RESET
+            ));
+        } else {
+            out.push_str(
+                "      + executed, ! never executed, < > ^ v branch only taken that way\n",
+            );
+        }
+        out
+    }
+}
+
+impl From<&CoverageReport> for Coverage {
+    fn from(report: &CoverageReport) -> Self {
+        Self {
+            runs: report.runs,
+            visits: report
+                .visited
+                .iter()
+                .map(|cell| ((cell.x, cell.y), cell.count))
+                .collect(),
+            branches: report
+                .branches
+                .iter()
+                .filter(|branch| branch.zero > 0 || branch.nonzero > 0)
+                .map(|branch| {
+                    (
+                        (branch.x, branch.y),
+                        BranchCount {
+                            instruction: branch.instruction,
+                            zero: branch.zero,
+                            nonzero: branch.nonzero,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl CoverageReport {
+    /// an empty report when `path` does not exist yet
+    pub fn load_or_default(path: &str) -> Result<Self, InterpreterError> {
+        if !Path::new(path).exists() {
+            return Ok(Self::default());
+        }
+        Self::load(path)
+    }
+
+    pub fn load(path: &str) -> Result<Self, InterpreterError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| InterpreterError::FileReadError(format!("{}: {}", path, e)))?;
+        serde_json::from_str(&text)
+            .map_err(|e| InterpreterError::ParseError(format!("{}: {}", path, e)))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), InterpreterError> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for CoverageSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "coverage: {}/{} cells ({}%), branches: {} both ways, {} one way, {} never (of {})",
+            self.visited,
+            self.cells,
+            self.percent,
+            self.both_ways,
+            self.one_way,
+            self.never,
+            self.branches
+        )
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const MISSED: &str = "\x1b[41;30m";
+const ONE_WAY: &str = "\x1b[43;30m";
+
+fn cell_at(grid: &[Vec<char>], x: usize, y: usize) -> char {
+    grid.get(y)
+        .and_then(|row| row.get(x))
+        .copied()
+        .unwrap_or(' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::Direction;
+
+    fn record(x: usize, y: usize, instruction: char, popped: &[usize]) -> StepRecord {
+        StepRecord {
+            step: 0,
+            ip_id: 0,
+            instruction,
+            x,
+            y,
+            z: 0,
+            direction: Direction::Right,
+            string_mode: false,
+            stack_kept: 0,
+            popped: popped.to_vec(),
+            pushed: vec![],
+            writes: vec![],
+            spawned: vec![],
+            input: vec![],
+            output: String::new(),
+        }
+    }
+
+    fn grid(lines: &[&str]) -> Vec<Vec<char>> {
+        lines.iter().map(|line| line.chars().collect()).collect()
+    }
+
+    #[test]
+    fn test_merge_runs_and_report() {
+        // `&_1@` with a `|` below the `_` that never runs
+        let program = grid(&["&_1@", " |  "]);
+        let run = |input: usize| {
+            let collector = CoverageCollector::new();
+            collector.on_step(&record(0, 0, '&', &[])).unwrap();
+            collector.on_step(&record(1, 0, '_', &[input])).unwrap();
+            if input == 0 {
+                collector.on_step(&record(2, 0, '1', &[])).unwrap();
+            }
+            collector.coverage().unwrap()
+        };
+
+        let mut coverage = run(0);
+        let report = coverage.report(&program);
+        assert_eq!(report.summary.one_way, 1);
+        assert_eq!(report.summary.never, 1);
+        assert_eq!(report.unvisited, vec![(3, 0), (1, 1)]);
+
+        // merged through the file format
+        let saved: CoverageReport =
+            serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        coverage = Coverage::from(&saved);
+        coverage.merge(&run(5));
+        let report = coverage.report(&program);
+        assert_eq!(report.runs, 2);
+        assert_eq!(report.summary.both_ways, 1);
+        assert_eq!(report.summary.visited, 3);
+        assert_eq!(report.summary.percent, 60.0);
+        assert_eq!(
+            report.branches[0],
+            BranchCoverage {
+                x: 1,
+                y: 0,
+                instruction: '_',
+                zero: 1,
+                nonzero: 1,
+            }
+        );
+
+        let overlay = run(0).overlay(&program, false);
+        assert!(overlay.starts_with("    0  &  _  1  @  | +>+!\n"));
+        assert!(overlay.contains("    1     |        |  !\n"));
+    }
+}
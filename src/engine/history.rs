@@ -0,0 +1,315 @@
+// src/engine/history.rs
+
+use crate::entities::{Direction, IPState, Position};
+use crate::errors::InterpreterError;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// A write to one cell by `p`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridWrite {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+    pub old: char,
+    pub new: char,
+}
+
+/// A value taken from the input by `~` or `&`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputEvent {
+    Char(char),
+    Number(usize),
+}
+
+/// Undo delta of one executed instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepRecord {
+    /// number of the instruction among all IPs, starting at 1
+    pub step: usize,
+    pub ip_id: usize,
+    pub instruction: char,
+    /// position, direction and string mode before the instruction
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+    pub direction: Direction,
+    pub string_mode: bool,
+    /// stack length left untouched by the instruction
+    pub stack_kept: usize,
+    /// values the instruction removed, bottom first
+    pub popped: Vec<usize>,
+    /// values the instruction left on top of `stack_kept`, bottom first
+    pub pushed: Vec<usize>,
+    pub writes: Vec<GridWrite>,
+    pub spawned: Vec<usize>,
+    pub input: Vec<InputEvent>,
+    pub output: String,
+}
+
+impl StepRecord {
+    /// the cell the instruction was executed at
+    pub fn position(&self) -> Position {
+        Position::new(self.x, self.y, self.z)
+    }
+
+    pub fn wrote_to(&self, at: Position) -> bool {
+        self.writes
+            .iter()
+            .any(|w| Position::new(w.x, w.y, w.z) == at)
+    }
+}
+
+/// side effects of the instruction the current IP thread is executing
+#[derive(Default)]
+struct PendingEffects {
+    writes: Vec<GridWrite>,
+    spawned: Vec<usize>,
+    input: Vec<InputEvent>,
+    output: String,
+}
+
+thread_local! {
+    // every IP runs on its own thread, so effects are collected per thread
+    static PENDING: RefCell<Option<PendingEffects>> = const { RefCell::new(None) };
+}
+
+fn with_pending(f: impl FnOnce(&mut PendingEffects)) {
+    PENDING.with(|pending| {
+        if let Some(effects) = pending.borrow_mut().as_mut() {
+            f(effects);
+        }
+    });
+}
+
+/// State of an IP right before an instruction, completed into a [`StepRecord`].
+pub struct StepStart {
+    step: usize,
+    ip_id: usize,
+    instruction: char,
+    x: usize,
+    y: usize,
+    z: usize,
+    direction: Direction,
+    string_mode: bool,
+    stack: Vec<usize>,
+}
+
+impl StepStart {
+    /// start collecting the effects of the `step`th instruction, which `ip` is about to execute
+    pub fn begin(step: usize, ip: &IPState, instruction: char) -> Self {
+        PENDING.with(|pending| *pending.borrow_mut() = Some(PendingEffects::default()));
+        Self {
+            step,
+            ip_id: ip.id,
+            instruction,
+            x: ip.ip_x,
+            y: ip.ip_y,
+            z: ip.ip_z,
+            direction: ip.direction,
+            string_mode: ip.string_mode_active,
+            stack: ip.stk.clone(),
+        }
+    }
+
+    /// finish the instruction, `ip` is its state afterwards
+    pub fn end(self, ip: &IPState) -> StepRecord {
+        let effects = PENDING
+            .with(|pending| pending.borrow_mut().take())
+            .unwrap_or_default();
+        let stack_kept = self
+            .stack
+            .iter()
+            .zip(ip.stk.iter())
+            .take_while(|(before, after)| before == after)
+            .count();
+        StepRecord {
+            step: self.step,
+            ip_id: self.ip_id,
+            instruction: self.instruction,
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            direction: self.direction,
+            string_mode: self.string_mode,
+            stack_kept,
+            popped: self.stack[stack_kept..].to_vec(),
+            pushed: ip.stk[stack_kept..].to_vec(),
+            writes: effects.writes,
+            spawned: effects.spawned,
+            input: effects.input,
+            output: effects.output,
+        }
+    }
+}
+
+/// Bounded ring buffer of undo records plus input waiting to be read again.
+pub struct History {
+    capacity: usize,
+    records: Mutex<VecDeque<StepRecord>>,
+    /// input given back by undone steps, read before the real input
+    unread: Mutex<VecDeque<InputEvent>>,
+}
+
+impl History {
+    pub const DEFAULT_CAPACITY: usize = 10_000;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity.min(1024))),
+            unread: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn push(&self, record: StepRecord) -> Result<(), InterpreterError> {
+        let mut records = self.records.lock()?;
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        if self.capacity > 0 {
+            records.push_back(record);
+        }
+        Ok(())
+    }
+
+    pub fn record_write(at: Position, old: char, new: char) {
+        let Position { x, y, z } = at;
+        with_pending(|effects| effects.writes.push(GridWrite { x, y, z, old, new }));
+    }
+
+    pub fn record_spawn(id: usize) {
+        with_pending(|effects| effects.spawned.push(id));
+    }
+
+    pub fn record_input(event: InputEvent) {
+        with_pending(|effects| effects.input.push(event));
+    }
+
+    pub fn record_output(output: &str) {
+        with_pending(|effects| effects.output.push_str(output));
+    }
+
+    /// remove the newest record
+    pub fn pop(&self) -> Result<Option<StepRecord>, InterpreterError> {
+        Ok(self.records.lock()?.pop_back())
+    }
+
+    /// drop every record, e.g. after the state was changed by hand
+    pub fn clear(&self) -> Result<(), InterpreterError> {
+        self.records.lock()?.clear();
+        Ok(())
+    }
+
+    pub fn len(&self) -> Result<usize, InterpreterError> {
+        Ok(self.records.lock()?.len())
+    }
+
+    /// how many of the newest records must be undone to get back before the last write to `at`
+    pub fn steps_since_write(&self, at: Position) -> Result<Option<usize>, InterpreterError> {
+        Ok(self
+            .records
+            .lock()?
+            .iter()
+            .rev()
+            .position(|record| record.wrote_to(at))
+            .map(|i| i + 1))
+    }
+
+    /// hand input consumed by an undone step back to the program
+    pub fn unread(&self, input: &[InputEvent]) -> Result<(), InterpreterError> {
+        let mut unread = self.unread.lock()?;
+        for event in input.iter().rev() {
+            unread.push_front(*event);
+        }
+        Ok(())
+    }
+
+    pub fn next_unread_char(&self) -> Result<Option<char>, InterpreterError> {
+        let mut unread = self.unread.lock()?;
+        match unread.front() {
+            Some(InputEvent::Char(c)) => {
+                let c = *c;
+                unread.pop_front();
+                Ok(Some(c))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn next_unread_number(&self) -> Result<Option<usize>, InterpreterError> {
+        let mut unread = self.unread.lock()?;
+        match unread.front() {
+            Some(InputEvent::Number(n)) => {
+                let n = *n;
+                unread.pop_front();
+                Ok(Some(n))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip_with_stack(stack: &[usize]) -> IPState {
+        let mut ip = IPState::new(1, 2, Direction::Right);
+        ip.stk = stack.to_vec();
+        ip
+    }
+
+    #[test]
+    fn test_record_stack_delta_and_effects() {
+        let history = History::new(8);
+        let start = StepStart::begin(1, &ip_with_stack(&[7, 1, 2]), '+');
+        History::record_write(Position::default(), ' ', 'x');
+        History::record_input(InputEvent::Char('a'));
+        let record = start.end(&ip_with_stack(&[7, 3]));
+        history.push(record.clone()).unwrap();
+
+        assert_eq!(record.stack_kept, 1);
+        assert_eq!(record.popped, vec![1, 2]);
+        assert_eq!(record.pushed, vec![3]);
+        assert_eq!(record.writes.len(), 1);
+        assert_eq!(record.input, vec![InputEvent::Char('a')]);
+        assert_eq!(
+            history.steps_since_write(Position::default()).unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            history.steps_since_write(Position::new(1, 0, 0)).unwrap(),
+            None
+        );
+
+        // effects outside begin/end are not recorded
+        History::record_write(Position::new(5, 5, 0), ' ', 'y');
+        let start = StepStart::begin(2, &ip_with_stack(&[]), ' ');
+        let record = start.end(&ip_with_stack(&[]));
+        assert!(record.writes.is_empty());
+    }
+
+    #[test]
+    fn test_ring_buffer_and_unread_input() {
+        let history = History::new(2);
+        for step in 1..=3 {
+            let start = StepStart::begin(step, &ip_with_stack(&[]), ' ');
+            history.push(start.end(&ip_with_stack(&[]))).unwrap();
+        }
+        assert_eq!(history.len().unwrap(), 2);
+
+        history
+            .unread(&[InputEvent::Char('a'), InputEvent::Number(4)])
+            .unwrap();
+        assert_eq!(history.next_unread_number().unwrap(), None);
+        assert_eq!(history.next_unread_char().unwrap(), Some('a'));
+        assert_eq!(history.next_unread_number().unwrap(), Some(4));
+
+        history.clear().unwrap();
+        assert_eq!(history.len().unwrap(), 0);
+    }
+}
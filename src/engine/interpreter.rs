@@ -1,12 +1,23 @@
 // src/usecases/interpreter.rs
 
 use crate::engine::commands::command::CommandGrid;
-use crate::entities::{CodeGrid, Direction, IPState};
-use crate::errors::InterpreterError;
-use crate::interfaces::{CommandResolve, IOHandle};
+use crate::engine::control::{ExecutionControl, ObservedIO};
+use crate::engine::history::{History, StepRecord, StepStart};
+use crate::engine::semantics::Semantics;
+use crate::engine::session::{Sequencer, Session, SessionIO, SessionInput, SessionMode};
+use crate::engine::topology::TopologyKind;
+use crate::entities::breakpoint::Region;
+use crate::entities::{
+    Breakpoint, CodeGrid, Dimensions, Direction, IPState, Position, Size, Watchpoint,
+};
+use crate::errors::{
+    Diagnostic, GridAccess, InterpreterError, RuntimeContext, RuntimeError, RuntimeErrorKind,
+};
+use crate::interfaces::{CommandResolve, IOHandle, StepObserver, Topology};
 use std::any::Any;
 use std::io::Write;
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::{char, io, thread};
 
 pub struct Interpreter {
@@ -15,7 +26,26 @@ pub struct Interpreter {
     ips: Arc<Mutex<Vec<Arc<Mutex<IPState>>>>>,
     command_registry: Arc<dyn CommandResolve + Send + Sync>,
     threads: Arc<Mutex<Vec<thread::JoinHandle<()>>>>,
-    step_mode: Arc<(Mutex<bool>, Condvar)>,
+    next_ip_id: Arc<AtomicUsize>,
+    steps: Arc<AtomicUsize>,
+    max_steps: Option<usize>,
+    semantics: Arc<Semantics>,
+    /// where IPs go when they move off an edge
+    topology: Arc<dyn Topology + Send + Sync>,
+    dimensions: Dimensions,
+    control: Arc<ExecutionControl>,
+    /// 複数IPのデバッグ出力が混ざらないようにするロック
+    debug_output: Arc<Mutex<()>>,
+    /// 逆実行用の取り消し履歴（デバッガ使用時のみ）
+    history: Option<Arc<History>>,
+    /// 実行した命令を受け取るもの（トレース、リプレイ検査など）
+    observers: Vec<Arc<dyn StepObserver + Send + Sync>>,
+    /// セッションの入力（記録または再生時のみ）
+    session_input: Option<Arc<SessionInput>>,
+    /// IP を復活させるときに使う、run() で包んだ IOHandle
+    io_handler: Arc<Mutex<Option<Arc<dyn IOHandle + Send + Sync>>>>,
+    breakpoints: Arc<Mutex<Vec<Breakpoint>>>,
+    watchpoints: Arc<Mutex<Vec<Watchpoint>>>,
 }
 
 impl Clone for Interpreter {
@@ -26,14 +56,28 @@ impl Clone for Interpreter {
             ips: Arc::clone(&self.ips),
             command_registry: Arc::clone(&self.command_registry),
             threads: Arc::clone(&self.threads),
-            step_mode: Arc::clone(&self.step_mode),
+            next_ip_id: Arc::clone(&self.next_ip_id),
+            steps: Arc::clone(&self.steps),
+            max_steps: self.max_steps,
+            semantics: Arc::clone(&self.semantics),
+            topology: Arc::clone(&self.topology),
+            dimensions: self.dimensions,
+            control: Arc::clone(&self.control),
+            debug_output: Arc::clone(&self.debug_output),
+            history: self.history.clone(),
+            observers: self.observers.clone(),
+            session_input: self.session_input.clone(),
+            io_handler: Arc::clone(&self.io_handler),
+            breakpoints: Arc::clone(&self.breakpoints),
+            watchpoints: Arc::clone(&self.watchpoints),
         }
     }
 }
 
 impl Interpreter {
-    const HEIGHT: usize = 1024;
-    const WIDTH: usize = 1024;
+    pub(crate) const HEIGHT: usize = 1024;
+    pub(crate) const WIDTH: usize = 1024;
+    pub(crate) const DEPTH: usize = 1024;
 
     pub fn new(
         program: CodeGrid,
@@ -46,81 +90,391 @@ impl Interpreter {
             ips: Arc::new(Mutex::new(Vec::new())),
             command_registry,
             threads: Arc::new(Mutex::new(Vec::new())),
-            step_mode: Arc::new((Mutex::new(false), Condvar::new())),
+            next_ip_id: Arc::new(AtomicUsize::new(0)),
+            steps: Arc::new(AtomicUsize::new(0)),
+            max_steps: None,
+            semantics: Arc::new(Semantics::default()),
+            topology: TopologyKind::default().topology(),
+            dimensions: Dimensions::default(),
+            control: Arc::new(ExecutionControl::new()),
+            debug_output: Arc::new(Mutex::new(())),
+            history: None,
+            observers: Vec::new(),
+            session_input: None,
+            io_handler: Arc::new(Mutex::new(None)),
+            breakpoints: Arc::new(Mutex::new(Vec::new())),
+            watchpoints: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
-    /// ステップ実行モードがアクティブかどうかを確認するメソッド
-    fn is_step_mode_active(&self) -> bool {
-        let (lock, _) = &*self.step_mode;
-        let step = lock.lock().unwrap();
-        *step
+    /// 記録中のセッション（記録していなければ None）
+    pub fn session(&self) -> Result<Option<Session>, InterpreterError> {
+        let Some(input) = &self.session_input else {
+            return Ok(None);
+        };
+        match (self.control.recorded_schedule()?, input.recorded()?) {
+            (Some(schedule), Some(input)) => Ok(Some(Session { schedule, input })),
+            _ => Ok(None),
+        }
+    }
+
+    /// 全IP合計の実行ステップ数の上限を設定する
+    pub fn with_max_steps(mut self, max_steps: Option<usize>) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// 直近 `capacity` 命令分の取り消し履歴を記録する（None なら記録しない）
+    pub fn with_history(mut self, capacity: Option<usize>) -> Self {
+        self.history = capacity.map(|capacity| Arc::new(History::new(capacity)));
+        self
+    }
+
+    /// 実行した命令を `observer` に渡す
+    pub fn with_observer(mut self, observer: Arc<dyn StepObserver + Send + Sync>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// スケジューリング順と入力をセッションとして記録する、または記録どおりに再実行する
+    pub fn with_session(mut self, mode: SessionMode) -> Self {
+        let (sequencer, input) = match mode {
+            SessionMode::Record => (Sequencer::record(), SessionInput::record()),
+            SessionMode::Replay(session) => (
+                Sequencer::replay(session.schedule),
+                SessionInput::replay(session.input),
+            ),
+        };
+        self.control = Arc::new(ExecutionControl::with_sequencer(sequencer));
+        self.session_input = Some(Arc::new(input));
+        self
+    }
+
+    /// ゼロ除算・スタックアンダーフロー時の振る舞いを設定する
+    pub fn with_semantics(mut self, semantics: Semantics) -> Self {
+        self.semantics = Arc::new(semantics);
+        self
+    }
+
+    /// IP の移動と端での折り返し方を差し替える
+    pub fn with_topology(mut self, topology: Arc<dyn Topology + Send + Sync>) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Unefunge / Trefunge として実行する（プログラムは同じ次元数で読み込んでおくこと）
+    pub fn with_dimensions(mut self, dimensions: Dimensions) -> Self {
+        self.dimensions = dimensions;
+        self
+    }
+
+    pub fn dimensions(&self) -> Dimensions {
+        self.dimensions
+    }
+
+    /// Trefunge なら層 `z`、それ以外は None
+    fn layer_label(&self, z: usize) -> Option<usize> {
+        (self.dimensions == Dimensions::Trefunge).then_some(z)
+    }
+
+    /// 実行の一時停止・再開・ステップ実行を操作するハンドル
+    pub fn control(&self) -> Arc<ExecutionControl> {
+        Arc::clone(&self.control)
+    }
+
+    /// 登録済みの全IP（終了したものを含む、id 順）
+    pub fn ips(&self) -> Result<Vec<Arc<Mutex<IPState>>>, InterpreterError> {
+        Ok(self.ips.lock()?.clone())
+    }
+
+    pub fn ip(&self, id: usize) -> Result<Option<Arc<Mutex<IPState>>>, InterpreterError> {
+        for ip in self.ips()? {
+            if ip.lock()?.id == id {
+                return Ok(Some(ip));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 記録済みの命令数（逆実行できる上限）
+    pub fn history_len(&self) -> Result<usize, InterpreterError> {
+        match &self.history {
+            Some(history) => history.len(),
+            None => Ok(0),
+        }
+    }
+
+    /// 履歴を捨てる。デバッガで状態を書き換えると記録との整合が取れなくなるため
+    pub fn clear_history(&self) -> Result<(), InterpreterError> {
+        match &self.history {
+            Some(history) => history.clear(),
+            None => Ok(()),
+        }
+    }
+
+    /// `at` への最後の書き込みより前に戻るのに取り消す命令数
+    pub fn steps_since_write(&self, at: Position) -> Result<Option<usize>, InterpreterError> {
+        match &self.history {
+            Some(history) => history.steps_since_write(at),
+            None => Ok(None),
+        }
     }
 
-    /// ステップ実行モードで一時停止し、次のステップの入力を待つメソッド
-    fn wait_for_step(&self) -> Result<(), InterpreterError> {
-        let (lock, cvar) = &*self.step_mode;
-        let mut step = lock.lock().unwrap();
-        while *step {
-            step = cvar.wait(step).unwrap();
+    /// 直前の命令を1つ取り消す。履歴が空なら None
+    ///
+    /// 全IPが停止している間（デバッガのプロンプト中）に呼ぶこと
+    pub fn step_back(&self) -> Result<Option<StepRecord>, InterpreterError> {
+        let Some(history) = &self.history else {
+            return Ok(None);
+        };
+        let Some(record) = history.pop()? else {
+            return Ok(None);
+        };
+
+        // IPs spawned by the instruction disappear
+        for id in &record.spawned {
+            if let Some(ip) = self.ip(*id)? {
+                ip.lock()?.terminated = true;
+                self.ips.lock()?.retain(|other| !Arc::ptr_eq(other, &ip));
+            }
+            self.control.retire(*id)?;
+        }
+
+        // grid writes, newest first
+        {
+            let mut grid = self.program.grid.lock()?;
+            for write in record.writes.iter().rev() {
+                if let Some(row) = self.program.row(write.y, write.z) {
+                    grid[row][write.x] = write.old;
+                }
+            }
+        }
+        history.unread(&record.input)?;
+        self.steps.fetch_sub(1, Ordering::SeqCst);
+
+        let Some(ip) = self.ip(record.ip_id)? else {
+            return Ok(Some(record));
+        };
+        {
+            let mut ip_locked = ip.lock()?;
+            ip_locked.set_position(Position::new(record.x, record.y, record.z));
+            ip_locked.direction = record.direction;
+            ip_locked.string_mode_active = record.string_mode;
+            ip_locked.stk.truncate(record.stack_kept);
+            ip_locked.stk.extend(&record.popped);
+            ip_locked.terminated = false;
+            if ip_locked.trail.back() == Some(&Position::new(record.x, record.y, record.z)) {
+                ip_locked.trail.pop_back();
+            }
+        }
+        // the IP stopped (terminated or failed) in this instruction: give it a thread again
+        if !self.control.is_live(record.ip_id)? {
+            let io_handler = self.io_handler.lock()?.clone();
+            if let Some(io_handler) = io_handler {
+                self.spawn_ip_thread(ip, io_handler)?;
+            }
         }
+        Ok(Some(record))
+    }
+
+    /// 読み込んだプログラムの幅と高さ（1層あたり）
+    pub fn code_size(&self) -> (usize, usize) {
+        (self.program.code_width, self.program.code_height)
+    }
+
+    /// Trefunge の層の数（それ以外では 1）
+    pub fn code_depth(&self) -> usize {
+        self.program.code_depth
+    }
+
+    /// `region` の層の範囲のセル（グリッド外は切り捨て）
+    pub fn grid_region(&self, region: Region) -> Result<Vec<Vec<char>>, InterpreterError> {
+        let grid = self.program.grid.lock()?;
+        Ok(grid[self.program.layer(region.z)]
+            .iter()
+            .skip(region.y)
+            .take(region.height)
+            .map(|row| {
+                row.iter()
+                    .skip(region.x)
+                    .take(region.width)
+                    .copied()
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// デバッガからセルを書き換える。グリッド外なら false
+    pub fn set_cell(&self, at: Position, value: char) -> Result<bool, InterpreterError> {
+        let mut grid = self.program.grid.lock()?;
+        let row = self.program.row(at.y, at.z);
+        match row.and_then(|row| grid[row].get_mut(at.x)) {
+            Some(cell) => {
+                *cell = value;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn add_breakpoint(&self, breakpoint: Breakpoint) -> Result<(), InterpreterError> {
+        self.breakpoints.lock()?.push(breakpoint);
         Ok(())
     }
 
-    /// ステップを進めるためのメソッド（外部から呼ばれる）
-    pub fn step(&self) -> Result<(), InterpreterError> {
-        let (lock, cvar) = &*self.step_mode;
-        let mut step = lock.lock().unwrap();
-        *step = false;
-        cvar.notify_one(); // 次のステップを実行させる
+    pub fn add_watchpoint(&self, watchpoint: Watchpoint) -> Result<(), InterpreterError> {
+        self.watchpoints.lock()?.push(watchpoint);
         Ok(())
     }
 
-    pub fn enable_step_mode(&self) {
-        let (lock, _) = &*self.step_mode;
-        let mut step = lock.lock().unwrap();
-        *step = true;
+    pub fn breakpoints(&self) -> Result<Vec<Breakpoint>, InterpreterError> {
+        Ok(self.breakpoints.lock()?.clone())
+    }
+
+    pub fn watchpoints(&self) -> Result<Vec<Watchpoint>, InterpreterError> {
+        Ok(self.watchpoints.lock()?.clone())
+    }
+
+    /// `index` 番目のブレークポイントを削除する。存在しなければ false
+    pub fn remove_breakpoint(&self, index: usize) -> Result<bool, InterpreterError> {
+        let mut breakpoints = self.breakpoints.lock()?;
+        if index < breakpoints.len() {
+            breakpoints.remove(index);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
     }
 
-    pub fn disable_step_mode(&self) {
-        let (lock, _) = &*self.step_mode;
-        let mut step = lock.lock().unwrap();
-        *step = false;
+    /// `index` 番目のウォッチポイントを削除する。存在しなければ false
+    pub fn remove_watchpoint(&self, index: usize) -> Result<bool, InterpreterError> {
+        let mut watchpoints = self.watchpoints.lock()?;
+        if index < watchpoints.len() {
+            watchpoints.remove(index);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn clear_breakpoints(&self) -> Result<(), InterpreterError> {
+        self.breakpoints.lock()?.clear();
+        self.watchpoints.lock()?.clear();
+        Ok(())
+    }
+
+    /// ブレークポイント・ウォッチポイントに掛かったことを通知し、ステップ実行モードに入る
+    fn hit(&self, ip: &Arc<Mutex<IPState>>, reason: &str) -> Result<(), InterpreterError> {
+        let (id, at) = {
+            let ip_locked = ip.lock()?;
+            (ip_locked.id, ip_locked.position())
+        };
+        let notice = format!(
+            "[*] IP {} at {}: {}",
+            id,
+            self.dimensions.format(at),
+            reason
+        );
+        let io_handler = self.io_handler.lock()?.clone();
+        if let Some(io_handler) = io_handler {
+            io_handler.write_error(&notice)?;
+        }
+        self.control.break_at(id)
+    }
+
+    /// `at` の `cmd` に掛かるブレークポイント
+    fn breakpoint_at(
+        &self,
+        at: Position,
+        cmd: char,
+        is_string_mode: bool,
+    ) -> Result<Option<Breakpoint>, InterpreterError> {
+        Ok(self
+            .breakpoints
+            .lock()?
+            .iter()
+            .find(|breakpoint| match breakpoint {
+                Breakpoint::Cell(cell) => *cell == at,
+                Breakpoint::Instruction(c) => !is_string_mode && *c == cmd,
+                _ => false,
+            })
+            .cloned())
+    }
+
+    fn has_breakpoint(&self, breakpoint: &Breakpoint) -> Result<bool, InterpreterError> {
+        Ok(self.breakpoints.lock()?.contains(breakpoint))
+    }
+
+    /// 実行前後のスタックトップで新たに条件を満たしたウォッチポイント
+    fn stack_watch_triggered(
+        &self,
+        before: Option<usize>,
+        after: Option<usize>,
+    ) -> Result<Option<Watchpoint>, InterpreterError> {
+        Ok(self
+            .watchpoints
+            .lock()?
+            .iter()
+            .find(|watch| watch.stack_top_matches(after) && !watch.stack_top_matches(before))
+            .cloned())
     }
 
     pub fn run(
         self: Arc<Self>,
         io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<(), InterpreterError> {
+        let result = self.run_all(io_handler);
+        self.control.set_finished()?;
+        for observer in &self.observers {
+            observer.finish()?;
+        }
+        result
+    }
+
+    fn run_all(
+        self: &Arc<Self>,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
         // get initial IPs
         let initial_ips = self.get_initial_ips()?;
 
-        //  add init IPs into ips
-        {
-            let mut ips_locked = self
-                .ips
-                .lock()
-                .map_err(|_| InterpreterError::ThreadError("Failed to lock ips".to_string()))?;
-            ips_locked.extend(initial_ips.clone());
-        }
-
-        //  run ips
+        // a session records input or replays it in place of the real one
+        let io_handler: Arc<dyn IOHandle + Send + Sync> = match &self.session_input {
+            Some(input) => Arc::new(SessionIO::new(io_handler, input.clone())),
+            None => io_handler,
+        };
+        // output is observed for run-until-output, input is recorded for reverse stepping
+        let io_handler: Arc<dyn IOHandle + Send + Sync> = Arc::new(ObservedIO::new(
+            io_handler,
+            self.control(),
+            self.history.clone(),
+        ));
+        *self.io_handler.lock()? = Some(io_handler.clone());
+
+        //  run ips (start_ip registers them into ips)
         for ip in initial_ips {
-            self.add_ip(ip.clone(), io_handler.clone())?;
+            self.start_ip(ip.clone(), io_handler.clone())?;
         }
+        self.control.set_started()?;
 
-        // wait for threads
-        let threads = {
-            let mut threads_locked = self
-                .threads
-                .lock()
-                .map_err(|_| InterpreterError::ThreadError("Failed to lock threads".to_string()))?;
-            threads_locked.drain(..).collect::<Vec<_>>()
-        };
+        // wait for threads, including IPs spawned while waiting
+        loop {
+            let threads = {
+                let mut threads_locked = self.threads.lock().map_err(|_| {
+                    InterpreterError::ThreadError("Failed to lock threads".to_string())
+                })?;
+                threads_locked.drain(..).collect::<Vec<_>>()
+            };
+            // the debugger may still revive an IP by stepping back
+            if threads.is_empty() && !self.control.wait_for_revival()? {
+                break;
+            }
 
-        for t in threads {
-            t.join()
-                .map_err(|_| InterpreterError::ThreadError("Failed to join thread".to_string()))?;
+            for t in threads {
+                t.join().map_err(|_| {
+                    InterpreterError::ThreadError("Failed to join thread".to_string())
+                })?;
+            }
         }
 
         Ok(())
@@ -130,9 +484,13 @@ impl Interpreter {
         let mut initial_ips = Vec::new();
         {
             let mut grid = self.program.grid.lock()?;
-            for y in 0..self.program.code_height {
+            for row in 0..grid.len() {
+                let (y, z) = self.program.layer_of(row);
+                if y >= self.program.code_height {
+                    continue;
+                }
                 for x in 0..self.program.code_width {
-                    let c = grid[y][x];
+                    let c = grid[row][x];
                     if c == '→' || c == '↓' || c == '↑' || c == '←' {
                         let direction = match c {
                             '→' => Direction::Right,
@@ -141,9 +499,10 @@ impl Interpreter {
                             '↓' => Direction::Down,
                             _ => unreachable!(),
                         };
-                        let ip = IPState::new(x, y, direction);
+                        let mut ip = IPState::new(x, y, direction);
+                        ip.ip_z = z;
                         initial_ips.push(Arc::new(Mutex::new(ip)));
-                        grid[y][x] = ' ';
+                        grid[row][x] = ' ';
                     }
                 }
             }
@@ -157,6 +516,214 @@ impl Interpreter {
         Ok(initial_ips)
     }
 
+    /// IP を登録してスレッドで実行する
+    fn start_ip(
+        &self,
+        new_ip: Arc<Mutex<IPState>>,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<(), InterpreterError> {
+        // assign id
+        {
+            let mut ip_locked = new_ip.lock()?;
+            ip_locked.id = self.next_ip_id.fetch_add(1, Ordering::SeqCst);
+        }
+
+        // add to ips
+        {
+            let mut ips_locked = self
+                .ips
+                .lock()
+                .map_err(|_| InterpreterError::ThreadError("Failed to lock ips".to_string()))?;
+            ips_locked.push(new_ip.clone());
+        }
+
+        self.spawn_ip_thread(new_ip, io_handler)
+    }
+
+    /// 登録済みの IP をスレッドで実行する
+    fn spawn_ip_thread(
+        &self,
+        new_ip: Arc<Mutex<IPState>>,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<(), InterpreterError> {
+        let id = new_ip.lock()?.id;
+        self.control.ip_started(id)?;
+
+        // prepare Interpreter, IOHandle
+        let interpreter_clone = Arc::clone(&Arc::new(self.clone()));
+        let io_handler_clone = io_handler.clone();
+        // prepare thread handle
+        let threads_clone = Arc::clone(&self.threads);
+
+        // spawn new thread
+        let handle = thread::spawn(move || {
+            if let Err(e) =
+                Arc::clone(&interpreter_clone).run_ip(new_ip.clone(), io_handler_clone.clone())
+            {
+                interpreter_clone.report_failure(e, io_handler_clone.clone());
+            }
+            let finished = match interpreter_clone.control.ip_finished(id) {
+                Ok(true) => interpreter_clone.warn(
+                    new_ip,
+                    RuntimeErrorKind::ReplayDiverged,
+                    io_handler_clone,
+                ),
+                other => other.map(|_| ()),
+            };
+            if let Err(e) = finished {
+                eprintln!("Thread Error: {}", e);
+            }
+        });
+
+        // handle threads
+        {
+            let mut threads_locked = threads_clone
+                .lock()
+                .map_err(|_| InterpreterError::ThreadError("Failed to lock threads".to_string()))?;
+            threads_locked.push(handle);
+        }
+
+        Ok(())
+    }
+
+    /// snapshot `ip` into a runtime error
+    fn capture_error(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        kind: RuntimeErrorKind,
+    ) -> Result<RuntimeError, InterpreterError> {
+        // read the grid directly: get_value reports out-of-bounds through runtime_error
+        let at = ip.lock()?.position();
+        let instruction = {
+            let grid = self.program.grid.lock()?;
+            self.program
+                .row(at.y, at.z)
+                .and_then(|row| grid[row].get(at.x))
+                .copied()
+                .unwrap_or(' ')
+        };
+        let ip_locked = ip.lock()?;
+        Ok(RuntimeError::new(
+            kind,
+            RuntimeContext::capture(&ip_locked, instruction).with_layer(self.layer_label(at.z)),
+        ))
+    }
+
+    /// report an error that stopped an IP thread
+    fn report_failure(&self, error: InterpreterError, io_handler: Arc<dyn IOHandle + Send + Sync>) {
+        let reported = match error {
+            InterpreterError::Runtime(error) => match self.program.grid.lock() {
+                Ok(grid) => {
+                    let layer = self.program.layer(error.context.z.unwrap_or(0));
+                    io_handler.display_diagnostic(&Diagnostic::error(*error, &grid[layer]))
+                }
+                Err(e) => Err(e.into()),
+            },
+            // reported by whoever checks the replay
+            InterpreterError::TraceMismatch(_) => Ok(()),
+            other => Err(other),
+        };
+        if let Err(e) = reported {
+            eprintln!("Thread Error: {}", e);
+        }
+    }
+
+    /// IP の現在位置と命令。読み込んだプログラムの外の Funge-Space は空白
+    fn fetch(&self, ip: &Arc<Mutex<IPState>>) -> Result<(Position, char), InterpreterError> {
+        // current (x, y, z)
+        let at = ip.lock()?.position();
+        let Position { x, y, z } = at;
+
+        // get cmd (x, y, z)
+        let grid_locked = self
+            .program
+            .grid
+            .lock()
+            .map_err(|_| InterpreterError::ThreadError("Failed to lock grid".to_string()))?;
+        let cmd = self
+            .program
+            .row(y, z)
+            .and_then(|row| grid_locked[row].get(x))
+            .copied()
+            .unwrap_or(' ');
+        Ok((at, cmd))
+    }
+
+    /// `cmd` を実行して IP を進める
+    fn execute_instruction(
+        &self,
+        ip: &Arc<Mutex<IPState>>,
+        cmd: char,
+        io_handler: &Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<(), InterpreterError> {
+        // string mode flag
+        let (is_string_mode, top_before) = {
+            let ip_locked = ip
+                .lock()
+                .map_err(|_| InterpreterError::ThreadError("Failed to lock IPState".to_string()))?;
+            (ip_locked.string_mode_active, ip_locked.stk.last().copied())
+        };
+
+        if is_string_mode {
+            if cmd == '"' {
+                //  toggle mode
+                let command = self.command_registry.get_command(cmd).unwrap();
+                command.execute(ip.clone(), self, io_handler.clone())?;
+            } else {
+                // push to stack
+                let ascii = cmd as usize;
+                {
+                    let mut ip_locked = ip.lock().map_err(|_| {
+                        InterpreterError::ThreadError("Failed to lock IPState".to_string())
+                    })?;
+                    ip_locked.stk.push(ascii);
+                }
+            }
+        } else {
+            // execute command
+            match self.command_registry.get_command(cmd) {
+                Some(command) => command.execute(ip.clone(), self, io_handler.clone())?,
+                // unknown command
+                None => self.semantics.on_unknown_instruction(
+                    ip.clone(),
+                    cmd,
+                    self,
+                    io_handler.clone(),
+                )?,
+            }
+        }
+
+        // 反射は命令の中で何度起きても1回だけ
+        // terminate breakpoint, stack watchpoints
+        let (terminated, top_after) = {
+            let mut ip_locked = ip.lock()?;
+            if std::mem::take(&mut ip_locked.reflect_pending) {
+                ip_locked.direction = ip_locked.direction.reverse();
+            }
+            (ip_locked.terminated, ip_locked.stk.last().copied())
+        };
+        if terminated && self.has_breakpoint(&Breakpoint::Terminate)? {
+            self.hit(ip, "terminated")?;
+        }
+        if let Some(watch) = self.stack_watch_triggered(top_before, top_after)? {
+            self.hit(ip, &format!("watchpoint on {}", watch))?;
+        }
+
+        // IP を移動
+        let _debug_output = if self.debug_mode {
+            Some(self.debug_output.lock()?)
+        } else {
+            None
+        };
+        self.move_ip(ip.clone())?;
+        if self.debug_mode {
+            self.dump_grid(ip.clone(), io_handler.clone())?;
+            self.dump_stack(ip.clone(), io_handler.clone())?;
+            io::stdout().flush().unwrap();
+        }
+        Ok(())
+    }
+
     pub fn dump_stack(
         &self,
         ip: Arc<Mutex<IPState>>,
@@ -181,20 +748,29 @@ impl Interpreter {
             .grid
             .lock()
             .map_err(|_| InterpreterError::ThreadError("Failed to lock grid".to_string()))?;
-        let (ip_x, ip_y) = {
-            let ip_locked = ip
-                .lock()
-                .map_err(|_| InterpreterError::ThreadError("Failed to lock IPState".to_string()))?;
-            (ip_locked.ip_x, ip_locked.ip_y)
-        };
-        io_handler.display_grid(&grid, ip_x, ip_y)
+        let Position { x, y, z } = ip
+            .lock()
+            .map_err(|_| InterpreterError::ThreadError("Failed to lock IPState".to_string()))?
+            .position();
+        io_handler.display_grid(&grid[self.program.layer(z)], x, y, self.layer_label(z))
     }
 }
 
 impl CommandGrid for Interpreter {
-    fn pop(&self, ip: Arc<Mutex<IPState>>) -> Result<usize, InterpreterError> {
-        let mut ip_locked = ip.lock()?;
-        Ok(ip_locked.stk.pop().unwrap_or(0))
+    fn pop(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<usize, InterpreterError> {
+        let popped = ip.lock()?.stk.pop();
+        match popped {
+            Some(value) => Ok(value),
+            None => self.semantics.on_stack_underflow(ip, self, io_handler),
+        }
+    }
+
+    fn semantics(&self) -> &Semantics {
+        &self.semantics
     }
 
     /// add new ip & run
@@ -203,36 +779,11 @@ impl CommandGrid for Interpreter {
         new_ip: Arc<Mutex<IPState>>,
         io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
-        // add to ips
-        {
-            let mut ips_locked = self
-                .ips
-                .lock()
-                .map_err(|_| InterpreterError::ThreadError("Failed to lock ips".to_string()))?;
-            ips_locked.push(new_ip.clone());
-        }
-
-        // prepare Interpreter, IOHandle
-        let interpreter_clone = Arc::clone(&Arc::new(self.clone()));
-        let io_handler_clone = io_handler.clone();
-        // prepare thread handle
-        let threads_clone = Arc::clone(&self.threads);
-
-        // spawn new thread
-        let handle = thread::spawn(move || {
-            if let Err(e) = interpreter_clone.run_ip(new_ip, io_handler_clone) {
-                eprintln!("Thread Error: {}", e);
-            }
-        });
-
-        // handle threads
-        {
-            let mut threads_locked = threads_clone
-                .lock()
-                .map_err(|_| InterpreterError::ThreadError("Failed to lock threads".to_string()))?;
-            threads_locked.push(handle);
+        self.start_ip(new_ip.clone(), io_handler)?;
+        History::record_spawn(new_ip.lock()?.id);
+        if self.has_breakpoint(&Breakpoint::Spawn)? {
+            self.hit(&new_ip, "spawned")?;
         }
-
         Ok(())
     }
 
@@ -245,6 +796,7 @@ impl CommandGrid for Interpreter {
         ip: Arc<Mutex<IPState>>,
         io_handler: Arc<dyn IOHandle + Send + Sync>,
     ) -> Result<(), InterpreterError> {
+        let id = ip.lock()?.id;
         loop {
             // is terminated
             if ip
@@ -255,161 +807,192 @@ impl CommandGrid for Interpreter {
                 break;
             }
 
-            // current (x, y)
-            let (x, y) = {
-                let ip_locked = ip.lock().map_err(|_| {
-                    InterpreterError::ThreadError("Failed to lock IPState".to_string())
-                })?;
-                (ip_locked.ip_x, ip_locked.ip_y)
-            };
-
-            // get cmd (x, y)
-            let cmd = {
-                let grid_locked = self.program.grid.lock().map_err(|_| {
-                    InterpreterError::ThreadError("Failed to lock grid".to_string())
-                })?;
-                if y >= grid_locked.len() || x >= grid_locked[0].len() {
-                    io_handler.write_error(&format!("IP out of bounds: x={}, y={}", x, y))?;
-                    // IP を終了
-                    {
-                        let mut ip_locked = ip.lock().map_err(|_| {
-                            InterpreterError::ThreadError("Failed to lock IPState".to_string())
-                        })?;
-                        ip_locked.terminated = true;
-                    }
-                    break;
-                }
-                grid_locked[y][x]
-            };
-
-            // string mode flag
-            let is_string_mode = {
-                let ip_locked = ip.lock().map_err(|_| {
-                    InterpreterError::ThreadError("Failed to lock IPState".to_string())
-                })?;
-                ip_locked.string_mode_active
-            };
+            // breakpoint: pause before executing
+            let (at, cmd) = self.fetch(&ip)?;
+            let is_string_mode = ip.lock()?.string_mode_active;
+            if let Some(breakpoint) = self.breakpoint_at(at, cmd, is_string_mode)? {
+                self.hit(&ip, &format!("breakpoint on {}", breakpoint))?;
+            }
 
-            if is_string_mode {
-                if cmd == '"' {
-                    //  toggle mode
-                    let command = self.command_registry.get_command(cmd).unwrap();
-                    command.execute(ip.clone(), self.as_ref(), io_handler.clone())?;
-                } else {
-                    // push to stack
-                    let ascii = cmd as usize;
-                    {
-                        let mut ip_locked = ip.lock().map_err(|_| {
-                            InterpreterError::ThreadError("Failed to lock IPState".to_string())
-                        })?;
-                        ip_locked.stk.push(ascii);
-                    }
-                }
-            } else {
-                // execute command
-                let command = match self.command_registry.get_command(cmd) {
-                    Some(cmd) => cmd,
-                    None => {
-                        // ignore unknown command
-                        self.move_ip(ip.clone())?;
-                        continue;
-                    }
-                };
-                // execute
-                command.execute(ip.clone(), self.as_ref(), io_handler.clone())?;
+            // wait while paused by the debugger, or for this IP's turn in a session
+            let _turn = self.control.checkpoint(id)?;
+            // undone by the debugger
+            if ip.lock()?.terminated {
+                break;
             }
 
-            // IP を移動
-            self.move_ip(ip.clone())?;
-            if self.debug_mode {
-                self.dump_grid(ip.clone(), io_handler.clone())?;
-                self.dump_stack(ip.clone(), io_handler.clone())?;
-                io::stdout().flush().unwrap();
+            // the debugger may have moved the IP or rewritten the grid meanwhile
+            let (_, cmd) = self.fetch(&ip)?;
+
+            // step limit
+            let step = self.steps.fetch_add(1, Ordering::SeqCst) + 1;
+            if let Some(max_steps) = self.max_steps {
+                if step > max_steps {
+                    return Err(self.runtime_error(
+                        ip.clone(),
+                        RuntimeErrorKind::LimitExceeded {
+                            limit: "step",
+                            value: max_steps,
+                        },
+                    ));
+                }
             }
 
-            if self.is_step_mode_active() {
-                {
-                    println!("Press Enter to execute the next IP, or type 'q' to quit:");
-                    print!("$ > ");
-                    io::stdout().flush().unwrap();
-                    self.wait_for_step()?;
-                    self.enable_step_mode();
+            // record the instruction for the debugger history and the observers
+            let start = if self.history.is_some() || !self.observers.is_empty() {
+                Some(StepStart::begin(step, &*ip.lock()?, cmd))
+            } else {
+                None
+            };
+            let result = self.execute_instruction(&ip, cmd, &io_handler);
+            if let Some(start) = start {
+                let record = start.end(&*ip.lock()?);
+                for observer in &self.observers {
+                    observer.on_step(&record)?;
+                }
+                if let Some(history) = &self.history {
+                    history.push(record)?;
                 }
             }
+            result?;
         }
 
         Ok(())
     }
 
     fn move_ip(&self, ip: Arc<Mutex<IPState>>) -> Result<(), InterpreterError> {
-        if self.debug_mode {
-            // ロックを取得して現在の位置を取得し、ロックを解除
-            let (_current_x, _current_y) = {
-                let ip_locked = ip.lock().map_err(|_| {
-                    InterpreterError::ThreadError("Failed to lock IPState".to_string())
+        // グリッドの大きさを取得し、ロックを解除
+        let size = {
+            let grid_locked =
+                self.program.grid.lock().map_err(|_| {
+                    InterpreterError::ThreadError("Failed to lock grid".to_string())
                 })?;
-                (ip_locked.ip_x, ip_locked.ip_y)
-            };
+            Size::new(
+                grid_locked.first().map_or(0, Vec::len),
+                self.program.layer_rows(),
+                self.program.code_depth,
+            )
+        };
 
-            // 再度ロックを取得して IP を移動
-            let mut ip_locked = ip
-                .lock()
-                .map_err(|_| InterpreterError::ThreadError("Failed to lock IPState".to_string()))?;
-            println!("Executed At ({}, {})", ip_locked.ip_x, ip_locked.ip_y);
-
-            let new_x = (ip_locked.ip_x as isize + ip_locked.direction.dx())
-                .rem_euclid(Self::WIDTH as isize);
-            let new_y = (ip_locked.ip_y as isize + ip_locked.direction.dy())
-                .rem_euclid(Self::HEIGHT as isize);
-            ip_locked.ip_x = new_x as usize;
-            ip_locked.ip_y = new_y as usize;
-        } else {
-            let mut ip_locked = ip
-                .lock()
-                .map_err(|_| InterpreterError::ThreadError("Failed to lock IPState".to_string()))?;
-            let new_x = (ip_locked.ip_x as isize + ip_locked.direction.dx())
-                .rem_euclid(Self::WIDTH as isize);
-            let new_y = (ip_locked.ip_y as isize + ip_locked.direction.dy())
-                .rem_euclid(Self::HEIGHT as isize);
-            ip_locked.ip_x = new_x as usize;
-            ip_locked.ip_y = new_y as usize;
+        let mut ip_locked = ip
+            .lock()
+            .map_err(|_| InterpreterError::ThreadError("Failed to lock IPState".to_string()))?;
+        if self.debug_mode {
+            println!(
+                "[IP {}] Executed At {}",
+                ip_locked.id,
+                self.dimensions.format(ip_locked.position())
+            );
+        }
+        ip_locked.remember_position();
+
+        // 壁で止まる topology では IP を終了
+        match self
+            .topology
+            .step(ip_locked.position(), ip_locked.direction, size)
+        {
+            Some((at, direction)) => {
+                ip_locked.set_position(at);
+                ip_locked.direction = direction;
+            }
+            None => ip_locked.terminated = true,
         }
         Ok(())
     }
 
-    fn set_value(&self, x: usize, y: usize, value: char) -> Result<(), InterpreterError> {
-        let mut grid = self
-            .program
-            .grid
-            .lock()
-            .map_err(|_| InterpreterError::ThreadError("Failed to lock grid".to_string()))?;
-        let sized_x: usize = (x as usize).try_into().unwrap();
-        let sized_y: usize = (y as usize).try_into().unwrap();
-        if sized_y < grid.len() && sized_x < grid[sized_y].len() {
-            grid[sized_y][sized_x] = value;
-            Ok(())
-        } else {
-            Err(InterpreterError::ThreadError(format!(
-                "Attempt to set value out of bounds at ({}, {})",
-                x, y
-            )))
+    fn runtime_error(&self, ip: Arc<Mutex<IPState>>, kind: RuntimeErrorKind) -> InterpreterError {
+        match self.capture_error(ip, kind) {
+            Ok(error) => error.into(),
+            Err(e) => e,
         }
     }
 
-    fn get_value(&self, x: usize, y: usize) -> Result<char, InterpreterError> {
-        let grid = self
-            .program
-            .grid
-            .lock()
-            .map_err(|_| InterpreterError::ThreadError("Failed to lock grid".to_string()))?;
+    fn warn(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        kind: RuntimeErrorKind,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<(), InterpreterError> {
+        let error = self.capture_error(ip, kind)?;
+        let diagnostic = {
+            let grid = self.program.grid.lock()?;
+            let layer = self.program.layer(error.context.z.unwrap_or(0));
+            Diagnostic::warning(error, &grid[layer])
+        };
+        io_handler.display_diagnostic(&diagnostic)
+    }
 
-        if y < grid.len() && x < grid[y].len() {
-            Ok(grid[y][x])
-        } else {
-            Err(InterpreterError::ThreadError(format!(
-                "Attempt to get value out of bounds at ({}, {})",
-                x, y
-            )))
+    fn set_value(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        at: Position,
+        value: char,
+    ) -> Result<(), InterpreterError> {
+        let Position { x, y, z } = at;
+        let written = {
+            let mut grid =
+                self.program.grid.lock().map_err(|_| {
+                    InterpreterError::ThreadError("Failed to lock grid".to_string())
+                })?;
+            let row = self.program.row(y, z).filter(|&row| x < grid[row].len());
+            if let Some(row) = row {
+                History::record_write(at, grid[row][x], value);
+                grid[row][x] = value;
+            }
+            row.is_some()
+        };
+        if !written {
+            return Err(self.runtime_error(
+                ip,
+                RuntimeErrorKind::OutOfBounds {
+                    access: GridAccess::Put,
+                    x,
+                    y,
+                    z: self.layer_label(z),
+                },
+            ));
+        }
+
+        // write watchpoints
+        let watch = self
+            .watchpoints
+            .lock()?
+            .iter()
+            .find(|watch| matches!(watch, Watchpoint::Write(region) if region.contains(at)))
+            .cloned();
+        if let Some(watch) = watch {
+            self.hit(
+                &ip,
+                &format!(
+                    "watchpoint on {}: wrote {:?} at {}",
+                    watch,
+                    value,
+                    self.dimensions.format(at)
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn get_value(&self, ip: Arc<Mutex<IPState>>, at: Position) -> Result<char, InterpreterError> {
+        let Position { x, y, z } = at;
+        {
+            let grid =
+                self.program.grid.lock().map_err(|_| {
+                    InterpreterError::ThreadError("Failed to lock grid".to_string())
+                })?;
+            if let Some(&cell) = self.program.row(y, z).and_then(|row| grid[row].get(x)) {
+                return Ok(cell);
+            }
         }
+        Err(self.runtime_error(
+            ip,
+            RuntimeErrorKind::OutOfBounds {
+                access: GridAccess::Get,
+                x,
+                y,
+                z: self.layer_label(z),
+            },
+        ))
     }
 }
@@ -1,2 +1,13 @@
 pub mod commands;
+pub mod control;
+pub mod coverage;
+pub mod history;
 pub mod interpreter;
+pub mod profile;
+pub mod replay;
+pub mod semantics;
+pub mod session;
+#[cfg(test)]
+pub mod testing;
+pub mod topology;
+pub mod trace;
@@ -0,0 +1,343 @@
+// src/engine/profile.rs
+
+use crate::engine::history::StepRecord;
+use crate::errors::InterpreterError;
+use crate::interfaces::StepObserver;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Execution counts gathered by a [`Profiler`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub total: usize,
+    /// executions per cell
+    pub cells: HashMap<(usize, usize), usize>,
+    pub instructions: BTreeMap<char, usize>,
+    pub ips: BTreeMap<usize, usize>,
+    /// writes by `p` per cell
+    pub writes: HashMap<(usize, usize), usize>,
+}
+
+/// Counts every executed instruction.
+#[derive(Default)]
+pub struct Profiler {
+    profile: Mutex<Profile>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn profile(&self) -> Result<Profile, InterpreterError> {
+        Ok(self.profile.lock()?.clone())
+    }
+}
+
+impl StepObserver for Profiler {
+    fn on_step(&self, record: &StepRecord) -> Result<(), InterpreterError> {
+        let mut profile = self.profile.lock()?;
+        profile.total += 1;
+        *profile.cells.entry((record.x, record.y)).or_default() += 1;
+        *profile.instructions.entry(record.instruction).or_default() += 1;
+        *profile.ips.entry(record.ip_id).or_default() += 1;
+        for write in &record.writes {
+            *profile.writes.entry((write.x, write.y)).or_default() += 1;
+        }
+        Ok(())
+    }
+}
+
+/// dark red to yellow, 256-color backgrounds
+const HEAT_COLORS: [u8; 9] = [52, 88, 124, 160, 196, 202, 208, 214, 226];
+/// density characters for terminals without color
+const HEAT_CHARS: [char; 9] = ['.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+impl Profile {
+    /// 0..=1 on a log scale, so that cold code is still visible next to hot loops
+    fn heat(&self, count: usize) -> f64 {
+        let max = self.cells.values().copied().max().unwrap_or(0);
+        if max == 0 {
+            return 0.0;
+        }
+        (1.0 + count as f64).ln() / (1.0 + max as f64).ln()
+    }
+
+    fn level(&self, count: usize) -> usize {
+        ((self.heat(count) * (HEAT_COLORS.len() - 1) as f64).round() as usize)
+            .min(HEAT_COLORS.len() - 1)
+    }
+
+    /// cells by execution count, hottest first
+    fn hottest(&self) -> Vec<((usize, usize), usize)> {
+        let mut cells: Vec<_> = self.cells.iter().map(|(cell, n)| (*cell, *n)).collect();
+        cells.sort_by(|(a, n), (b, m)| m.cmp(n).then((a.1, a.0).cmp(&(b.1, b.0))));
+        cells
+    }
+
+    /// rows and columns needed to show the program and every executed cell
+    pub fn extent(&self, width: usize, height: usize) -> (usize, usize) {
+        self.cells
+            .keys()
+            .chain(self.writes.keys())
+            .fold((width, height), |(w, h), (x, y)| {
+                (w.max(x + 1), h.max(y + 1))
+            })
+    }
+
+    fn share(&self, count: usize) -> String {
+        format!("{:.1}%", 100.0 * count as f64 / self.total.max(1) as f64)
+    }
+
+    /// hottest cells, instruction histogram, steps per IP and written cells
+    pub fn summary(&self, grid: &[Vec<char>], top: usize) -> String {
+        let mut out = format!("profile: {} steps, {} IP(s)\n", self.total, self.ips.len());
+
+        out.push_str("\nhottest cells\n");
+        out.push_str(&format!(
+            "  {:<12} {:<6} {:>10} {:>7}\n",
+            "cell", "char", "count", "share"
+        ));
+        for ((x, y), count) in self.hottest().into_iter().take(top) {
+            out.push_str(&format!(
+                "  {:<12} {:<6} {:>10} {:>7}\n",
+                format!("({}, {})", x, y),
+                format!("{:?}", cell_at(grid, x, y)),
+                count,
+                self.share(count)
+            ));
+        }
+
+        out.push_str("\ninstructions\n");
+        out.push_str(&format!("  {:<6} {:>10} {:>7}\n", "char", "count", "share"));
+        let mut instructions: Vec<_> = self.instructions.iter().collect();
+        instructions.sort_by(|(a, n), (b, m)| m.cmp(n).then(a.cmp(b)));
+        for (c, count) in instructions {
+            out.push_str(&format!(
+                "  {:<6} {:>10} {:>7}\n",
+                format!("{:?}", c),
+                count,
+                self.share(*count)
+            ));
+        }
+
+        out.push_str("\nsteps per IP\n");
+        out.push_str(&format!("  {:<6} {:>10} {:>7}\n", "ip", "steps", "share"));
+        for (ip, count) in &self.ips {
+            out.push_str(&format!(
+                "  {:<6} {:>10} {:>7}\n",
+                ip,
+                count,
+                self.share(*count)
+            ));
+        }
+
+        if !self.writes.is_empty() {
+            out.push_str("\ncells written by `p`\n");
+            out.push_str(&format!("  {:<12} {:>10}\n", "cell", "writes"));
+            let mut writes: Vec<_> = self.writes.iter().collect();
+            writes.sort_by(|(a, n), (b, m)| m.cmp(n).then((a.1, a.0).cmp(&(b.1, b.0))));
+            for ((x, y), count) in writes.into_iter().take(top) {
+                out.push_str(&format!(
+                    "  {:<12} {:>10}\n",
+                    format!("({}, {})", x, y),
+                    count
+                ));
+            }
+        }
+        out
+    }
+
+    /// the grid with executed cells colored by heat, or next to a density map without color
+    pub fn heatmap(&self, grid: &[Vec<char>], color: bool) -> String {
+        let (width, height) = self.extent(grid.first().map_or(0, Vec::len), grid.len());
+        let mut out = String::new();
+        for y in 0..height {
+            let mut code = String::new();
+            let mut heat = String::new();
+            for x in 0..width {
+                let c = printable(cell_at(grid, x, y));
+                let count = self.cells.get(&(x, y)).copied().unwrap_or(0);
+                if count == 0 {
+                    code.push_str(&format!(" {} ", c));
+                    heat.push(' ');
+                } else if color {
+                    code.push_str(&format!(
+                        "\x1b[48;5;{}m\x1b[30m {} \x1b[0m",
+                        HEAT_COLORS[self.level(count)],
+                        c
+                    ));
+                } else {
+                    code.push_str(&format!(" {} ", c));
+                    heat.push(HEAT_CHARS[self.level(count)]);
+                }
+            }
+            if color {
+                out.push_str(&format!("{:5} {}\n", y, code));
+            } else {
+                out.push_str(&format!("{:5} {} | {}\n", y, code, heat));
+            }
+        }
+        if !color {
+            out.push_str(&format!(
+                "      heat: cold {} hot, blank = never executed\n",
+                HEAT_CHARS.iter().collect::<String>()
+            ));
+        }
+        out
+    }
+
+    /// self-contained HTML page with the heatmap and the summary tables
+    pub fn html(&self, grid: &[Vec<char>], title: &str) -> String {
+        let (width, height) = self.extent(grid.first().map_or(0, Vec::len), grid.len());
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str(&format!(
+            "<title>Profile of {}</title>\n",
+            escape_html(title)
+        ));
+        out.push_str(
+            "<style>\n\
+             body { font-family: sans-serif; margin: 2em; }\n\
+             table.grid { border-collapse: collapse; font-family: monospace; }\n\
+             table.grid td { width: 1.4em; height: 1.4em; text-align: center; border: 1px solid #eee; }\n\
+             table.grid td.written { outline: 2px solid #36c; outline-offset: -2px; }\n\
+             table.grid th { color: #999; font-weight: normal; padding-right: 0.5em; }\n\
+             pre { background: #f6f6f6; padding: 1em; }\n\
+             </style>\n</head>\n<body>\n",
+        );
+        out.push_str(&format!(
+            "<h1>Profile of {}</h1>\n<p>{} steps, {} IP(s). Hover over a cell for its counts; \
+             cells written by <code>p</code> are outlined.</p>\n",
+            escape_html(title),
+            self.total,
+            self.ips.len()
+        ));
+        out.push_str("<table class=\"grid\">\n");
+        for y in 0..height {
+            out.push_str(&format!("<tr><th>{}</th>", y));
+            for x in 0..width {
+                let c = cell_at(grid, x, y);
+                let count = self.cells.get(&(x, y)).copied().unwrap_or(0);
+                let writes = self.writes.get(&(x, y)).copied().unwrap_or(0);
+                let style = if count > 0 {
+                    // blue (cold) to red (hot)
+                    format!(
+                        " style=\"background: hsl({:.0}, 85%, 60%)\"",
+                        240.0 * (1.0 - self.heat(count))
+                    )
+                } else {
+                    String::new()
+                };
+                let class = if writes > 0 { " class=\"written\"" } else { "" };
+                out.push_str(&format!(
+                    "<td{}{} title=\"({}, {}) {}: {} executions, {} writes\">{}</td>",
+                    class,
+                    style,
+                    x,
+                    y,
+                    escape_html(&format!("{:?}", c)),
+                    count,
+                    writes,
+                    escape_html(&printable(c).to_string())
+                ));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n");
+        out.push_str(&format!(
+            "<pre>{}</pre>\n</body>\n</html>\n",
+            escape_html(&self.summary(grid, 20))
+        ));
+        out
+    }
+}
+
+fn cell_at(grid: &[Vec<char>], x: usize, y: usize) -> char {
+    grid.get(y)
+        .and_then(|row| row.get(x))
+        .copied()
+        .unwrap_or(' ')
+}
+
+/// cells holding control characters (written by `p`) are shown as `·`
+fn printable(c: char) -> char {
+    if c.is_control() {
+        '·'
+    } else {
+        c
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::history::GridWrite;
+    use crate::entities::Direction;
+
+    fn record(ip_id: usize, x: usize, instruction: char) -> StepRecord {
+        StepRecord {
+            step: 0,
+            ip_id,
+            instruction,
+            x,
+            y: 0,
+            z: 0,
+            direction: Direction::Right,
+            string_mode: false,
+            stack_kept: 0,
+            popped: vec![],
+            pushed: vec![],
+            writes: vec![],
+            spawned: vec![],
+            input: vec![],
+            output: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_counts_and_reports() {
+        let profiler = Profiler::new();
+        for _ in 0..3 {
+            profiler.on_step(&record(0, 0, '>')).unwrap();
+        }
+        let mut put = record(1, 1, 'p');
+        put.writes.push(GridWrite {
+            x: 2,
+            y: 1,
+            z: 0,
+            old: ' ',
+            new: '<',
+        });
+        profiler.on_step(&put).unwrap();
+
+        let profile = profiler.profile().unwrap();
+        assert_eq!(profile.total, 4);
+        assert_eq!(profile.cells[&(0, 0)], 3);
+        assert_eq!(profile.instructions[&'p'], 1);
+        assert_eq!(profile.ips[&1], 1);
+        assert_eq!(profile.writes[&(2, 1)], 1);
+        // the written cell is part of the map
+        assert_eq!(profile.extent(1, 1), (3, 2));
+
+        let grid = vec![vec!['>', 'p', ' ']];
+        let summary = profile.summary(&grid, 1);
+        assert!(summary.contains("profile: 4 steps, 2 IP(s)"));
+        assert!(summary.contains("(0, 0)       '>'             3   75.0%"));
+        assert!(!summary.contains("(1, 0)       'p'"));
+
+        let heatmap = profile.heatmap(&grid, false);
+        assert!(heatmap.starts_with("    0  >  p     | @+ \n"));
+
+        let html = profile.html(&grid, "a<b>.bf");
+        assert!(html.contains("<title>Profile of a&lt;b&gt;.bf</title>"));
+        assert!(html.contains("class=\"written\""));
+    }
+}
@@ -0,0 +1,275 @@
+// src/engine/replay.rs
+
+use crate::engine::history::StepRecord;
+use crate::engine::trace::TraceEvent;
+use crate::errors::{format_cell, InterpreterError};
+use crate::interfaces::StepObserver;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::Mutex;
+
+/// First instruction at which two executions differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub ip: usize,
+    /// instructions of `ip` that matched before this one
+    pub index: usize,
+    /// None when that side has no more instructions for `ip`
+    pub expected: Option<TraceEvent>,
+    pub actual: Option<TraceEvent>,
+}
+
+impl Divergence {
+    pub fn step(&self) -> usize {
+        self.expected
+            .iter()
+            .chain(self.actual.iter())
+            .map(|event| event.step)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// both states side by side, differing rows marked with `*`
+    pub fn render(&self, left: &str, right: &str) -> String {
+        let left_rows = rows(self.expected.as_ref());
+        let right_rows = rows(self.actual.as_ref());
+        let width = left_rows
+            .iter()
+            .map(|(_, value)| value.chars().count())
+            .chain([left.chars().count(), 12])
+            .max()
+            .unwrap_or(12);
+
+        let mut out = format!(
+            "first divergence at step {}: ip {}, after {} matching instruction(s) of that IP\n",
+            self.step(),
+            self.ip,
+            self.index
+        );
+        out.push_str(&format!(
+            "  {:<10} {:<width$}  {}\n",
+            "",
+            left,
+            right,
+            width = width
+        ));
+        for ((label, l), (_, r)) in left_rows.iter().zip(right_rows.iter()) {
+            let marker = if l == r { ' ' } else { '*' };
+            out.push_str(&format!(
+                "{} {:<10} {:<width$}  {}\n",
+                marker,
+                label,
+                l,
+                r,
+                width = width
+            ));
+        }
+        out
+    }
+}
+
+/// `(x, y)`, or `(x, y, z)` off the first layer like the trace itself
+fn layered(x: usize, y: usize, z: usize) -> String {
+    format_cell(x, y, (z > 0).then_some(z))
+}
+
+fn rows(event: Option<&TraceEvent>) -> Vec<(&'static str, String)> {
+    let labels = [
+        "step",
+        "ip",
+        "position",
+        "char",
+        "direction",
+        "popped",
+        "pushed",
+        "writes",
+        "output",
+    ];
+    let values = match event {
+        Some(event) => vec![
+            event.step.to_string(),
+            event.ip.to_string(),
+            layered(event.x, event.y, event.z),
+            format!("{:?}", event.instruction),
+            event.direction.to_string(),
+            format!("{:?}", event.popped),
+            format!("{:?}", event.pushed),
+            format!(
+                "[{}]",
+                event
+                    .writes
+                    .iter()
+                    .map(|write| {
+                        format!("{}={:?}", layered(write.x, write.y, write.z), write.value)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            format!("{:?}", event.output),
+        ],
+        None => {
+            let mut values = vec!["-".to_string(); labels.len()];
+            values[0] = "(ended)".to_string();
+            values
+        }
+    };
+    labels.into_iter().zip(values).collect()
+}
+
+fn by_ip(events: &[TraceEvent]) -> BTreeMap<usize, Vec<&TraceEvent>> {
+    let mut map: BTreeMap<usize, Vec<&TraceEvent>> = BTreeMap::new();
+    for event in events {
+        map.entry(event.ip).or_default().push(event);
+    }
+    map
+}
+
+/// IPs run on their own threads, so executions are compared per IP;
+/// the divergence with the lowest step wins.
+pub fn first_divergence(expected: &[TraceEvent], actual: &[TraceEvent]) -> Option<Divergence> {
+    let expected = by_ip(expected);
+    let actual = by_ip(actual);
+    let ips: BTreeSet<usize> = expected.keys().chain(actual.keys()).copied().collect();
+    ips.into_iter()
+        .filter_map(|ip| {
+            let left = expected.get(&ip).map(Vec::as_slice).unwrap_or_default();
+            let right = actual.get(&ip).map(Vec::as_slice).unwrap_or_default();
+            let index = left
+                .iter()
+                .zip(right.iter())
+                .take_while(|(l, r)| l.same_execution(r))
+                .count();
+            if index == left.len() && index == right.len() {
+                return None;
+            }
+            Some(Divergence {
+                ip,
+                index,
+                expected: left.get(index).map(|event| (*event).clone()),
+                actual: right.get(index).map(|event| (*event).clone()),
+            })
+        })
+        .min_by_key(Divergence::step)
+}
+
+#[derive(Default)]
+struct ReplayState {
+    /// instructions each IP has yet to execute
+    expected: BTreeMap<usize, VecDeque<TraceEvent>>,
+    matched: BTreeMap<usize, usize>,
+    divergence: Option<Divergence>,
+}
+
+/// Checks a run against a recorded (unfiltered) trace, stopping every IP at the first mismatch.
+pub struct TraceReplay {
+    state: Mutex<ReplayState>,
+}
+
+impl TraceReplay {
+    pub fn new(trace: Vec<TraceEvent>) -> Self {
+        let mut state = ReplayState::default();
+        for event in trace {
+            state.expected.entry(event.ip).or_default().push_back(event);
+        }
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+
+    /// instructions that matched the trace
+    pub fn matched(&self) -> Result<usize, InterpreterError> {
+        Ok(self.state.lock()?.matched.values().sum())
+    }
+
+    /// the first mismatch, or the earliest recorded instruction that never ran (after the run)
+    pub fn divergence(&self) -> Result<Option<Divergence>, InterpreterError> {
+        let state = self.state.lock()?;
+        if let Some(divergence) = &state.divergence {
+            return Ok(Some(divergence.clone()));
+        }
+        Ok(state
+            .expected
+            .iter()
+            .filter_map(|(ip, queue)| {
+                queue.front().map(|event| Divergence {
+                    ip: *ip,
+                    index: state.matched.get(ip).copied().unwrap_or(0),
+                    expected: Some(event.clone()),
+                    actual: None,
+                })
+            })
+            .min_by_key(Divergence::step))
+    }
+}
+
+impl StepObserver for TraceReplay {
+    fn on_step(&self, record: &StepRecord) -> Result<(), InterpreterError> {
+        let mut state = self.state.lock()?;
+        if let Some(divergence) = &state.divergence {
+            return Err(InterpreterError::TraceMismatch(divergence.step()));
+        }
+        let actual = TraceEvent::from(record);
+        let expected = state
+            .expected
+            .get_mut(&actual.ip)
+            .and_then(VecDeque::pop_front);
+        match expected {
+            Some(expected) if expected.same_execution(&actual) => {
+                *state.matched.entry(actual.ip).or_default() += 1;
+                Ok(())
+            }
+            expected => {
+                let divergence = Divergence {
+                    ip: actual.ip,
+                    index: state.matched.get(&actual.ip).copied().unwrap_or(0),
+                    expected,
+                    actual: Some(actual),
+                };
+                let step = divergence.step();
+                state.divergence = Some(divergence);
+                Err(InterpreterError::TraceMismatch(step))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::Direction;
+
+    fn event(step: usize, ip: usize, instruction: char) -> TraceEvent {
+        TraceEvent {
+            step,
+            ip,
+            x: 0,
+            y: 0,
+            z: 0,
+            instruction,
+            direction: Direction::Right,
+            popped: vec![],
+            pushed: vec![],
+            writes: vec![],
+            output: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_first_divergence_per_ip() {
+        // the two IPs interleave differently, which is not a divergence
+        let left = vec![event(1, 0, '1'), event(2, 1, '2'), event(3, 0, '3')];
+        let right = vec![event(1, 1, '2'), event(2, 0, '1'), event(3, 0, '3')];
+        assert_eq!(first_divergence(&left, &right), None);
+
+        let right = vec![event(1, 0, '1'), event(2, 1, '2'), event(3, 0, '4')];
+        let divergence = first_divergence(&left, &right).unwrap();
+        assert_eq!((divergence.ip, divergence.index), (0, 1));
+        assert_eq!(divergence.step(), 3);
+        let rendered = divergence.render("left", "right");
+        assert!(rendered.contains("* char       '3'"));
+        assert!(rendered.contains("  ip         0"));
+
+        // one side stops early
+        let divergence = first_divergence(&left, &left[..2]).unwrap();
+        assert_eq!(divergence.actual, None);
+    }
+}
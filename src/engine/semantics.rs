@@ -0,0 +1,171 @@
+// src/engine/semantics.rs
+
+use crate::engine::commands::command::CommandGrid;
+use crate::entities::IPState;
+use crate::errors::{InterpreterError, RuntimeErrorKind};
+use crate::interfaces::IOHandle;
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// What `/` and `%` do when the divisor is zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DivisionByZeroPolicy {
+    /// push 0
+    PushZero,
+    /// ask the user for the result (Befunge-93)
+    Prompt,
+    /// push nothing and reverse the IP
+    Reflect,
+    /// stop the IP with a runtime error
+    Error,
+    /// push 0 and print a warning the first time it happens
+    #[default]
+    WarnOnce,
+}
+
+/// What popping an empty stack does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum StackUnderflowPolicy {
+    /// pop 0
+    #[default]
+    PushZero,
+    /// pop 0 and reverse the IP
+    Reflect,
+    /// stop the IP with a runtime error
+    Error,
+    /// pop 0 and print a warning the first time it happens
+    WarnOnce,
+}
+
+/// What a cell with no registered command does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum UnknownInstructionPolicy {
+    /// skip the cell
+    #[default]
+    Ignore,
+    /// reverse the IP (Funge-98)
+    Reflect,
+    /// skip the cell and print a warning the first time each character is seen
+    Warn,
+    /// stop the IP with a runtime error
+    Error,
+}
+
+/// Dialect choices shared by every command of one interpreter.
+#[derive(Debug, Default)]
+pub struct Semantics {
+    pub division_by_zero: DivisionByZeroPolicy,
+    pub stack_underflow: StackUnderflowPolicy,
+    pub unknown_instruction: UnknownInstructionPolicy,
+    division_by_zero_warned: AtomicBool,
+    stack_underflow_warned: AtomicBool,
+    unknown_instructions_warned: Mutex<HashSet<char>>,
+}
+
+impl Semantics {
+    pub fn new(
+        division_by_zero: DivisionByZeroPolicy,
+        stack_underflow: StackUnderflowPolicy,
+        unknown_instruction: UnknownInstructionPolicy,
+    ) -> Self {
+        Self {
+            division_by_zero,
+            stack_underflow,
+            unknown_instruction,
+            ..Self::default()
+        }
+    }
+
+    /// result of `b / 0` or `b % 0`, `None` when nothing should be pushed
+    pub fn on_division_by_zero(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        interpreter: &dyn CommandGrid,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<Option<usize>, InterpreterError> {
+        match self.division_by_zero {
+            DivisionByZeroPolicy::PushZero => Ok(Some(0)),
+            DivisionByZeroPolicy::Prompt => {
+                let (x, y) = {
+                    let ip_locked = ip.lock()?;
+                    (ip_locked.ip_x, ip_locked.ip_y)
+                };
+                io_handler.write_error(&format!(
+                    "Division by zero at ({}, {}). What result do you want?",
+                    x, y
+                ))?;
+                Ok(Some(io_handler.read_number()?))
+            }
+            DivisionByZeroPolicy::Reflect => {
+                reflect(&ip)?;
+                Ok(None)
+            }
+            DivisionByZeroPolicy::Error => {
+                Err(interpreter.runtime_error(ip, RuntimeErrorKind::DivisionByZero))
+            }
+            DivisionByZeroPolicy::WarnOnce => {
+                if !self.division_by_zero_warned.swap(true, Ordering::SeqCst) {
+                    interpreter.warn(ip, RuntimeErrorKind::DivisionByZero, io_handler)?;
+                }
+                Ok(Some(0))
+            }
+        }
+    }
+
+    /// value popped from an empty stack
+    pub fn on_stack_underflow(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        interpreter: &dyn CommandGrid,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<usize, InterpreterError> {
+        match self.stack_underflow {
+            StackUnderflowPolicy::PushZero => Ok(0),
+            StackUnderflowPolicy::Reflect => {
+                reflect(&ip)?;
+                Ok(0)
+            }
+            StackUnderflowPolicy::Error => {
+                Err(interpreter.runtime_error(ip, RuntimeErrorKind::StackUnderflow))
+            }
+            StackUnderflowPolicy::WarnOnce => {
+                if !self.stack_underflow_warned.swap(true, Ordering::SeqCst) {
+                    interpreter.warn(ip, RuntimeErrorKind::StackUnderflow, io_handler)?;
+                }
+                Ok(0)
+            }
+        }
+    }
+
+    /// handle `instruction`, which no command is registered for
+    pub fn on_unknown_instruction(
+        &self,
+        ip: Arc<Mutex<IPState>>,
+        instruction: char,
+        interpreter: &dyn CommandGrid,
+        io_handler: Arc<dyn IOHandle + Send + Sync>,
+    ) -> Result<(), InterpreterError> {
+        match self.unknown_instruction {
+            UnknownInstructionPolicy::Ignore => Ok(()),
+            UnknownInstructionPolicy::Reflect => reflect(&ip),
+            UnknownInstructionPolicy::Warn => {
+                let first_time = self.unknown_instructions_warned.lock()?.insert(instruction);
+                if first_time {
+                    interpreter.warn(ip, RuntimeErrorKind::UnknownInstruction, io_handler)?;
+                }
+                Ok(())
+            }
+            UnknownInstructionPolicy::Error => {
+                Err(interpreter.runtime_error(ip, RuntimeErrorKind::UnknownInstruction))
+            }
+        }
+    }
+}
+
+/// the interpreter reverses the IP once the instruction is done
+fn reflect(ip: &Arc<Mutex<IPState>>) -> Result<(), InterpreterError> {
+    ip.lock()?.reflect_pending = true;
+    Ok(())
+}
@@ -0,0 +1,283 @@
+// src/engine/session.rs
+
+use crate::engine::history::InputEvent;
+use crate::errors::{Diagnostic, InterpreterError};
+use crate::interfaces::IOHandle;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+/// Scheduling order and input of one run, enough to repeat it exactly.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// IP ids in the order they executed instructions, as `[id, count]` runs
+    pub schedule: Vec<(usize, usize)>,
+    /// everything read by `~` and `&`, in order
+    pub input: Vec<InputEvent>,
+}
+
+impl Session {
+    pub fn load(path: &str) -> Result<Self, InterpreterError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| InterpreterError::FileReadError(format!("{}: {}", path, e)))?;
+        serde_json::from_str(&text)
+            .map_err(|e| InterpreterError::ParseError(format!("{}: {}", path, e)))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), InterpreterError> {
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Whether a run is being recorded or repeats a recorded session.
+#[derive(Debug, Clone)]
+pub enum SessionMode {
+    Record,
+    Replay(Session),
+}
+
+/// Lets one IP at a time execute an instruction, recording or following the order.
+#[derive(Debug)]
+pub(crate) struct Sequencer {
+    replaying: bool,
+    schedule: Vec<(usize, usize)>,
+    /// replay position: run index and instructions already taken from that run
+    run: usize,
+    taken: usize,
+    /// the replay went off the schedule; IPs are still sequenced but in any order
+    diverged: bool,
+    in_turn: Option<usize>,
+}
+
+impl Sequencer {
+    pub(crate) fn record() -> Self {
+        Self::new(false, Vec::new())
+    }
+
+    pub(crate) fn replay(schedule: Vec<(usize, usize)>) -> Self {
+        Self::new(true, schedule)
+    }
+
+    fn new(replaying: bool, schedule: Vec<(usize, usize)>) -> Self {
+        Self {
+            replaying,
+            schedule,
+            run: 0,
+            taken: 0,
+            diverged: false,
+            in_turn: None,
+        }
+    }
+
+    /// IP that has to execute the next instruction
+    fn next(&self) -> Option<usize> {
+        if !self.replaying || self.diverged {
+            return None;
+        }
+        self.schedule.get(self.run).map(|(id, _)| *id)
+    }
+
+    pub(crate) fn allows(&self, id: usize) -> bool {
+        self.in_turn.is_none() && self.next().is_none_or(|next| next == id)
+    }
+
+    pub(crate) fn begin_turn(&mut self, id: usize) {
+        self.in_turn = Some(id);
+        if !self.replaying {
+            match self.schedule.last_mut() {
+                Some((last, count)) if *last == id => *count += 1,
+                _ => self.schedule.push((id, 1)),
+            }
+        } else if self.next() == Some(id) {
+            self.taken += 1;
+            if self.taken == self.schedule[self.run].1 {
+                self.run += 1;
+                self.taken = 0;
+            }
+        }
+    }
+
+    pub(crate) fn end_turn(&mut self, id: usize) {
+        if self.in_turn == Some(id) {
+            self.in_turn = None;
+        }
+    }
+
+    /// IP `id` has no more instructions; the replay cannot go on if it was due next
+    ///
+    /// true when the replay diverges here, for the interpreter to warn about
+    pub(crate) fn ip_ended(&mut self, id: usize) -> bool {
+        let diverged = self.next() == Some(id);
+        self.diverged |= diverged;
+        diverged
+    }
+
+    pub(crate) fn recorded(&self) -> Option<Vec<(usize, usize)>> {
+        (!self.replaying).then(|| self.schedule.clone())
+    }
+}
+
+/// Input read during a recording, or still to be handed out on replay.
+pub struct SessionInput {
+    replaying: bool,
+    events: Mutex<VecDeque<InputEvent>>,
+}
+
+impl SessionInput {
+    pub fn record() -> Self {
+        Self {
+            replaying: false,
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn replay(input: Vec<InputEvent>) -> Self {
+        Self {
+            replaying: true,
+            events: Mutex::new(input.into()),
+        }
+    }
+
+    pub fn recorded(&self) -> Result<Option<Vec<InputEvent>>, InterpreterError> {
+        if self.replaying {
+            return Ok(None);
+        }
+        Ok(Some(self.events.lock()?.iter().copied().collect()))
+    }
+}
+
+/// Forwards to another `IOHandle`, recording input or replaying it from a session.
+pub struct SessionIO {
+    inner: Arc<dyn IOHandle + Send + Sync>,
+    input: Arc<SessionInput>,
+}
+
+impl SessionIO {
+    pub fn new(inner: Arc<dyn IOHandle + Send + Sync>, input: Arc<SessionInput>) -> Self {
+        Self { inner, input }
+    }
+
+    /// the next recorded event if `take` accepts it, the real input after the recording ends
+    fn replayed<T>(
+        &self,
+        take: impl FnOnce(&InputEvent) -> Option<T>,
+    ) -> Result<Option<T>, InterpreterError> {
+        let mut events = self.input.events.lock()?;
+        let value = events.front().and_then(take);
+        if value.is_some() {
+            events.pop_front();
+        }
+        Ok(value)
+    }
+
+    fn recorded(&self, event: InputEvent) -> Result<(), InterpreterError> {
+        if !self.input.replaying {
+            self.input.events.lock()?.push_back(event);
+        }
+        Ok(())
+    }
+}
+
+impl IOHandle for SessionIO {
+    fn write_output(&self, output: &str) -> Result<(), InterpreterError> {
+        self.inner.write_output(output)
+    }
+
+    fn write_error(&self, error: &str) -> Result<(), InterpreterError> {
+        self.inner.write_error(error)
+    }
+
+    fn read_number(&self) -> Result<usize, InterpreterError> {
+        if self.input.replaying {
+            if let Some(number) = self.replayed(|event| match event {
+                InputEvent::Number(number) => Some(*number),
+                InputEvent::Char(_) => None,
+            })? {
+                return Ok(number);
+            }
+        }
+        let number = self.inner.read_number()?;
+        self.recorded(InputEvent::Number(number))?;
+        Ok(number)
+    }
+
+    fn read_char(&self) -> Result<char, InterpreterError> {
+        if self.input.replaying {
+            if let Some(c) = self.replayed(|event| match event {
+                InputEvent::Char(c) => Some(*c),
+                InputEvent::Number(_) => None,
+            })? {
+                return Ok(c);
+            }
+        }
+        let c = self.inner.read_char()?;
+        self.recorded(InputEvent::Char(c))?;
+        Ok(c)
+    }
+
+    fn display_stack(&self, stack: &[usize]) -> Result<(), InterpreterError> {
+        self.inner.display_stack(stack)
+    }
+
+    fn display_grid(
+        &self,
+        grid: &[Vec<char>],
+        ip_x: usize,
+        ip_y: usize,
+        layer: Option<usize>,
+    ) -> Result<(), InterpreterError> {
+        self.inner.display_grid(grid, ip_x, ip_y, layer)
+    }
+
+    fn display_diagnostic(&self, diagnostic: &Diagnostic) -> Result<(), InterpreterError> {
+        self.inner.display_diagnostic(diagnostic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_follow_schedule() {
+        let mut recorder = Sequencer::record();
+        for id in [0, 0, 1, 0] {
+            assert!(recorder.allows(id));
+            recorder.begin_turn(id);
+            // one IP at a time
+            assert!(!recorder.allows(1 - id));
+            recorder.end_turn(id);
+        }
+        let schedule = recorder.recorded().unwrap();
+        assert_eq!(schedule, vec![(0, 2), (1, 1), (0, 1)]);
+
+        let mut replayer = Sequencer::replay(schedule);
+        for id in [0, 0, 1] {
+            assert!(replayer.allows(id));
+            assert!(!replayer.allows(1 - id));
+            replayer.begin_turn(id);
+            replayer.end_turn(id);
+        }
+        // ip 0 is due, so its end means the replay went off the recording
+        assert!(replayer.ip_ended(0));
+        assert!(replayer.allows(1));
+        assert!(!replayer.ip_ended(1));
+        assert_eq!(replayer.recorded(), None);
+    }
+
+    #[test]
+    fn test_session_file_format() {
+        let session = Session {
+            schedule: vec![(0, 3), (1, 1)],
+            input: vec![InputEvent::Char('a'), InputEvent::Number(42)],
+        };
+        let json = serde_json::to_string(&session).unwrap();
+        assert_eq!(
+            json,
+            r#"{"schedule":[[0,3],[1,1]],"input":[{"char":"a"},{"number":42}]}"#
+        );
+        assert_eq!(serde_json::from_str::<Session>(&json).unwrap(), session);
+    }
+}
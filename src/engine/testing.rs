@@ -0,0 +1,37 @@
+// src/engine/testing.rs
+
+use crate::engine::interpreter::Interpreter;
+use crate::entities::code_grid::CodeGrid;
+use crate::entities::Dimensions;
+use crate::interfaces::command_registry::CommandRegistry;
+use crate::interfaces::memory_io_handler::MemoryIOHandler;
+use std::sync::Arc;
+
+/// a Befunge program for tests, rows padded with spaces
+pub fn program(source: &str) -> CodeGrid {
+    CodeGrid::parse(source, Dimensions::Befunge)
+}
+
+/// cells of the program made of `lines`
+pub fn grid(lines: &[&str]) -> Vec<Vec<char>> {
+    program(&lines.join("\n")).grid.lock().unwrap().clone()
+}
+
+/// an interpreter running `source` with the default commands
+pub fn interpreter(source: &str) -> Interpreter {
+    Interpreter::new(program(source), false, Arc::new(CommandRegistry::new()))
+}
+
+/// output and errors of `interpreter` fed `input`
+pub fn outcome(interpreter: Interpreter, input: &str) -> (String, String) {
+    let io = Arc::new(MemoryIOHandler::new(input));
+    Arc::new(interpreter).run(io.clone()).unwrap();
+    (io.output().unwrap(), io.errors().unwrap())
+}
+
+/// output of `source` fed `input`, which must run without errors
+pub fn run(source: &str, input: &str) -> String {
+    let (output, errors) = outcome(interpreter(source), input);
+    assert_eq!(errors, "", "{}", source);
+    output
+}
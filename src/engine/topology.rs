@@ -0,0 +1,298 @@
+// src/engine/topology.rs
+
+use crate::engine::interpreter::Interpreter;
+use crate::entities::{Direction, Position, Size};
+use crate::interfaces::Topology;
+use clap::ValueEnum;
+use std::sync::Arc;
+
+/// The topologies selectable from the command line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TopologyKind {
+    /// wrap at the edges of the 1024x1024 Funge-Space (1024 layers deep in Trefunge)
+    #[default]
+    Torus,
+    /// wrap at the edges of the program (Funge-98 Lahey-space)
+    Lahey,
+    /// bounce back off the edges of the program
+    Reflect,
+    /// stop the IP at the edges of the program
+    Kill,
+    /// wrap at the edges of the program, mirroring rows across the left and right edges
+    Klein,
+}
+
+impl TopologyKind {
+    pub fn topology(self) -> Arc<dyn Topology + Send + Sync> {
+        match self {
+            TopologyKind::Torus => Arc::new(Torus::new(Size::new(
+                Interpreter::WIDTH,
+                Interpreter::HEIGHT,
+                Interpreter::DEPTH,
+            ))),
+            TopologyKind::Lahey => Arc::new(Lahey),
+            TopologyKind::Reflect => Arc::new(Reflect),
+            TopologyKind::Kill => Arc::new(Kill),
+            TopologyKind::Klein => Arc::new(Klein),
+        }
+    }
+}
+
+/// `coordinate` wrapped into `0..length`
+fn wrap(coordinate: isize, length: usize) -> usize {
+    coordinate.rem_euclid(length as isize) as usize
+}
+
+/// A torus of a fixed size, whatever the size of the program. Outside the program it is
+/// empty space, which an IP crosses until it wraps around to the other side.
+#[derive(Debug, Clone, Copy)]
+pub struct Torus {
+    size: Size,
+}
+
+impl Torus {
+    pub fn new(size: Size) -> Self {
+        Self { size }
+    }
+}
+
+impl Topology for Torus {
+    fn step(
+        &self,
+        at: Position,
+        direction: Direction,
+        _size: Size,
+    ) -> Option<(Position, Direction)> {
+        let (x, y, z) = at.step(direction);
+        let size = self.size;
+        Some((
+            Position::new(
+                wrap(x, size.width),
+                wrap(y, size.height),
+                wrap(z, size.depth),
+            ),
+            direction,
+        ))
+    }
+}
+
+/// Lahey-space: an IP leaving the program comes back in at the opposite edge. For the six
+/// cardinal directions this is a torus the size of the program.
+#[derive(Debug, Clone, Copy)]
+pub struct Lahey;
+
+impl Topology for Lahey {
+    fn step(
+        &self,
+        at: Position,
+        direction: Direction,
+        size: Size,
+    ) -> Option<(Position, Direction)> {
+        Torus::new(size).step(at, direction, size)
+    }
+}
+
+/// Walls at the edges of the program that turn the IP around, so it runs the cell before
+/// the wall next.
+#[derive(Debug, Clone, Copy)]
+pub struct Reflect;
+
+impl Topology for Reflect {
+    fn step(
+        &self,
+        at: Position,
+        direction: Direction,
+        size: Size,
+    ) -> Option<(Position, Direction)> {
+        let (x, y, z) = at.step(direction);
+        if size.contains(x, y, z) {
+            return Some((Position::new(x as usize, y as usize, z as usize), direction));
+        }
+        let back = direction.reverse();
+        let (x, y, z) = at.step(back);
+        // a program one cell wide keeps the IP on its cell
+        match size.contains(x, y, z) {
+            true => Some((Position::new(x as usize, y as usize, z as usize), back)),
+            false => Some((at, back)),
+        }
+    }
+}
+
+/// Walls at the edges of the program that stop the IP.
+#[derive(Debug, Clone, Copy)]
+pub struct Kill;
+
+impl Topology for Kill {
+    fn step(
+        &self,
+        at: Position,
+        direction: Direction,
+        size: Size,
+    ) -> Option<(Position, Direction)> {
+        let (x, y, z) = at.step(direction);
+        size.contains(x, y, z)
+            .then_some((Position::new(x as usize, y as usize, z as usize), direction))
+    }
+}
+
+/// A Klein bottle the size of the program: the top and bottom edges (and the first and last
+/// layers) wrap like a torus, and an IP leaving through the left or right edge comes back in
+/// at the other one, upside down.
+#[derive(Debug, Clone, Copy)]
+pub struct Klein;
+
+impl Topology for Klein {
+    fn step(
+        &self,
+        at: Position,
+        direction: Direction,
+        size: Size,
+    ) -> Option<(Position, Direction)> {
+        let (x, y, z) = at.step(direction);
+        let (y, z) = (wrap(y, size.height), wrap(z, size.depth));
+        if (0..size.width as isize).contains(&x) {
+            return Some((Position::new(x as usize, y, z), direction));
+        }
+        // only horizontal moves cross these edges, so the direction stays the same
+        Some((
+            Position::new(wrap(x, size.width), size.height - 1 - y, z),
+            direction,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::testing;
+    use Direction::*;
+
+    /// positions visited from (x, y) in a 3x2 program, stopping when the IP dies
+    fn walk(
+        topology: &dyn Topology,
+        x: usize,
+        y: usize,
+        direction: Direction,
+        steps: usize,
+    ) -> Vec<(usize, usize, Direction)> {
+        let mut at = (Position::new(x, y, 0), direction);
+        let mut path = Vec::new();
+        for _ in 0..steps {
+            let Some(next) = topology.step(at.0, at.1, Size::new(3, 2, 1)) else {
+                break;
+            };
+            at = next;
+            path.push((at.0.x, at.0.y, at.1));
+        }
+        path
+    }
+
+    /// output and errors of a one-row program under `kind`
+    fn run(row: &str, kind: TopologyKind) -> (String, String) {
+        testing::outcome(testing::interpreter(row).with_topology(kind.topology()), "")
+    }
+
+    #[test]
+    fn test_interpreter_topologies() {
+        // the IP turns left off the first cell
+        let row = "<@.1";
+        // across the empty Funge-Space to the right of the program
+        assert_eq!(
+            run(row, TopologyKind::Torus),
+            ("1 ".to_string(), String::new())
+        );
+        assert_eq!(
+            run(row, TopologyKind::Lahey),
+            ("1 ".to_string(), String::new())
+        );
+        assert_eq!(
+            run(row, TopologyKind::Klein),
+            ("1 ".to_string(), String::new())
+        );
+        // bounced onto `@`
+        assert_eq!(
+            run(row, TopologyKind::Reflect),
+            (String::new(), String::new())
+        );
+        assert_eq!(run(row, TopologyKind::Kill), (String::new(), String::new()));
+    }
+
+    #[test]
+    fn test_torus() {
+        let torus = Torus::new(Size::new(4, 4, 1));
+        assert_eq!(
+            walk(&torus, 2, 0, Right, 2),
+            vec![(3, 0, Right), (0, 0, Right)]
+        );
+        assert_eq!(walk(&torus, 0, 0, Up, 1), vec![(0, 3, Up)]);
+        // the default runs past a small program into empty Funge-Space
+        let default = TopologyKind::default().topology();
+        assert_eq!(walk(default.as_ref(), 2, 0, Right, 1), vec![(3, 0, Right)]);
+        assert_eq!(walk(default.as_ref(), 0, 0, Left, 1), vec![(1023, 0, Left)]);
+    }
+
+    #[test]
+    fn test_lahey() {
+        assert_eq!(
+            walk(&Lahey, 1, 0, Right, 3),
+            vec![(2, 0, Right), (0, 0, Right), (1, 0, Right)]
+        );
+        assert_eq!(walk(&Lahey, 0, 0, Up, 2), vec![(0, 1, Up), (0, 0, Up)]);
+    }
+
+    #[test]
+    fn test_reflect() {
+        assert_eq!(
+            walk(&Reflect, 1, 0, Right, 4),
+            vec![(2, 0, Right), (1, 0, Left), (0, 0, Left), (1, 0, Right)]
+        );
+        assert_eq!(walk(&Reflect, 0, 1, Down, 1), vec![(0, 0, Up)]);
+        let origin = Position::default();
+        assert_eq!(
+            Reflect.step(origin, Right, Size::new(1, 1, 1)),
+            Some((origin, Left))
+        );
+    }
+
+    #[test]
+    fn test_kill() {
+        assert_eq!(walk(&Kill, 1, 0, Right, 5), vec![(2, 0, Right)]);
+        assert_eq!(walk(&Kill, 0, 0, Up, 5), vec![]);
+    }
+
+    #[test]
+    fn test_klein() {
+        // leaving on the right of the top row comes back on the left of the bottom row
+        assert_eq!(
+            walk(&Klein, 1, 0, Right, 4),
+            vec![(2, 0, Right), (0, 1, Right), (1, 1, Right), (2, 1, Right)]
+        );
+        assert_eq!(walk(&Klein, 0, 1, Left, 1), vec![(2, 0, Left)]);
+        // top and bottom wrap without a flip
+        assert_eq!(walk(&Klein, 2, 1, Down, 1), vec![(2, 0, Down)]);
+    }
+
+    #[test]
+    fn test_layers() {
+        let size = Size::new(3, 2, 2);
+        let top = Position::new(1, 1, 1);
+        assert_eq!(
+            Lahey.step(top, High, size),
+            Some((Position::new(1, 1, 0), High))
+        );
+        assert_eq!(
+            Reflect.step(top, High, size),
+            Some((Position::new(1, 1, 0), Low))
+        );
+        assert_eq!(Kill.step(top, High, size), None);
+        assert_eq!(
+            Kill.step(top, Low, size),
+            Some((Position::new(1, 1, 0), Low))
+        );
+        // rows flip, layers do not
+        assert_eq!(
+            Klein.step(Position::new(2, 0, 1), Right, size),
+            Some((Position::new(0, 1, 1), Right))
+        );
+    }
+}
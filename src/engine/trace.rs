@@ -0,0 +1,258 @@
+// src/engine/trace.rs
+
+use crate::engine::history::StepRecord;
+use crate::entities::breakpoint::Region;
+use crate::entities::Direction;
+use crate::errors::InterpreterError;
+use crate::interfaces::StepObserver;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// One line of a `--trace` file: an executed instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEvent {
+    pub step: usize,
+    pub ip: usize,
+    /// position and direction before the instruction
+    pub x: usize,
+    pub y: usize,
+    /// only written for Trefunge layers above the first
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub z: usize,
+    #[serde(rename = "char")]
+    pub instruction: char,
+    pub direction: Direction,
+    /// stack delta: values removed and values left on top, bottom first
+    pub popped: Vec<usize>,
+    pub pushed: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub writes: Vec<TraceWrite>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub output: String,
+}
+
+/// A cell written by `p`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceWrite {
+    pub x: usize,
+    pub y: usize,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub z: usize,
+    pub value: char,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+impl From<&StepRecord> for TraceEvent {
+    fn from(record: &StepRecord) -> Self {
+        Self {
+            step: record.step,
+            ip: record.ip_id,
+            x: record.x,
+            y: record.y,
+            z: record.z,
+            instruction: record.instruction,
+            direction: record.direction,
+            popped: record.popped.clone(),
+            pushed: record.pushed.clone(),
+            writes: record
+                .writes
+                .iter()
+                .map(|write| TraceWrite {
+                    x: write.x,
+                    y: write.y,
+                    z: write.z,
+                    value: write.new,
+                })
+                .collect(),
+            output: record.output.clone(),
+        }
+    }
+}
+
+impl TraceEvent {
+    /// same instruction with the same effects, whatever its global step number
+    pub fn same_execution(&self, other: &TraceEvent) -> bool {
+        Self {
+            step: 0,
+            ..self.clone()
+        } == Self {
+            step: 0,
+            ..other.clone()
+        }
+    }
+}
+
+/// Read a trace written by `--trace`.
+pub fn read_trace(path: &str) -> Result<Vec<TraceEvent>, InterpreterError> {
+    let file = File::open(path)
+        .map_err(|e| InterpreterError::FileReadError(format!("{}: {}", path, e)))?;
+    let mut events = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line)
+            .map_err(|e| InterpreterError::ParseError(format!("{}:{}: {}", path, i + 1, e)))?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Which instructions end up in the trace.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    /// only these IPs (all when empty)
+    pub ips: Vec<usize>,
+    /// only instructions executed inside this region
+    pub region: Option<Region>,
+    /// keep every n-th matching instruction (0 and 1 keep all)
+    pub every: usize,
+}
+
+impl TraceFilter {
+    fn matches(&self, record: &StepRecord) -> bool {
+        (self.ips.is_empty() || self.ips.contains(&record.ip_id))
+            && self
+                .region
+                .is_none_or(|region| region.contains(record.position()))
+    }
+}
+
+/// Writes executed instructions as JSON lines.
+pub struct Tracer {
+    filter: TraceFilter,
+    /// instructions that passed the filter so far, for sampling
+    matched: AtomicUsize,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write + Send>, filter: TraceFilter) -> Self {
+        Self {
+            filter,
+            matched: AtomicUsize::new(0),
+            out: Mutex::new(out),
+        }
+    }
+
+    pub fn create(path: &str, filter: TraceFilter) -> Result<Self, InterpreterError> {
+        let file = File::create(path)?;
+        Ok(Self::new(Box::new(BufWriter::new(file)), filter))
+    }
+}
+
+impl StepObserver for Tracer {
+    fn on_step(&self, record: &StepRecord) -> Result<(), InterpreterError> {
+        if !self.filter.matches(record) {
+            return Ok(());
+        }
+        let matched = self.matched.fetch_add(1, Ordering::SeqCst);
+        if !matched.is_multiple_of(self.filter.every.max(1)) {
+            return Ok(());
+        }
+        let line = serde_json::to_string(&TraceEvent::from(record))?;
+        writeln!(self.out.lock()?, "{}", line)?;
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<(), InterpreterError> {
+        self.out.lock()?.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::history::GridWrite;
+    use std::sync::Arc;
+
+    /// Write into a buffer the test can read back.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn record(step: usize, ip_id: usize, x: usize) -> StepRecord {
+        StepRecord {
+            step,
+            ip_id,
+            instruction: 'p',
+            x,
+            y: 0,
+            z: 0,
+            direction: Direction::Right,
+            string_mode: false,
+            stack_kept: 0,
+            popped: vec![65, 1, 2],
+            pushed: vec![],
+            writes: vec![GridWrite {
+                x: 1,
+                y: 2,
+                z: 0,
+                old: ' ',
+                new: 'A',
+            }],
+            spawned: vec![],
+            input: vec![],
+            output: String::new(),
+        }
+    }
+
+    fn lines(buffer: &SharedBuffer) -> Vec<TraceEvent> {
+        String::from_utf8(buffer.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_trace_line_format() {
+        let buffer = SharedBuffer::default();
+        let tracer = Tracer::new(Box::new(buffer.clone()), TraceFilter::default());
+        tracer.on_step(&record(1, 0, 3)).unwrap();
+
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            text,
+            "{\"step\":1,\"ip\":0,\"x\":3,\"y\":0,\"char\":\"p\",\"direction\":\"right\",\
+             \"popped\":[65,1,2],\"pushed\":[],\"writes\":[{\"x\":1,\"y\":2,\"value\":\"A\"}]}\n"
+        );
+        assert_eq!(lines(&buffer), vec![TraceEvent::from(&record(1, 0, 3))]);
+    }
+
+    #[test]
+    fn test_filter_and_sampling() {
+        let buffer = SharedBuffer::default();
+        let filter = TraceFilter {
+            ips: vec![1],
+            region: Some("0,0:6,1".parse().unwrap()),
+            every: 2,
+        };
+        let tracer = Tracer::new(Box::new(buffer.clone()), filter);
+        for step in 1..=10 {
+            // ip 0 and x >= 6 are filtered out
+            tracer.on_step(&record(step, step % 2, step)).unwrap();
+        }
+        // ip 1 inside the region: steps 1, 3 and 5, sampled every other one
+        let steps: Vec<usize> = lines(&buffer).iter().map(|event| event.step).collect();
+        assert_eq!(steps, vec![1, 5]);
+    }
+}
@@ -0,0 +1,306 @@
+// src/entities/breakpoint.rs
+
+use crate::entities::Position;
+use crate::errors::format_cell;
+use std::fmt;
+use std::str::FromStr;
+
+/// Pauses execution before a cell runs, or when an IP is spawned / terminates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// `x,y`, or `x,y,z` on a Trefunge layer
+    Cell(Position),
+    /// `'c'` or `char:c`
+    Instruction(char),
+    /// `spawn`
+    Spawn,
+    /// `terminate`
+    Terminate,
+}
+
+/// Rectangle of grid cells on one layer (0 outside Trefunge).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn cell(at: Position) -> Self {
+        Self {
+            x: at.x,
+            y: at.y,
+            z: at.z,
+            width: 1,
+            height: 1,
+        }
+    }
+
+    pub fn contains(&self, at: Position) -> bool {
+        at.z == self.z
+            && at.x >= self.x
+            && at.x < self.x + self.width
+            && at.y >= self.y
+            && at.y < self.y + self.height
+    }
+}
+
+/// `(x, y)`, or `(x, y, z)` off the first layer
+fn format_position(at: Position) -> String {
+    format_cell(at.x, at.y, (at.z > 0).then_some(at.z))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    const ALL: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+    ];
+
+    pub fn holds(&self, lhs: usize, rhs: usize) -> bool {
+        match self {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(_, c)| c == self)
+            .map(|(s, _)| *s)
+            .unwrap_or("==")
+    }
+}
+
+/// Pauses execution after a `p` writes into a region, or when the stack top starts meeting a condition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// `x,y[,z]` or `x,y[,z]:w,h`
+    Write(Region),
+    /// `top>5`, `top==0`, ...
+    StackTop(Comparison, usize),
+}
+
+impl Watchpoint {
+    pub fn stack_top_matches(&self, top: Option<usize>) -> bool {
+        match (self, top) {
+            (Watchpoint::StackTop(comparison, value), Some(top)) => comparison.holds(top, *value),
+            _ => false,
+        }
+    }
+}
+
+/// `x,y`, or `x,y,z` with `z` defaulting to the first layer
+pub(crate) fn parse_position(s: &str) -> Result<Position, String> {
+    match s.split(',').count() {
+        3 => {
+            let (pair, z) = s.rsplit_once(',').unwrap_or_default();
+            let (x, y) = parse_pair(pair)?;
+            let z = z
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid number `{}`", z.trim()))?;
+            Ok(Position::new(x, y, z))
+        }
+        _ => parse_pair(s).map(|(x, y)| Position::new(x, y, 0)),
+    }
+}
+
+/// `x,y`
+pub(crate) fn parse_pair(s: &str) -> Result<(usize, usize), String> {
+    let (a, b) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected `x,y`, got `{}`", s))?;
+    let a = a
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| format!("invalid number `{}`", a.trim()))?;
+    let b = b
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| format!("invalid number `{}`", b.trim()))?;
+    Ok((a, b))
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s {
+            "spawn" => return Ok(Breakpoint::Spawn),
+            "terminate" => return Ok(Breakpoint::Terminate),
+            _ => {}
+        }
+        let quoted = s
+            .strip_prefix('\'')
+            .and_then(|rest| rest.strip_suffix('\''));
+        if let Some(c) = quoted.or_else(|| s.strip_prefix("char:")) {
+            let mut chars = c.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(Breakpoint::Instruction(c)),
+                _ => Err(format!("expected a single character, got `{}`", c)),
+            };
+        }
+        Ok(Breakpoint::Cell(parse_position(s)?))
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(condition) = s.strip_prefix("top") {
+            let condition = condition.trim();
+            let (symbol, comparison) = Comparison::ALL
+                .iter()
+                .find(|(symbol, _)| condition.starts_with(symbol))
+                .ok_or_else(|| format!("expected a comparison after `top`, got `{}`", s))?;
+            let value = condition[symbol.len()..].trim();
+            let value = value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid number `{}`", value))?;
+            return Ok(Watchpoint::StackTop(*comparison, value));
+        }
+        Ok(Watchpoint::Write(s.parse()?))
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// `x,y` (one cell) or `x,y:w,h`, with `,z` after `y` for a Trefunge layer
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(':') {
+            Some((position, size)) => {
+                let Position { x, y, z } = parse_position(position)?;
+                let (width, height) = parse_pair(size)?;
+                Ok(Region {
+                    x,
+                    y,
+                    z,
+                    width,
+                    height,
+                })
+            }
+            None => Ok(Region::cell(parse_position(s)?)),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Cell(at) => write!(f, "cell {}", format_position(*at)),
+            Breakpoint::Instruction(c) => write!(f, "instruction {:?}", c),
+            Breakpoint::Spawn => write!(f, "IP spawn"),
+            Breakpoint::Terminate => write!(f, "IP terminate"),
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watchpoint::Write(region) => {
+                let at = Position::new(region.x, region.y, region.z);
+                write!(f, "write to {}", format_position(at))?;
+                if region.width != 1 || region.height != 1 {
+                    write!(f, " size {}x{}", region.width, region.height)?;
+                }
+                Ok(())
+            }
+            Watchpoint::StackTop(comparison, value) => {
+                write!(f, "stack top {} {}", comparison.symbol(), value)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_breakpoints() {
+        assert_eq!("3,4".parse(), Ok(Breakpoint::Cell(Position::new(3, 4, 0))));
+        assert_eq!(
+            "3,4,1".parse(),
+            Ok(Breakpoint::Cell(Position::new(3, 4, 1)))
+        );
+        assert_eq!("'p'".parse(), Ok(Breakpoint::Instruction('p')));
+        assert_eq!("char:,".parse(), Ok(Breakpoint::Instruction(',')));
+        assert_eq!("spawn".parse(), Ok(Breakpoint::Spawn));
+        assert_eq!("terminate".parse(), Ok(Breakpoint::Terminate));
+        assert!("3".parse::<Breakpoint>().is_err());
+        assert!("'ab'".parse::<Breakpoint>().is_err());
+    }
+
+    #[test]
+    fn test_parse_watchpoints() {
+        assert_eq!(
+            "1,2".parse(),
+            Ok(Watchpoint::Write(Region::cell(Position::new(1, 2, 0))))
+        );
+        assert_eq!(
+            "1,2,1:3,4".parse(),
+            Ok(Watchpoint::Write(Region {
+                x: 1,
+                y: 2,
+                z: 1,
+                width: 3,
+                height: 4
+            }))
+        );
+        assert_eq!(
+            "top>=10".parse(),
+            Ok(Watchpoint::StackTop(Comparison::Ge, 10))
+        );
+        assert_eq!(
+            "top == 0".parse(),
+            Ok(Watchpoint::StackTop(Comparison::Eq, 0))
+        );
+        assert!("top~3".parse::<Watchpoint>().is_err());
+    }
+
+    #[test]
+    fn test_watchpoint_matching() {
+        let region = Region {
+            x: 1,
+            y: 1,
+            z: 0,
+            width: 2,
+            height: 2,
+        };
+        assert!(region.contains(Position::new(2, 2, 0)));
+        assert!(!region.contains(Position::new(3, 1, 0)));
+        assert!(!region.contains(Position::new(2, 2, 1)));
+
+        let watch = Watchpoint::StackTop(Comparison::Gt, 5);
+        assert!(watch.stack_top_matches(Some(6)));
+        assert!(!watch.stack_top_matches(Some(5)));
+        assert!(!watch.stack_top_matches(None));
+    }
+}
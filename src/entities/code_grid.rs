@@ -1,27 +1,71 @@
 use std::{
     fs,
+    ops::Range,
     path::Path,
     sync::{Arc, Mutex},
 };
 
+use crate::entities::Dimensions;
 use crate::errors::InterpreterError;
 
 #[derive(Clone)]
 pub struct CodeGrid {
+    /// 全ての層の行を層の順に並べたもの（各層の行数はそろえる）
     pub grid: Arc<Mutex<Vec<Vec<char>>>>,
     pub code_width: usize,
+    /// 1層あたりの行数
     pub code_height: usize,
+    /// Trefunge の層の数（それ以外では 1）
+    pub code_depth: usize,
+    /// grid の中の1層あたりの行数（空のプログラムでも 1）
+    layer_rows: usize,
 }
 
 impl CodeGrid {
     pub fn new(grid: Vec<Vec<char>>, code_width: usize, code_height: usize) -> Self {
         Self {
+            layer_rows: grid.len(),
             grid: Arc::new(Mutex::new(grid)),
             code_width,
             code_height,
+            code_depth: 1,
         }
     }
+
+    /// 層ごとの行からグリッドを作る。幅と高さは最大のものに合わせてスペースで埋める
+    pub fn layered(layers: Vec<Vec<Vec<char>>>) -> Self {
+        let code_width = layers.iter().flatten().map(Vec::len).max().unwrap_or(0);
+        let code_height = layers.iter().map(Vec::len).max().unwrap_or(0);
+
+        // スタブ用に最低1x1のグリッドを確保
+        let layer_rows = code_height.max(1);
+        let row_width = code_width.max(1);
+        let code_depth = layers.len().max(1);
+        let mut grid: Vec<Vec<char>> = Vec::with_capacity(layer_rows * code_depth);
+        for mut layer in layers {
+            layer.resize(layer_rows, Vec::new());
+            for mut row in layer {
+                row.resize(row_width, ' ');
+                grid.push(row);
+            }
+        }
+        if grid.is_empty() {
+            grid.push(vec![' '; 1]);
+        }
+
+        Self {
+            code_depth,
+            layer_rows,
+            ..Self::new(grid, code_width, code_height)
+        }
+    }
+
     pub fn load(file_path: &str) -> Result<Self, InterpreterError> {
+        Self::load_as(file_path, Dimensions::Befunge)
+    }
+
+    /// `dimensions` のプログラムとして読み込む
+    pub fn load_as(file_path: &str, dimensions: Dimensions) -> Result<Self, InterpreterError> {
         // ファイルパスが存在するか確認
         if !Path::new(file_path).exists() {
             return Err(InterpreterError::FileNotFound(format!(
@@ -35,37 +79,57 @@ impl CodeGrid {
             InterpreterError::FileReadError(format!("Failed to read file {}: {}", file_path, e))
         })?;
 
-        // 各行をベクターに分割
-        let lines: Vec<&str> = content.lines().collect();
-
-        // 最長行の長さを取得
-        let max_width = lines
-            .iter()
-            .map(|line| line.chars().count())
-            .max()
-            .unwrap_or(0);
+        Ok(Self::parse(&content, dimensions))
+    }
 
-        // グリッドの高さは行数、幅は最長行に合わせる
-        let code_height = lines.len();
-        let code_width = max_width;
+    /// Unefunge は改行を無視して1行に、Trefunge はフォームフィードで層に分ける
+    pub fn parse(content: &str, dimensions: Dimensions) -> Self {
+        let rows = |text: &str| -> Vec<Vec<char>> {
+            text.lines().map(|line| line.chars().collect()).collect()
+        };
+        let layers = match dimensions {
+            Dimensions::Unefunge => {
+                let row: Vec<char> = content.lines().flat_map(str::chars).collect();
+                match row.is_empty() {
+                    true => Vec::new(),
+                    false => vec![vec![row]],
+                }
+            }
+            Dimensions::Befunge => vec![rows(content)],
+            Dimensions::Trefunge => {
+                let mut layers: Vec<Vec<Vec<char>>> = content.split('\u{c}').map(rows).collect();
+                // 末尾のフォームフィードの後ろの空の層は数えない
+                while layers
+                    .last()
+                    .is_some_and(|layer| layer.iter().all(Vec::is_empty))
+                {
+                    layers.pop();
+                }
+                layers
+            }
+        };
+        Self::layered(layers)
+    }
 
-        // グリッドを初期化（パディングはスペースで埋める）
-        let mut grid: Vec<Vec<char>> = Vec::with_capacity(code_height);
+    /// 層 `z` の `y` 行目が入っている grid の行。プログラムの外なら None
+    pub fn row(&self, y: usize, z: usize) -> Option<usize> {
+        (y < self.layer_rows && z < self.code_depth).then_some(z * self.layer_rows + y)
+    }
 
-        for line in lines {
-            let mut row: Vec<char> = line.chars().collect();
-            if row.len() < max_width {
-                row.extend(std::iter::repeat(' ').take(max_width - row.len()));
-            }
-            grid.push(row);
-        }
+    /// 層 `z` の行が入っている grid の範囲
+    pub fn layer(&self, z: usize) -> Range<usize> {
+        let z = z.min(self.code_depth);
+        z * self.layer_rows..(z + 1).min(self.code_depth) * self.layer_rows
+    }
 
-        // スタブ用に最低1x1のグリッドを確保
-        if grid.is_empty() {
-            grid.push(vec![' '; 1]);
-        }
+    /// grid の `row` 行目の (y, z)
+    pub fn layer_of(&self, row: usize) -> (usize, usize) {
+        (row % self.layer_rows, row / self.layer_rows)
+    }
 
-        Ok(Self::new(grid, code_width, code_height))
+    /// grid の中の1層あたりの行数
+    pub fn layer_rows(&self) -> usize {
+        self.layer_rows
     }
 }
 
@@ -177,4 +241,37 @@ mod tests {
             "Second row should be padded with space"
         );
     }
+
+    #[test]
+    fn test_parse_layers() {
+        use crate::entities::Dimensions;
+
+        // a form feed starts the next layer; a trailing one adds nothing
+        let program = CodeGrid::parse("ab\nc\n\u{c}def\n\u{c}\n", Dimensions::Trefunge);
+        assert_eq!(
+            (program.code_width, program.code_height, program.code_depth),
+            (3, 2, 2)
+        );
+        let grid = program.grid.lock().unwrap();
+        assert_eq!(grid.len(), 4, "two layers of two rows");
+        assert_eq!(grid[1], vec!['c', ' ', ' ']);
+        assert_eq!(grid[2], vec!['d', 'e', 'f']);
+        assert_eq!(
+            grid[3],
+            vec![' ', ' ', ' '],
+            "layers are padded to the same height"
+        );
+        assert_eq!(program.row(0, 1), Some(2));
+        assert_eq!(program.row(2, 0), None);
+        assert_eq!(program.row(0, 2), None);
+        assert_eq!(program.layer_of(3), (1, 1));
+
+        // other spaces keep the form feed as a cell
+        let program = CodeGrid::parse("a\u{c}b\nc", Dimensions::Unefunge);
+        assert_eq!(
+            program.grid.lock().unwrap()[0],
+            vec!['a', '\u{c}', 'b', 'c']
+        );
+        assert_eq!(program.code_depth, 1);
+    }
 }
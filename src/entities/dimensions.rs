@@ -0,0 +1,142 @@
+// src/entities/dimensions.rs
+
+use crate::entities::{Direction, Position};
+use clap::ValueEnum;
+
+/// How many coordinates Funge-Space has (Funge-98 Unefunge, Befunge and Trefunge).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Dimensions {
+    /// one row; `^`, `v` and `|` are not instructions
+    Unefunge,
+    #[default]
+    Befunge,
+    /// layers separated by form feeds, with `h`, `l` and `m` moving between them
+    Trefunge,
+}
+
+impl Dimensions {
+    /// coordinates popped by `p` and `g`
+    pub fn count(self) -> usize {
+        match self {
+            Dimensions::Unefunge => 1,
+            Dimensions::Befunge => 2,
+            Dimensions::Trefunge => 3,
+        }
+    }
+
+    /// whether an IP can move in `direction`
+    pub fn allows(self, direction: Direction) -> bool {
+        match direction {
+            Direction::Right | Direction::Left => true,
+            Direction::Up | Direction::Down => self != Dimensions::Unefunge,
+            Direction::High | Direction::Low => self == Dimensions::Trefunge,
+        }
+    }
+
+    /// `at` as `(x, y)`, or `(x, y, z)` in Trefunge
+    pub fn format(self, at: Position) -> String {
+        match self {
+            Dimensions::Trefunge => at.to_string(),
+            _ => format!("({}, {})", at.x, at.y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::interpreter::Interpreter;
+    use crate::engine::testing;
+    use crate::entities::breakpoint::Region;
+    use crate::entities::{Breakpoint, CodeGrid};
+    use crate::interfaces::command_registry::CommandRegistry;
+    use std::sync::Arc;
+
+    /// output and errors of `source` run in `dimensions`
+    fn run(source: &str, dimensions: Dimensions) -> (String, String) {
+        let interpreter = Interpreter::new(
+            CodeGrid::parse(source, dimensions),
+            false,
+            Arc::new(CommandRegistry::for_dimensions(dimensions)),
+        )
+        .with_dimensions(dimensions);
+        testing::outcome(interpreter, "")
+    }
+
+    #[test]
+    fn test_unefunge() {
+        // `v` is not an instruction, `p` and `g` take only x
+        assert_eq!(run("1v.@", Dimensions::Unefunge).0, "1 ");
+        assert_eq!(run("\"A\"9p9g,@ ", Dimensions::Unefunge).0, "A");
+        // line breaks are ignored
+        assert_eq!(run("12\n+.@\n", Dimensions::Unefunge).0, "3 ");
+    }
+
+    #[test]
+    fn test_trefunge() {
+        // up a layer, read a cell of it, write and read one below, then down onto `@`
+        let source = format!("h{}@\u{c}>001g,\"Z\"900p900g,0m", " ".repeat(18));
+        assert_eq!(
+            run(&source, Dimensions::Trefunge),
+            (">Z".to_string(), String::new())
+        );
+        // `h` is not an instruction in Befunge
+        assert_eq!(run("h1.@", Dimensions::Befunge).0, "1 ");
+    }
+
+    #[test]
+    fn test_trefunge_out_of_bounds() {
+        let (_, errors) = run("1 1 1g@", Dimensions::Trefunge);
+        assert!(
+            errors.contains("attempt to get value out of bounds at (1, 1, 1)"),
+            "{}",
+            errors
+        );
+        // below the first layer, across the empty ones up to the second
+        assert_eq!(
+            run("l\u{c}>1.@", Dimensions::Trefunge),
+            ("1 ".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn test_trefunge_same_cell_on_two_layers() {
+        // writes (20, 0) on layer 0, then on layer 1
+        let source = format!("\"A\"45*00p\"B\"45*01p@  \u{c}{}", " ".repeat(21));
+        let interpreter = Interpreter::new(
+            CodeGrid::parse(&source, Dimensions::Trefunge),
+            false,
+            Arc::new(CommandRegistry::for_dimensions(Dimensions::Trefunge)),
+        )
+        .with_dimensions(Dimensions::Trefunge)
+        .with_history(Some(32));
+        testing::outcome(interpreter.clone(), "");
+        let since = |z| {
+            interpreter
+                .steps_since_write(Position::new(20, 0, z))
+                .unwrap()
+        };
+        assert_eq!((since(0), since(1)), (Some(11), Some(2)));
+
+        let region: Region = "9,0,1".parse().unwrap();
+        assert!(region.contains(Position::new(9, 0, 1)));
+        assert!(!region.contains(Position::new(9, 0, 0)));
+        assert_eq!(
+            "9,0".parse::<Breakpoint>().unwrap().to_string(),
+            "cell (9, 0)"
+        );
+        assert_eq!(
+            "9,0,1".parse::<Breakpoint>().unwrap().to_string(),
+            "cell (9, 0, 1)"
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let at = Position::new(1, 2, 3);
+        assert_eq!(Dimensions::Befunge.format(at), "(1, 2)");
+        assert_eq!(Dimensions::Trefunge.format(at), "(1, 2, 3)");
+        assert!(!Dimensions::Unefunge.allows(Direction::Down));
+        assert!(Dimensions::Trefunge.allows(Direction::Low));
+    }
+}
@@ -1,11 +1,20 @@
 // src/entities/direction.rs
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Direction {
     Right,
     Left,
     Up,
     Down,
+    /// Trefunge: towards the next layer
+    High,
+    /// Trefunge: towards the previous layer
+    Low,
 }
 
 impl Direction {
@@ -15,6 +24,7 @@ impl Direction {
             Direction::Left => -1,
             Direction::Up => 0,
             Direction::Down => 0,
+            Direction::High | Direction::Low => 0,
         }
     }
 
@@ -24,8 +34,29 @@ impl Direction {
             Direction::Left => 0,
             Direction::Up => -1,
             Direction::Down => 1,
+            Direction::High | Direction::Low => 0,
+        }
+    }
+
+    pub fn dz(&self) -> isize {
+        match self {
+            Direction::High => 1,
+            Direction::Low => -1,
+            _ => 0,
+        }
+    }
+
+    pub fn reverse(&self) -> Self {
+        match self {
+            Direction::Right => Direction::Left,
+            Direction::Left => Direction::Right,
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::High => Direction::Low,
+            Direction::Low => Direction::High,
         }
     }
+
     pub fn from_dx_dy(dx: isize, dy: isize) -> Option<Self> {
         match (dx, dy) {
             (1, 0) => Some(Direction::Right),
@@ -36,3 +67,35 @@ impl Direction {
         }
     }
 }
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Direction::Right => "right",
+            Direction::Left => "left",
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::High => "high",
+            Direction::Low => "low",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    /// `right` / `left` / `up` / `down` / `high` / `low`, or the instructions
+    /// `>` `<` `^` `v` `h` `l`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "right" | ">" => Ok(Direction::Right),
+            "left" | "<" => Ok(Direction::Left),
+            "up" | "^" => Ok(Direction::Up),
+            "down" | "v" => Ok(Direction::Down),
+            "high" | "h" => Ok(Direction::High),
+            "low" | "l" => Ok(Direction::Low),
+            other => Err(format!("unknown direction `{}`", other)),
+        }
+    }
+}
@@ -1,25 +1,56 @@
 // src/entities/ip_state.rs
 
-use crate::entities::Direction;
+use crate::entities::{Direction, Position};
+use std::collections::VecDeque;
 
 pub struct IPState {
+    pub id: usize,
     pub ip_x: usize,
     pub ip_y: usize,
+    /// Trefunge の層（それ以外では常に 0）
+    pub ip_z: usize,
     pub direction: Direction,
     pub terminated: bool,
     pub stk: Vec<usize>,
     pub string_mode_active: bool,
+    /// 命令の実行後に向きを反転する（何度 reflect しても1命令に1回）
+    pub reflect_pending: bool,
+    /// 直近に通過したセル（古い順）
+    pub trail: VecDeque<Position>,
 }
 
 impl IPState {
+    pub const TRAIL_LENGTH: usize = 8;
+
     pub fn new(x: usize, y: usize, direction: Direction) -> Self {
         Self {
+            id: 0,
             ip_x: x,
             ip_y: y,
+            ip_z: 0,
             direction,
             terminated: false,
             stk: Vec::new(),
             string_mode_active: false,
+            reflect_pending: false,
+            trail: VecDeque::with_capacity(Self::TRAIL_LENGTH),
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        Position::new(self.ip_x, self.ip_y, self.ip_z)
+    }
+
+    pub fn set_position(&mut self, at: Position) {
+        self.ip_x = at.x;
+        self.ip_y = at.y;
+        self.ip_z = at.z;
+    }
+
+    pub fn remember_position(&mut self) {
+        if self.trail.len() == Self::TRAIL_LENGTH {
+            self.trail.pop_front();
         }
+        self.trail.push_back(self.position());
     }
 }
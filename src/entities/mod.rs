@@ -1,10 +1,16 @@
 // src/entities/mod.rs
 
+pub mod breakpoint;
 pub mod code_grid;
+pub mod dimensions;
 pub mod direction;
 pub mod ip_state;
+pub mod position;
 
 // 再エクスポート（必要に応じて）
+pub use breakpoint::{Breakpoint, Watchpoint};
 pub use code_grid::CodeGrid;
+pub use dimensions::Dimensions;
 pub use direction::Direction;
 pub use ip_state::IPState;
+pub use position::{Position, Size};
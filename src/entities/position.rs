@@ -0,0 +1,58 @@
+// src/entities/position.rs
+
+use crate::entities::Direction;
+use std::fmt;
+
+/// A cell of Funge-Space. Unefunge and Befunge programs leave the coordinates they do not
+/// have at 0.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+impl Position {
+    pub fn new(x: usize, y: usize, z: usize) -> Self {
+        Self { x, y, z }
+    }
+
+    /// the cell one step in `direction`, without bounds
+    pub fn step(&self, direction: Direction) -> (isize, isize, isize) {
+        (
+            self.x as isize + direction.dx(),
+            self.y as isize + direction.dy(),
+            self.z as isize + direction.dz(),
+        )
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {}, {})", self.x, self.y, self.z)
+    }
+}
+
+/// The extent of a program: columns, rows per layer and layers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Size {
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+}
+
+impl Size {
+    pub fn new(width: usize, height: usize, depth: usize) -> Self {
+        Self {
+            width,
+            height,
+            depth,
+        }
+    }
+
+    pub fn contains(&self, x: isize, y: isize, z: isize) -> bool {
+        (0..self.width as isize).contains(&x)
+            && (0..self.height as isize).contains(&y)
+            && (0..self.depth as isize).contains(&z)
+    }
+}
@@ -0,0 +1,126 @@
+// src/errors/diagnostic.rs
+
+use crate::entities::Position;
+use crate::errors::runtime_error::format_cell;
+use crate::errors::RuntimeError;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A runtime error or warning together with the part of the grid around it.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub error: RuntimeError,
+    /// cut-out of the grid around the offending cell
+    pub snippet: Vec<Vec<char>>,
+    /// grid coordinates of `snippet[0][0]`
+    pub origin: (usize, usize),
+    /// found by static analysis: there is no running IP, `notes` replace its path and stack
+    pub is_static: bool,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// rows shown above and below the offending cell
+    pub const ROW_RADIUS: usize = 2;
+    /// columns shown left and right of the offending cell
+    pub const COLUMN_RADIUS: usize = 8;
+
+    pub fn new(severity: Severity, error: RuntimeError, grid: &[Vec<char>]) -> Self {
+        let (x, y) = (error.context.x, error.context.y);
+        let top = y.saturating_sub(Self::ROW_RADIUS);
+        let left = x.saturating_sub(Self::COLUMN_RADIUS);
+        let bottom = (y + Self::ROW_RADIUS + 1).min(grid.len());
+        let snippet = grid
+            .get(top..bottom)
+            .unwrap_or_default()
+            .iter()
+            .map(|row| {
+                let right = (x + Self::COLUMN_RADIUS + 1).min(row.len());
+                row.get(left..right).unwrap_or_default().to_vec()
+            })
+            .collect();
+        Self {
+            severity,
+            error,
+            snippet,
+            origin: (left, top),
+            is_static: false,
+            notes: Vec::new(),
+        }
+    }
+
+    /// a finding of `check`, with `notes` shown below the snippet
+    pub fn finding(
+        severity: Severity,
+        error: RuntimeError,
+        grid: &[Vec<char>],
+        notes: Vec<String>,
+    ) -> Self {
+        Self {
+            is_static: true,
+            notes,
+            ..Self::new(severity, error, grid)
+        }
+    }
+
+    pub fn error(error: RuntimeError, grid: &[Vec<char>]) -> Self {
+        Self::new(Severity::Error, error, grid)
+    }
+
+    pub fn warning(error: RuntimeError, grid: &[Vec<char>]) -> Self {
+        Self::new(Severity::Warning, error, grid)
+    }
+
+    /// position of the offending cell inside `snippet`
+    pub fn focus(&self) -> (usize, usize) {
+        (
+            self.error.context.x - self.origin.0,
+            self.error.context.y - self.origin.1,
+        )
+    }
+
+    /// the offending cell, `(x, y)` or `(x, y, z)` in Trefunge
+    pub fn cell(&self) -> String {
+        let ctx = &self.error.context;
+        format_cell(ctx.x, ctx.y, ctx.z)
+    }
+
+    /// recent path formatted as `(x, y) -> (x, y) -> ...`, ending at the offending cell
+    pub fn path(&self) -> String {
+        let ctx = &self.error.context;
+        ctx.trail
+            .iter()
+            .map(|at| format_cell(at.x, at.y, ctx.z.map(|_| at.z)))
+            .chain(std::iter::once(self.cell()))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+
+    /// the IP passed the snippet's cell (x, y) on its way here
+    pub fn on_trail(&self, x: usize, y: usize) -> bool {
+        let ctx = &self.error.context;
+        let z = ctx.z.unwrap_or(0);
+        ctx.trail.contains(&Position::new(x, y, z))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.error)
+    }
+}
@@ -1,5 +1,6 @@
 // errors.rs
 
+use crate::errors::RuntimeError;
 use std::fmt;
 use std::io;
 use std::sync::{MutexGuard, PoisonError};
@@ -12,6 +13,9 @@ pub enum InterpreterError {
     LockError(String),
     FileNotFound(String),
     FileReadError(String),
+    Runtime(Box<RuntimeError>),
+    /// a replayed run left its trace at this step
+    TraceMismatch(usize),
 }
 
 impl fmt::Display for InterpreterError {
@@ -23,6 +27,8 @@ impl fmt::Display for InterpreterError {
             InterpreterError::ParseError(s) => write!(f, "Parse Error: {}", s),
             InterpreterError::ThreadError(s) => write!(f, "Thread Error: {}", s),
             InterpreterError::LockError(s) => write!(f, "Lock Error: {}", s),
+            InterpreterError::Runtime(e) => write!(f, "Runtime Error: {}", e),
+            InterpreterError::TraceMismatch(step) => write!(f, "Trace Mismatch at step {}", step),
         }
     }
 }
@@ -33,6 +39,18 @@ impl From<io::Error> for InterpreterError {
     }
 }
 
+impl From<serde_json::Error> for InterpreterError {
+    fn from(error: serde_json::Error) -> Self {
+        InterpreterError::ParseError(error.to_string())
+    }
+}
+
+impl From<RuntimeError> for InterpreterError {
+    fn from(error: RuntimeError) -> Self {
+        InterpreterError::Runtime(Box::new(error))
+    }
+}
+
 // Removed the specific implementation for Vec<Vec<char>>
 
 // Retain the generic implementation
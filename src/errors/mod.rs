@@ -1,6 +1,11 @@
 // src/errors/mod.rs
 
+pub mod diagnostic;
+#[allow(clippy::module_inception)]
 pub mod errors;
+pub mod runtime_error;
 
 // 再エクスポート
+pub use diagnostic::{Diagnostic, Severity};
 pub use errors::InterpreterError;
+pub use runtime_error::{format_cell, GridAccess, RuntimeContext, RuntimeError, RuntimeErrorKind};
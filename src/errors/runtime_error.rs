@@ -0,0 +1,170 @@
+// src/errors/runtime_error.rs
+
+use crate::entities::{Direction, IPState, Position};
+use std::fmt;
+
+/// Snapshot of the IP that raised a runtime error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeContext {
+    pub ip_id: usize,
+    pub x: usize,
+    pub y: usize,
+    /// layer of a Trefunge program, `None` in fewer dimensions
+    pub z: Option<usize>,
+    pub direction: Direction,
+    pub instruction: char,
+    pub stack_top: Option<usize>,
+    pub stack: Vec<usize>,
+    /// cells passed before, oldest first
+    pub trail: Vec<Position>,
+}
+
+impl RuntimeContext {
+    pub fn capture(ip: &IPState, instruction: char) -> Self {
+        Self {
+            ip_id: ip.id,
+            x: ip.ip_x,
+            y: ip.ip_y,
+            z: None,
+            direction: ip.direction,
+            instruction,
+            stack_top: ip.stk.last().copied(),
+            stack: ip.stk.clone(),
+            trail: ip.trail.iter().copied().collect(),
+        }
+    }
+
+    /// record the IP's layer, for Trefunge programs
+    pub fn with_layer(mut self, z: Option<usize>) -> Self {
+        self.z = z;
+        self
+    }
+}
+
+/// `(x, y)`, or `(x, y, z)` with a layer
+pub fn format_cell(x: usize, y: usize, z: Option<usize>) -> String {
+    match z {
+        Some(z) => format!("({}, {}, {})", x, y, z),
+        None => format!("({}, {})", x, y),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridAccess {
+    Get,
+    Put,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    StackUnderflow,
+    OutOfBounds {
+        access: GridAccess,
+        x: usize,
+        y: usize,
+        z: Option<usize>,
+    },
+    UnknownInstruction,
+    InputEof,
+    LimitExceeded {
+        limit: &'static str,
+        value: usize,
+    },
+    /// a replayed session expected more instructions from the IP
+    ReplayDiverged,
+    /// found by `check`: cells no IP can reach
+    UnreachableCode,
+    /// found by `check`: the IP moves off the program into empty space
+    LeavesProgram,
+    /// found by `check`: string mode runs off the program and pushes the empty space
+    UnclosedString,
+    /// found by `check`: `t` with deltas that are not a unit step
+    InvalidDelta {
+        dx: usize,
+        dy: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub context: RuntimeContext,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, context: RuntimeContext) -> Self {
+        Self { kind, context }
+    }
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::DivisionByZero => write!(f, "division by zero"),
+            RuntimeErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            RuntimeErrorKind::OutOfBounds {
+                access: GridAccess::Get,
+                x,
+                y,
+                z,
+            } => write!(
+                f,
+                "attempt to get value out of bounds at {}",
+                format_cell(*x, *y, *z)
+            ),
+            RuntimeErrorKind::OutOfBounds {
+                access: GridAccess::Put,
+                x,
+                y,
+                z,
+            } => write!(
+                f,
+                "attempt to set value out of bounds at {}",
+                format_cell(*x, *y, *z)
+            ),
+            RuntimeErrorKind::UnknownInstruction => write!(f, "unknown instruction"),
+            RuntimeErrorKind::InputEof => write!(f, "unexpected end of input"),
+            RuntimeErrorKind::LimitExceeded { limit, value } => {
+                write!(f, "{} limit of {} exceeded", limit, value)
+            }
+            RuntimeErrorKind::ReplayDiverged => write!(
+                f,
+                "session replay diverged, the IP ended before its recorded instructions"
+            ),
+            RuntimeErrorKind::UnreachableCode => write!(f, "unreachable code"),
+            RuntimeErrorKind::LeavesProgram => {
+                write!(f, "IP runs off the program into empty space")
+            }
+            RuntimeErrorKind::UnclosedString => {
+                write!(f, "string mode runs off the program")
+            }
+            RuntimeErrorKind::InvalidDelta { dx, dy } => {
+                write!(
+                    f,
+                    "invalid delta ({}, {}) for `t`",
+                    *dx as isize, *dy as isize
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ctx = &self.context;
+        write!(
+            f,
+            "{} (ip {} at {} moving {}, instruction {:?}, stack top: ",
+            self.kind,
+            ctx.ip_id,
+            format_cell(ctx.x, ctx.y, ctx.z),
+            ctx.direction,
+            ctx.instruction
+        )?;
+        match ctx.stack_top {
+            Some(top) => write!(f, "{})", top),
+            None => write!(f, "empty)"),
+        }
+    }
+}
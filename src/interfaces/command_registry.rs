@@ -1,12 +1,17 @@
 // src/interfaces/command_registry.rs
 
-use crate::engine::commands::command::Command;
+use crate::engine::commands::command::{Command, StackEffect};
 use crate::engine::commands::digit_command::DigitCommand;
 use crate::engine::commands::down_command::DownCommand;
 use crate::engine::commands::get_command::GetCommand;
 use crate::engine::commands::greater_than_command::GraterThanCommand;
+use crate::engine::commands::high_command::HighCommand;
+use crate::engine::commands::high_low_if_command::HighLowIfCommand;
 use crate::engine::commands::horizontal_if_command::HorizontalIfCommand;
 use crate::engine::commands::left_command::LeftCommand;
+use crate::engine::commands::low_command::LowCommand;
+use crate::engine::commands::move_command::MoveCommand;
+use crate::engine::commands::nop_command::NopCommand;
 use crate::engine::commands::read_number_command::ReadNumberCommand;
 use crate::engine::commands::right_command::RightCommand;
 use crate::engine::commands::{
@@ -21,6 +26,7 @@ use crate::engine::commands::{
 use crate::engine::commands::{
     put_command::PutCommand, read_character_command::ReadCharacterCommand,
 };
+use crate::entities::Dimensions;
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -40,6 +46,11 @@ impl CommandResolve for CommandRegistry {
 
 impl CommandRegistry {
     pub fn new() -> Self {
+        Self::for_dimensions(Dimensions::Befunge)
+    }
+
+    /// Befunge の命令から、Unefunge では上下の移動を除き、Trefunge では層の移動を加える
+    pub fn for_dimensions(dimensions: Dimensions) -> Self {
         let mut commands: HashMap<char, Arc<dyn Command + Send + Sync>> = HashMap::new();
         commands.insert('+', Arc::new(AddCommand));
         commands.insert('-', Arc::new(SubtractCommand));
@@ -60,12 +71,17 @@ impl CommandRegistry {
         commands.insert('<', Arc::new(LeftCommand));
         commands.insert('^', Arc::new(UpCommand));
         commands.insert('v', Arc::new(DownCommand));
-        commands.insert('p', Arc::new(PutCommand));
-        commands.insert('g', Arc::new(GetCommand));
+        commands.insert('p', Arc::new(PutCommand::new(dimensions)));
+        commands.insert('g', Arc::new(GetCommand::new(dimensions)));
         commands.insert('"', Arc::new(StringModeCommand));
         commands.insert('&', Arc::new(ReadNumberCommand));
         commands.insert('~', Arc::new(ReadCharacterCommand));
         commands.insert('`', Arc::new(GraterThanCommand));
+        commands.insert('#', Arc::new(MoveCommand));
+        // 空白と初期方向の矢印は何もしない
+        for nop in [' ', '→', '←', '↑', '↓'] {
+            commands.insert(nop, Arc::new(NopCommand));
+        }
         // 数字コマンドを登録
         for digit in 0..=9 {
             commands.insert(
@@ -73,42 +89,85 @@ impl CommandRegistry {
                 Arc::new(DigitCommand::new(digit as usize)),
             );
         }
+        match dimensions {
+            Dimensions::Unefunge => {
+                for cmd in ['^', 'v', '|'] {
+                    commands.remove(&cmd);
+                }
+            }
+            Dimensions::Befunge => {}
+            Dimensions::Trefunge => {
+                commands.insert('h', Arc::new(HighCommand));
+                commands.insert('l', Arc::new(LowCommand));
+                commands.insert('m', Arc::new(HighLowIfCommand));
+            }
+        }
         Self { commands }
     }
+
+    /// the stack effect of `cmd`, `None` if no command is registered for it
+    pub fn stack_effect(&self, cmd: char) -> Option<StackEffect> {
+        self.get_command(cmd).map(|command| command.stack_effect())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::engine::commands::command::CommandGrid;
+    use crate::engine::semantics::{
+        DivisionByZeroPolicy, Semantics, StackUnderflowPolicy, UnknownInstructionPolicy,
+    };
+    use crate::engine::testing;
     use crate::entities::ip_state::IPState;
-    use crate::entities::Direction;
-    use crate::errors::InterpreterError;
+    use crate::entities::{Direction, Position};
+    use crate::errors::{InterpreterError, RuntimeContext, RuntimeError, RuntimeErrorKind};
     use crate::interfaces::IOHandle;
     use std::sync::{Arc, Mutex};
 
     struct MockCommandGrid {
         grid: Mutex<Vec<Vec<char>>>,
+        semantics: Semantics,
     }
 
     impl MockCommandGrid {
         fn new() -> Self {
             Self {
                 grid: Mutex::new(vec![vec![' '; 10]; 10]),
+                semantics: Semantics::default(),
             }
         }
 
         fn from_grid(grid: Vec<Vec<char>>) -> Self {
             Self {
                 grid: Mutex::new(grid),
+                semantics: Semantics::default(),
+            }
+        }
+
+        fn with_semantics(semantics: Semantics) -> Self {
+            Self {
+                grid: Mutex::new(vec![vec![' '; 10]; 10]),
+                semantics,
             }
         }
     }
 
     impl CommandGrid for MockCommandGrid {
-        fn pop(&self, ip: Arc<Mutex<IPState>>) -> Result<usize, InterpreterError> {
-            let mut ip_locked = ip.lock().unwrap();
-            Ok(ip_locked.stk.pop().unwrap_or(0))
+        fn pop(
+            &self,
+            ip: Arc<Mutex<IPState>>,
+            io_handler: Arc<dyn IOHandle + Send + Sync>,
+        ) -> Result<usize, InterpreterError> {
+            let popped = ip.lock().unwrap().stk.pop();
+            match popped {
+                Some(value) => Ok(value),
+                None => self.semantics.on_stack_underflow(ip, self, io_handler),
+            }
+        }
+
+        fn semantics(&self) -> &Semantics {
+            &self.semantics
         }
 
         fn move_ip(&self, _ip: Arc<Mutex<IPState>>) -> Result<(), InterpreterError> {
@@ -135,15 +194,35 @@ mod tests {
             Ok(())
         }
 
-        fn set_value(&self, x: usize, y: usize, value: char) -> Result<(), InterpreterError> {
+        fn set_value(
+            &self,
+            _ip: Arc<Mutex<IPState>>,
+            at: Position,
+            value: char,
+        ) -> Result<(), InterpreterError> {
             let mut grid = self.grid.lock().unwrap();
-            grid[y][x] = value;
+            grid[at.y][at.x] = value;
             Ok(())
         }
 
-        fn get_value(&self, x: usize, y: usize) -> Result<char, InterpreterError> {
+        fn get_value(
+            &self,
+            _ip: Arc<Mutex<IPState>>,
+            at: Position,
+        ) -> Result<char, InterpreterError> {
             let grid = self.grid.lock().unwrap();
-            Ok(grid[y][x])
+            Ok(grid[at.y][at.x])
+        }
+
+        fn runtime_error(
+            &self,
+            ip: Arc<Mutex<IPState>>,
+            kind: RuntimeErrorKind,
+        ) -> InterpreterError {
+            let ip_locked = ip.lock().unwrap();
+            let at = ip_locked.position();
+            let instruction = self.grid.lock().unwrap()[at.y][at.x];
+            RuntimeError::new(kind, RuntimeContext::capture(&ip_locked, instruction)).into()
         }
     }
 
@@ -175,6 +254,7 @@ mod tests {
             _grid: &[Vec<char>],
             _ip_x: usize,
             _ip_y: usize,
+            _layer: Option<usize>,
         ) -> Result<(), InterpreterError> {
             todo!()
         }
@@ -204,8 +284,7 @@ mod tests {
         }
 
         fn read_char(&self) -> Result<char, InterpreterError> {
-            Err(InterpreterError::IoError(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            Err(InterpreterError::IoError(std::io::Error::other(
                 "read_char not implemented",
             )))
         }
@@ -219,6 +298,7 @@ mod tests {
             _grid: &[Vec<char>],
             _ip_x: usize,
             _ip_y: usize,
+            _layer: Option<usize>,
         ) -> Result<(), InterpreterError> {
             Ok(())
         }
@@ -244,8 +324,7 @@ mod tests {
         }
 
         fn read_number(&self) -> Result<usize, InterpreterError> {
-            Err(InterpreterError::IoError(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            Err(InterpreterError::IoError(std::io::Error::other(
                 "read_number not implemented",
             )))
         }
@@ -263,10 +342,49 @@ mod tests {
             _grid: &[Vec<char>],
             _ip_x: usize,
             _ip_y: usize,
+            _layer: Option<usize>,
+        ) -> Result<(), InterpreterError> {
+            Ok(())
+        }
+    }
+    struct MockIOHandlerAtEof;
+
+    impl IOHandle for MockIOHandlerAtEof {
+        fn write_output(&self, _output: &str) -> Result<(), InterpreterError> {
+            Ok(())
+        }
+
+        fn write_error(&self, _error: &str) -> Result<(), InterpreterError> {
+            Ok(())
+        }
+
+        fn read_number(&self) -> Result<usize, InterpreterError> {
+            Err(InterpreterError::IoError(
+                std::io::ErrorKind::UnexpectedEof.into(),
+            ))
+        }
+
+        fn read_char(&self) -> Result<char, InterpreterError> {
+            Err(InterpreterError::IoError(
+                std::io::ErrorKind::UnexpectedEof.into(),
+            ))
+        }
+
+        fn display_stack(&self, _stack: &[usize]) -> Result<(), InterpreterError> {
+            Ok(())
+        }
+
+        fn display_grid(
+            &self,
+            _grid: &[Vec<char>],
+            _ip_x: usize,
+            _ip_y: usize,
+            _layer: Option<usize>,
         ) -> Result<(), InterpreterError> {
             Ok(())
         }
     }
+
     #[test]
     fn test_command_registry_add_command() {
         let registry = CommandRegistry::new();
@@ -289,6 +407,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_command_registry_whitespace_and_arrows() {
+        let registry = CommandRegistry::new();
+
+        for c in [' ', '→', '←', '↑', '↓'] {
+            assert!(
+                registry.get_command(c).is_some(),
+                "{:?} should be handled explicitly",
+                c
+            );
+        }
+    }
+
     #[test]
     fn test_command_registry_digit_command() {
         let registry = CommandRegistry::new();
@@ -300,6 +431,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_command_registry_stack_effect() {
+        let registry = CommandRegistry::new();
+
+        assert_eq!(registry.stack_effect('p'), Some(StackEffect::new(3, 0)));
+        assert_eq!(registry.stack_effect(':'), Some(StackEffect::new(1, 2)));
+        assert_eq!(registry.stack_effect('7'), Some(StackEffect::new(0, 1)));
+        assert_eq!(registry.stack_effect('>'), Some(StackEffect::new(0, 0)));
+        assert_eq!(registry.stack_effect('x'), None);
+    }
+
     #[test]
     fn test_command_execution_add_command() {
         let registry = CommandRegistry::new();
@@ -511,7 +653,9 @@ mod tests {
             .unwrap();
 
         // Verify 'A' is placed at (1, 2)
-        let value = mock_command_grid.get_value(1, 2).unwrap();
+        let value = mock_command_grid
+            .get_value(ip.clone(), Position::new(1, 2, 0))
+            .unwrap();
         assert_eq!(value, 'A', "The value at (1, 2) should be 'A'");
     }
 
@@ -526,7 +670,9 @@ mod tests {
         let mock_io_handler = Arc::new(MockIOHandler);
 
         // 'B' at (3, 4) in the grid
-        mock_command_grid.set_value(3, 4, 'B').unwrap();
+        mock_command_grid
+            .set_value(ip.clone(), Position::new(3, 4, 0), 'B')
+            .unwrap();
 
         // getting a character from (3, 4)
         {
@@ -1009,4 +1155,203 @@ mod tests {
             "ReadCharacterCommand should push ASCII value of 'A' onto the stack"
         );
     }
+
+    #[test]
+    fn test_read_character_command_at_eof() {
+        let registry = CommandRegistry::new();
+        let read_character_command = registry.get_command('~').unwrap();
+
+        // Mock objects
+        let grid = vec![vec![' ', '~', ' '], vec![' ', ' ', ' ']];
+        let mock_command_grid = MockCommandGrid::from_grid(grid);
+        let mock_io_handler = Arc::new(MockIOHandlerAtEof);
+        let ip = Arc::new(Mutex::new(IPState::new(1, 0, Direction::Down)));
+        {
+            let mut ip_locked = ip.lock().unwrap();
+            ip_locked.id = 3;
+            ip_locked.stk.push(7);
+        }
+
+        // Execute the command
+        let result =
+            read_character_command.execute(ip.clone(), &mock_command_grid, mock_io_handler);
+
+        // Verify the error carries the IP context
+        match result {
+            Err(InterpreterError::Runtime(e)) => {
+                assert_eq!(e.kind, RuntimeErrorKind::InputEof);
+                assert_eq!(e.context.ip_id, 3);
+                assert_eq!((e.context.x, e.context.y), (1, 0));
+                assert_eq!(e.context.direction, Direction::Down);
+                assert_eq!(e.context.instruction, '~');
+                assert_eq!(e.context.stack_top, Some(7));
+            }
+            _ => panic!("ReadCharacterCommand should fail with InputEof at end of input"),
+        }
+    }
+
+    #[test]
+    fn test_divide_command_by_zero_reflects() {
+        let registry = CommandRegistry::new();
+        let divide_command = registry.get_command('/').unwrap();
+
+        // Mock objects
+        let ip = Arc::new(Mutex::new(IPState::new(0, 0, Direction::Right)));
+        let mock_command_grid = MockCommandGrid::with_semantics(Semantics::new(
+            DivisionByZeroPolicy::Reflect,
+            StackUnderflowPolicy::PushZero,
+            UnknownInstructionPolicy::Ignore,
+        ));
+        let mock_io_handler = Arc::new(MockIOHandler);
+        {
+            let mut ip_locked = ip.lock().unwrap();
+            ip_locked.stk.push(10);
+            ip_locked.stk.push(0);
+        }
+
+        // Execute the command
+        divide_command
+            .execute(ip.clone(), &mock_command_grid, mock_io_handler.clone())
+            .unwrap();
+
+        // Verify the operands are consumed and the IP turns around after the instruction
+        let ip_locked = ip.lock().unwrap();
+        assert!(ip_locked.stk.is_empty(), "Reflect should push nothing");
+        assert!(ip_locked.reflect_pending);
+    }
+
+    #[test]
+    fn test_modulo_command_by_zero_prompts() {
+        let registry = CommandRegistry::new();
+        let modulo_command = registry.get_command('%').unwrap();
+
+        // Mock objects
+        let ip = Arc::new(Mutex::new(IPState::new(0, 0, Direction::Right)));
+        let mock_command_grid = MockCommandGrid::with_semantics(Semantics::new(
+            DivisionByZeroPolicy::Prompt,
+            StackUnderflowPolicy::PushZero,
+            UnknownInstructionPolicy::Ignore,
+        ));
+        let mock_io_handler = Arc::new(MockIOHandlerWithNumber::new(42));
+        {
+            let mut ip_locked = ip.lock().unwrap();
+            ip_locked.stk.push(10);
+            ip_locked.stk.push(0);
+        }
+
+        // Execute the command
+        modulo_command
+            .execute(ip.clone(), &mock_command_grid, mock_io_handler.clone())
+            .unwrap();
+
+        // Verify the answer from the user is pushed
+        let mut ip_locked = ip.lock().unwrap();
+        assert_eq!(ip_locked.stk.pop(), Some(42));
+    }
+
+    #[test]
+    fn test_stack_underflow_error_policy() {
+        let registry = CommandRegistry::new();
+        let add_command = registry.get_command('+').unwrap();
+
+        // Mock objects
+        let ip = Arc::new(Mutex::new(IPState::new(0, 0, Direction::Right)));
+        let mock_command_grid = MockCommandGrid::with_semantics(Semantics::new(
+            DivisionByZeroPolicy::WarnOnce,
+            StackUnderflowPolicy::Error,
+            UnknownInstructionPolicy::Ignore,
+        ));
+        let mock_io_handler = Arc::new(MockIOHandler);
+        ip.lock().unwrap().stk.push(1);
+
+        // Execute the command with a single value on the stack
+        let result = add_command.execute(ip.clone(), &mock_command_grid, mock_io_handler);
+
+        // Verify the second pop fails
+        match result {
+            Err(InterpreterError::Runtime(e)) => {
+                assert_eq!(e.kind, RuntimeErrorKind::StackUnderflow)
+            }
+            _ => panic!("AddCommand should fail with StackUnderflow"),
+        }
+    }
+
+    #[test]
+    fn test_stack_underflow_reflects_once() {
+        // `+` fails both pops: the IP turns around once, onto the `v`
+        let interpreter = testing::interpreter("#v+1.@\n >.@").with_semantics(Semantics::new(
+            DivisionByZeroPolicy::WarnOnce,
+            StackUnderflowPolicy::Reflect,
+            UnknownInstructionPolicy::Ignore,
+        ));
+        assert_eq!(
+            testing::outcome(interpreter, ""),
+            ("0 ".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn test_unknown_instruction_policies() {
+        let mock_io_handler = Arc::new(MockIOHandler);
+
+        // Reflect turns the IP around
+        let reflecting = MockCommandGrid::with_semantics(Semantics::new(
+            DivisionByZeroPolicy::WarnOnce,
+            StackUnderflowPolicy::PushZero,
+            UnknownInstructionPolicy::Reflect,
+        ));
+        let ip = Arc::new(Mutex::new(IPState::new(0, 0, Direction::Up)));
+        reflecting
+            .semantics()
+            .on_unknown_instruction(ip.clone(), 'x', &reflecting, mock_io_handler.clone())
+            .unwrap();
+        assert!(ip.lock().unwrap().reflect_pending);
+
+        // Error stops the IP
+        let failing = MockCommandGrid::with_semantics(Semantics::new(
+            DivisionByZeroPolicy::WarnOnce,
+            StackUnderflowPolicy::PushZero,
+            UnknownInstructionPolicy::Error,
+        ));
+        let result =
+            failing
+                .semantics()
+                .on_unknown_instruction(ip.clone(), 'x', &failing, mock_io_handler);
+        match result {
+            Err(InterpreterError::Runtime(e)) => {
+                assert_eq!(e.kind, RuntimeErrorKind::UnknownInstruction)
+            }
+            _ => panic!("Unknown instruction should fail under the error policy"),
+        }
+    }
+
+    #[test]
+    fn test_registry_for_dimensions() {
+        use crate::entities::Dimensions;
+
+        let unefunge = CommandRegistry::for_dimensions(Dimensions::Unefunge);
+        assert!(unefunge.get_command('v').is_none());
+        assert_eq!(unefunge.stack_effect('p'), Some(StackEffect::new(2, 0)));
+        let befunge = CommandRegistry::new();
+        assert!(befunge.get_command('h').is_none());
+        assert_eq!(befunge.stack_effect('g'), Some(StackEffect::new(2, 1)));
+        let trefunge = CommandRegistry::for_dimensions(Dimensions::Trefunge);
+        assert_eq!(trefunge.stack_effect('m'), Some(StackEffect::new(1, 0)));
+        assert_eq!(trefunge.stack_effect('p'), Some(StackEffect::new(4, 0)));
+    }
+
+    #[test]
+    fn test_high_low_if_command() {
+        let registry = CommandRegistry::for_dimensions(crate::entities::Dimensions::Trefunge);
+        let command = registry.get_command('m').unwrap();
+        let ip = Arc::new(Mutex::new(IPState::new(0, 0, Direction::Right)));
+        let grid = MockCommandGrid::new();
+        let io = Arc::new(MockIOHandler);
+        ip.lock().unwrap().stk.push(0);
+        command.execute(ip.clone(), &grid, io.clone()).unwrap();
+        assert_eq!(ip.lock().unwrap().direction, Direction::Low);
+        ip.lock().unwrap().stk.push(5);
+        command.execute(ip.clone(), &grid, io).unwrap();
+        assert_eq!(ip.lock().unwrap().direction, Direction::High);
+    }
 }
@@ -1,8 +1,8 @@
 // src/interfaces/console_io_handler.rs
 
-use crate::errors::InterpreterError;
+use crate::errors::{Diagnostic, InterpreterError, Severity};
 use crate::interfaces::IOHandle;
-use std::io::{self, Read, Write};
+use std::io::{self, IsTerminal, Read, Write};
 
 pub struct ConsoleIOHandler {
     is_verbose_mode: bool,
@@ -19,14 +19,10 @@ impl IOHandle for ConsoleIOHandler {
         if self.is_verbose_mode {
             println!();
             println!("[STDOUT] {}", output);
-            io::stdout()
-                .flush()
-                .map_err(|e| InterpreterError::IoError(e))
+            io::stdout().flush().map_err(InterpreterError::IoError)
         } else {
             print!("{}", output);
-            io::stdout()
-                .flush()
-                .map_err(|e| InterpreterError::IoError(e))
+            io::stdout().flush().map_err(InterpreterError::IoError)
         }
     }
 
@@ -34,14 +30,10 @@ impl IOHandle for ConsoleIOHandler {
         if self.is_verbose_mode {
             eprintln!();
             eprintln!("[STDERR] {}", error);
-            io::stderr()
-                .flush()
-                .map_err(|e| InterpreterError::IoError(e))
+            io::stderr().flush().map_err(InterpreterError::IoError)
         } else {
             eprintln!("{}", error);
-            io::stderr()
-                .flush()
-                .map_err(|e| InterpreterError::IoError(e))
+            io::stderr().flush().map_err(InterpreterError::IoError)
         }
     }
 
@@ -50,9 +42,14 @@ impl IOHandle for ConsoleIOHandler {
             println!("[Reading Num] >");
         }
         let mut input = String::new();
-        io::stdin()
+        let read = io::stdin()
             .read_line(&mut input)
-            .map_err(|e| InterpreterError::IoError(e))?;
+            .map_err(InterpreterError::IoError)?;
+        if read == 0 {
+            return Err(InterpreterError::IoError(
+                io::ErrorKind::UnexpectedEof.into(),
+            ));
+        }
         input
             .trim()
             .parse::<usize>()
@@ -66,7 +63,7 @@ impl IOHandle for ConsoleIOHandler {
         }
         io::stdin()
             .read_exact(&mut buffer)
-            .map_err(|e| InterpreterError::IoError(e))?;
+            .map_err(InterpreterError::IoError)?;
         Ok(buffer[0] as char)
     }
 
@@ -100,20 +97,204 @@ impl IOHandle for ConsoleIOHandler {
         grid: &[Vec<char>],
         ip_x: usize,
         ip_y: usize,
+        layer: Option<usize>,
     ) -> Result<(), InterpreterError> {
-        println!("[IP on Grid] : ");
+        match layer {
+            Some(z) => println!("[IP on Grid] (layer {}) : ", z),
+            None => println!("[IP on Grid] : "),
+        }
         for (y, row) in grid.iter().enumerate() {
-            print!("{:5}", y);
-            for (x, &cell) in row.iter().enumerate() {
-                if x == ip_x && y == ip_y {
-                    print!("[{}]", cell);
-                } else {
-                    print!(" {} ", cell);
-                }
-            }
-            println!(); // 改行
+            let highlight = if y == ip_y { Some(ip_x) } else { None };
+            println!("{:5}{}", y, format_cells(row, highlight));
         }
 
         Ok(())
     }
+
+    fn display_diagnostic(&self, diagnostic: &Diagnostic) -> Result<(), InterpreterError> {
+        let rendered = render_diagnostic(diagnostic, io::stderr().is_terminal());
+        eprint!("{}", rendered);
+        io::stderr().flush().map_err(InterpreterError::IoError)
+    }
+}
+
+/// one grid row in the `display_grid` layout: ` c ` per cell, `[c]` for the IP
+pub(crate) fn format_cells(row: &[char], highlight: Option<usize>) -> String {
+    row.iter()
+        .enumerate()
+        .map(|(x, &cell)| {
+            if Some(x) == highlight {
+                format!("[{}]", cell)
+            } else {
+                format!(" {} ", cell)
+            }
+        })
+        .collect()
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[36m";
+
+/// rustc-style rendering: grid snippet, caret, recent path and stack
+fn render_diagnostic(diagnostic: &Diagnostic, color: bool) -> String {
+    let paint = |code: &str, text: &str| {
+        if color {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    };
+    let accent = match diagnostic.severity {
+        Severity::Error => "\x1b[1;31m",
+        Severity::Warning => "\x1b[1;33m",
+    };
+    let ctx = &diagnostic.error.context;
+    let (focus_x, focus_y) = diagnostic.focus();
+    let gutter = paint(BLUE, "      |");
+
+    let mut out = String::new();
+    out.push_str(&format!(
+        "{}{}\n",
+        paint(accent, &diagnostic.severity.to_string()),
+        paint(BOLD, &format!(": {}", diagnostic.error.kind))
+    ));
+    if diagnostic.is_static {
+        out.push_str(&format!(
+            "{} {}, instruction {:?}\n",
+            paint(BLUE, "   -->"),
+            diagnostic.cell(),
+            ctx.instruction
+        ));
+    } else {
+        out.push_str(&format!(
+            "{} ip {} at {} moving {}, instruction {:?}\n",
+            paint(BLUE, "   -->"),
+            ctx.ip_id,
+            diagnostic.cell(),
+            ctx.direction,
+            ctx.instruction
+        ));
+    }
+    out.push_str(&format!("{}\n", gutter));
+    for (row_index, row) in diagnostic.snippet.iter().enumerate() {
+        let y = diagnostic.origin.1 + row_index;
+        let highlight = if row_index == focus_y {
+            Some(focus_x)
+        } else {
+            None
+        };
+        let mut cells = String::new();
+        for (column, &cell) in row.iter().enumerate() {
+            let x = diagnostic.origin.0 + column;
+            let is_focus = Some(column) == highlight;
+            let text = format_cells(&[cell], if is_focus { Some(0) } else { None });
+            if is_focus {
+                cells.push_str(&paint(accent, &text));
+            } else if diagnostic.on_trail(x, y) {
+                cells.push_str(&paint(CYAN, &text));
+            } else {
+                cells.push_str(&text);
+            }
+        }
+        out.push_str(&format!(
+            "{}{}\n",
+            paint(BLUE, &format!("{:5} |", y)),
+            cells
+        ));
+        if row_index == focus_y {
+            out.push_str(&format!(
+                "{}{}{}\n",
+                gutter,
+                " ".repeat(focus_x * 3 + 1),
+                paint(accent, "^")
+            ));
+        }
+    }
+    out.push_str(&format!("{}\n", gutter));
+    if diagnostic.is_static {
+        for note in &diagnostic.notes {
+            out.push_str(&format!("{} {}\n", paint(BLUE, "      ="), note));
+        }
+        return out;
+    }
+    out.push_str(&format!(
+        "{} path: {}\n",
+        paint(BLUE, "      ="),
+        diagnostic.path()
+    ));
+    let stack = ctx
+        .stack
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    out.push_str(&format!(
+        "{} stack: [{}] (top is last)\n",
+        paint(BLUE, "      ="),
+        stack
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::{Direction, IPState, Position};
+    use crate::errors::{RuntimeContext, RuntimeError, RuntimeErrorKind};
+
+    #[test]
+    fn test_render_diagnostic_points_at_cell() {
+        let grid = vec![vec!['>', '1', '0', '/', '@'], vec![' '; 5]];
+        let mut ip = IPState::new(3, 0, Direction::Right);
+        ip.stk = vec![1, 0];
+        for x in 0..3 {
+            ip.ip_x = x;
+            ip.remember_position();
+        }
+        ip.ip_x = 3;
+        let error = RuntimeError::new(
+            RuntimeErrorKind::DivisionByZero,
+            RuntimeContext::capture(&ip, '/'),
+        );
+
+        let rendered = render_diagnostic(&Diagnostic::error(error, &grid), false);
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines[0], "error: division by zero");
+        assert_eq!(lines[3], "    0 | >  1  0 [/] @ ");
+        assert_eq!(lines[4], "      |          ^");
+        assert!(rendered.contains("path: (0, 0) -> (1, 0) -> (2, 0) -> (3, 0)"));
+        assert!(rendered.contains("stack: [1, 0]"));
+    }
+
+    #[test]
+    fn test_render_diagnostic_shows_layer() {
+        // `h` on layer 0, then `>10/` on layer 1
+        let layer = vec![vec!['>', '1', '0', '/', '@']];
+        let mut ip = IPState::new(0, 0, Direction::High);
+        ip.remember_position();
+        ip.set_position(Position::new(0, 0, 1));
+        ip.direction = Direction::Right;
+        for x in 0..3 {
+            ip.ip_x = x;
+            ip.remember_position();
+        }
+        ip.ip_x = 3;
+        let error = RuntimeError::new(
+            RuntimeErrorKind::DivisionByZero,
+            RuntimeContext::capture(&ip, '/').with_layer(Some(1)),
+        );
+
+        let rendered = render_diagnostic(&Diagnostic::error(error, &layer), false);
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(
+            lines[1],
+            "   --> ip 0 at (3, 0, 1) moving right, instruction '/'"
+        );
+        assert!(rendered
+            .contains("path: (0, 0, 0) -> (0, 0, 1) -> (1, 0, 1) -> (2, 0, 1) -> (3, 0, 1)"));
+    }
 }
@@ -0,0 +1,701 @@
+// src/interfaces/debugger.rs
+
+use crate::engine::control::ExecutionControl;
+use crate::engine::interpreter::Interpreter;
+use crate::entities::breakpoint::{parse_pair, Region};
+use crate::entities::{Breakpoint, Dimensions, Direction, IPState, Position, Watchpoint};
+use crate::errors::InterpreterError;
+use crate::interfaces::console_io_handler::format_cells;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+const HELP: &str = "\
+Commands (Enter alone steps the selected IP once):
+  s, step [N]             execute N instructions of the selected IP (default 1)
+  t, tick [N]             execute one instruction of every IP that is not frozen, N times
+  c, continue             run until a breakpoint or watchpoint
+  o, until-output         run until the program writes to stdout
+  back [N]                undo the last N instructions (default 1)
+  back-to X,Y             undo instructions until just before the last write to cell X,Y
+  p, print stack          stack of the selected IP (top is last)
+  p, print grid [REGION]  cells of the IP's layer, REGION is x,y or x,y:w,h (default: around the IP)
+  p, print ip             position, direction and next instruction of the selected IP
+  ips                     list IPs (* marks the selected one)
+  ip ID                   select an IP
+  freeze [ID], thaw [ID]  keep an IP (default: the selected one) from running, or release it
+  set X,Y[,Z] VALUE       write a cell, VALUE is 'c' or a character code (Z: the IP's layer)
+  push V [V ...]          push values onto the selected IP's stack
+  pop                     pop and show the top of the selected IP's stack
+  move X,Y[,Z]            move the selected IP (Z: its layer)
+  dir DIR                 turn the selected IP: right|left|up|down|high|low or > < ^ v h l
+  b, break SPEC           add a breakpoint: x,y | 'c' | spawn | terminate
+  w, watch SPEC           add a watchpoint: x,y | x,y:w,h | top>5
+  l, list                 list breakpoints and watchpoints
+  d, delete bN|wN         delete a breakpoint or watchpoint
+  history                 show previous commands; !! repeats the last, !N runs number N
+  h, help                 show this help
+  q, quit                 drop all breakpoints and let the program finish";
+
+/// One line typed at the debugger prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DebugCommand {
+    Step(usize),
+    Tick(usize),
+    Continue,
+    RunUntilOutput,
+    Back(usize),
+    /// `z` is the selected IP's layer when not given
+    BackTo {
+        x: usize,
+        y: usize,
+        z: Option<usize>,
+    },
+    PrintStack,
+    PrintGrid(Option<Region>),
+    PrintIp,
+    ListIps,
+    SelectIp(usize),
+    Freeze(Option<usize>),
+    Thaw(Option<usize>),
+    /// `z` is the selected IP's layer when not given
+    SetCell {
+        x: usize,
+        y: usize,
+        z: Option<usize>,
+        value: char,
+    },
+    Push(Vec<usize>),
+    Pop,
+    MoveIp {
+        x: usize,
+        y: usize,
+        z: Option<usize>,
+    },
+    Turn(Direction),
+    Break(Breakpoint),
+    Watch(Watchpoint),
+    List,
+    DeleteBreakpoint(usize),
+    DeleteWatchpoint(usize),
+    History,
+    Help,
+    Quit,
+}
+
+/// `'c'`, a single character, or a character code
+fn parse_cell_value(s: &str) -> Result<char, String> {
+    let s = s.trim();
+    let quoted = s
+        .strip_prefix('\'')
+        .and_then(|rest| rest.strip_suffix('\''));
+    if let Some(inner) = quoted {
+        let mut chars = inner.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(format!("expected a single character, got `{}`", s)),
+        };
+    }
+    if let Ok(code) = s.parse::<u32>() {
+        return char::from_u32(code).ok_or_else(|| format!("invalid character code {}", code));
+    }
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("expected 'c' or a character code, got `{}`", s)),
+    }
+}
+
+/// `x,y` or, for Trefunge, `x,y,z`
+fn parse_cell(s: &str) -> Result<(usize, usize, Option<usize>), String> {
+    match s.rsplit_once(',') {
+        Some((pair, z)) if pair.contains(',') => {
+            let (x, y) = parse_pair(pair)?;
+            Ok((x, y, Some(parse_number(z)?)))
+        }
+        _ => parse_pair(s).map(|(x, y)| (x, y, None)),
+    }
+}
+
+fn parse_number(s: &str) -> Result<usize, String> {
+    s.trim()
+        .parse::<usize>()
+        .map_err(|_| format!("invalid number `{}`", s.trim()))
+}
+
+/// `N`, or `default` when the argument is empty
+fn parse_count(s: &str, default: usize) -> Result<usize, String> {
+    if s.is_empty() {
+        Ok(default)
+    } else {
+        parse_number(s)
+    }
+}
+
+/// an IP id, or `None` (the selected IP) when the argument is empty
+fn parse_ip_id(s: &str) -> Result<Option<usize>, String> {
+    if s.is_empty() {
+        Ok(None)
+    } else {
+        parse_number(s).map(Some)
+    }
+}
+
+impl DebugCommand {
+    /// whether the command lets the program run
+    fn resumes(&self) -> bool {
+        matches!(
+            self,
+            DebugCommand::Step(_)
+                | DebugCommand::Tick(_)
+                | DebugCommand::Continue
+                | DebugCommand::RunUntilOutput
+        )
+    }
+}
+
+impl FromStr for DebugCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (command, argument) = s.split_once(' ').unwrap_or((s, ""));
+        let argument = argument.trim();
+        let command = match command {
+            "" => DebugCommand::Step(1),
+            "s" | "step" => DebugCommand::Step(parse_count(argument, 1)?),
+            "t" | "tick" => DebugCommand::Tick(parse_count(argument, 1)?),
+            "c" | "continue" => DebugCommand::Continue,
+            "o" | "until-output" | "run-until-output" => DebugCommand::RunUntilOutput,
+            "back" => DebugCommand::Back(parse_count(argument, 1)?),
+            "back-to" => {
+                let (x, y, z) = parse_cell(argument)?;
+                DebugCommand::BackTo { x, y, z }
+            }
+            "p" | "print" => {
+                let (what, region) = argument.split_once(' ').unwrap_or((argument, ""));
+                match what {
+                    "stack" | "" => DebugCommand::PrintStack,
+                    "grid" if region.trim().is_empty() => DebugCommand::PrintGrid(None),
+                    "grid" => DebugCommand::PrintGrid(Some(region.parse()?)),
+                    "ip" => DebugCommand::PrintIp,
+                    other => return Err(format!("cannot print `{}` (stack, grid, ip)", other)),
+                }
+            }
+            "ips" => DebugCommand::ListIps,
+            "ip" | "select" => DebugCommand::SelectIp(parse_number(argument)?),
+            "freeze" => DebugCommand::Freeze(parse_ip_id(argument)?),
+            "thaw" => DebugCommand::Thaw(parse_ip_id(argument)?),
+            "set" => {
+                let (cell, value) = argument
+                    .split_once(' ')
+                    .ok_or_else(|| "usage: set X,Y[,Z] VALUE".to_string())?;
+                let (x, y, z) = parse_cell(cell)?;
+                DebugCommand::SetCell {
+                    x,
+                    y,
+                    z,
+                    value: parse_cell_value(value)?,
+                }
+            }
+            "push" if argument.is_empty() => return Err("usage: push V [V ...]".to_string()),
+            "push" => DebugCommand::Push(
+                argument
+                    .split_whitespace()
+                    .map(parse_number)
+                    .collect::<Result<_, _>>()?,
+            ),
+            "pop" => DebugCommand::Pop,
+            "move" => {
+                let (x, y, z) = parse_cell(argument)?;
+                DebugCommand::MoveIp { x, y, z }
+            }
+            "dir" => DebugCommand::Turn(argument.parse()?),
+            "b" | "break" => DebugCommand::Break(argument.parse()?),
+            "w" | "watch" => DebugCommand::Watch(argument.parse()?),
+            "l" | "list" => DebugCommand::List,
+            "d" | "delete" => match (argument.get(..1), argument.get(1..).map(parse_number)) {
+                (Some("b"), Some(Ok(i))) => DebugCommand::DeleteBreakpoint(i),
+                (Some("w"), Some(Ok(i))) => DebugCommand::DeleteWatchpoint(i),
+                _ => return Err("usage: delete b<N> | w<N> (see 'list')".to_string()),
+            },
+            "history" => DebugCommand::History,
+            "h" | "help" | "?" => DebugCommand::Help,
+            "q" | "quit" => DebugCommand::Quit,
+            other => return Err(format!("unknown command `{}` (try 'help')", other)),
+        };
+        Ok(command)
+    }
+}
+
+/// Interactive debugger driving an [`Interpreter`] through its [`ExecutionControl`].
+pub struct Debugger {
+    interpreter: Arc<Interpreter>,
+    control: Arc<ExecutionControl>,
+    selected: usize,
+    history: Vec<String>,
+}
+
+impl Debugger {
+    /// rows / columns shown by `print grid` without a region
+    const VIEW_HEIGHT: usize = 9;
+    const VIEW_WIDTH: usize = 24;
+
+    pub fn new(interpreter: Arc<Interpreter>) -> Result<Self, InterpreterError> {
+        let control = interpreter.control();
+        // keep the program open after its last IP stops, so `back` can revive it
+        control.attach()?;
+        Ok(Self {
+            interpreter,
+            control,
+            selected: 0,
+            history: Vec::new(),
+        })
+    }
+
+    /// read commands from stdin until `quit` or end of input
+    ///
+    /// stdin is locked one line at a time, the program reads its input from it too
+    pub fn run(&mut self) -> Result<(), InterpreterError> {
+        let mut running = true;
+        // the program ran since the last prompt
+        let mut advanced = true;
+        loop {
+            let stopped = self.control.wait_until_stopped()?;
+            if running && !stopped {
+                eprintln!("[*] Program has Terminated.");
+                eprintln!("[*] Type 'q' to quit. ");
+            }
+            running = stopped;
+            if let Some(id) = self.control.take_focus()? {
+                self.selected = id;
+            }
+            if running && advanced {
+                self.show_selected()?;
+            }
+            if running {
+                let id = self.selected_ip()?.lock()?.id;
+                eprint!("[ip {}] $ > ", id);
+            } else {
+                eprint!("$ > ");
+            }
+            io::stderr().flush()?;
+
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line)? == 0 {
+                self.quit()?;
+                return Ok(());
+            }
+            let parsed = self
+                .expand_history(line.trim())
+                .and_then(|line| Ok((line.parse::<DebugCommand>()?, line)));
+            let (command, line) = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    eprintln!("[!] {}", e);
+                    advanced = false;
+                    continue;
+                }
+            };
+            if !line.is_empty() {
+                self.history.push(line);
+            }
+            if command == DebugCommand::Quit {
+                self.quit()?;
+                return Ok(());
+            }
+            advanced = command.resumes()
+                || matches!(command, DebugCommand::Back(_) | DebugCommand::BackTo { .. });
+            if let Err(e) = self.execute(command, running) {
+                eprintln!("[!] {}", e);
+            }
+        }
+    }
+
+    /// `!!` and `!N` refer to earlier lines
+    fn expand_history(&self, line: &str) -> Result<String, String> {
+        let Some(reference) = line.strip_prefix('!') else {
+            return Ok(line.to_string());
+        };
+        let entry = if reference == "!" {
+            self.history.last()
+        } else {
+            let index = parse_number(reference)?;
+            index.checked_sub(1).and_then(|i| self.history.get(i))
+        };
+        entry
+            .cloned()
+            .ok_or_else(|| format!("no history entry `{}`", line))
+    }
+
+    fn quit(&self) -> Result<(), InterpreterError> {
+        self.interpreter.clear_breakpoints()?;
+        self.control.detach()?;
+        self.control.resume()
+    }
+
+    /// undo `count` instructions, returns how many were undone
+    fn step_back(&mut self, count: usize) -> Result<usize, InterpreterError> {
+        // revived IPs must not run off on their own
+        self.control.pause()?;
+        let mut undone = 0;
+        while undone < count {
+            let Some(record) = self.interpreter.step_back()? else {
+                break;
+            };
+            undone += 1;
+            if undone == count && self.interpreter.ip(record.ip_id)?.is_some() {
+                self.selected = record.ip_id;
+            }
+        }
+        Ok(undone)
+    }
+
+    fn execute(&mut self, command: DebugCommand, running: bool) -> Result<(), InterpreterError> {
+        if command.resumes() && !running {
+            eprintln!("[!] the program has terminated");
+            return Ok(());
+        }
+        match command {
+            DebugCommand::Step(count) => {
+                let id = self.selected_ip()?.lock()?.id;
+                self.control.step_ip(id, count)?;
+            }
+            DebugCommand::Tick(count) => {
+                for _ in 0..count {
+                    self.control.tick()?;
+                    if !self.control.wait_until_stopped()? {
+                        break;
+                    }
+                    // stop ticking at a breakpoint
+                    if let Some(id) = self.control.take_focus()? {
+                        self.selected = id;
+                        break;
+                    }
+                }
+            }
+            DebugCommand::Continue => self.control.resume()?,
+            DebugCommand::RunUntilOutput => self.control.run_until_output()?,
+            DebugCommand::Back(count) => {
+                let undone = self.step_back(count)?;
+                if undone < count {
+                    eprintln!(
+                        "[!] the history ends here ({} instruction(s) undone)",
+                        undone
+                    );
+                }
+                eprintln!(
+                    "[*] {} instruction(s) left in the history",
+                    self.interpreter.history_len()?
+                );
+            }
+            DebugCommand::BackTo { x, y, z } => {
+                let z = match z {
+                    Some(z) => z,
+                    None => self.selected_ip()?.lock()?.ip_z,
+                };
+                let at = Position::new(x, y, z);
+                match self.interpreter.steps_since_write(at)? {
+                    Some(count) => {
+                        self.step_back(count)?;
+                    }
+                    None => eprintln!("[!] no write to {} in the history", self.format(at)),
+                }
+            }
+            DebugCommand::PrintStack => {
+                let ip = self.selected_ip()?;
+                let ip_locked = ip.lock()?;
+                eprintln!(
+                    "ip {} stack: {:?} (top is last)",
+                    ip_locked.id, ip_locked.stk
+                );
+            }
+            DebugCommand::PrintGrid(region) => self.print_grid(region)?,
+            DebugCommand::PrintIp => self.show_selected()?,
+            DebugCommand::ListIps => {
+                for ip in self.interpreter.ips()? {
+                    let ip_locked = ip.lock()?;
+                    let marker = if ip_locked.id == self.selected {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    eprintln!("{} {}", marker, self.describe(&ip_locked)?);
+                }
+            }
+            DebugCommand::SelectIp(id) => match self.interpreter.ip(id)? {
+                Some(_) => self.selected = id,
+                None => eprintln!("[!] no IP {}", id),
+            },
+            DebugCommand::Freeze(id) => {
+                let id = self.resolve_ip(id)?;
+                self.control.freeze(id)?;
+            }
+            DebugCommand::Thaw(id) => {
+                let id = self.resolve_ip(id)?;
+                self.control.thaw(id)?;
+            }
+            DebugCommand::SetCell { x, y, z, value } => {
+                let z = match z {
+                    Some(z) => z,
+                    None => self.selected_ip()?.lock()?.ip_z,
+                };
+                let at = Position::new(x, y, z);
+                match self.interpreter.set_cell(at, value)? {
+                    true => self.edited()?,
+                    false => eprintln!("[!] {} is outside the grid", self.format(at)),
+                }
+            }
+            DebugCommand::Push(values) => {
+                self.selected_ip()?.lock()?.stk.extend(values);
+                self.edited()?;
+            }
+            DebugCommand::Pop => {
+                let popped = self.selected_ip()?.lock()?.stk.pop();
+                match popped {
+                    Some(value) => {
+                        eprintln!("{}", value);
+                        self.edited()?;
+                    }
+                    None => eprintln!("[!] the stack is empty"),
+                }
+            }
+            DebugCommand::MoveIp { x, y, z } => {
+                {
+                    let ip = self.selected_ip()?;
+                    let mut ip_locked = ip.lock()?;
+                    let z = z.unwrap_or(ip_locked.ip_z);
+                    ip_locked.set_position(Position::new(x, y, z));
+                }
+                self.edited()?;
+            }
+            DebugCommand::Turn(direction) => {
+                let dimensions = self.interpreter.dimensions();
+                match dimensions.allows(direction) {
+                    true => {
+                        self.selected_ip()?.lock()?.direction = direction;
+                        self.edited()?;
+                    }
+                    false => eprintln!("[!] {:?} has no direction {}", dimensions, direction),
+                }
+            }
+            DebugCommand::Break(breakpoint) => self.interpreter.add_breakpoint(breakpoint)?,
+            DebugCommand::Watch(watchpoint) => self.interpreter.add_watchpoint(watchpoint)?,
+            DebugCommand::List => {
+                for (i, breakpoint) in self.interpreter.breakpoints()?.iter().enumerate() {
+                    eprintln!("  b{}: {}", i, breakpoint);
+                }
+                for (i, watchpoint) in self.interpreter.watchpoints()?.iter().enumerate() {
+                    eprintln!("  w{}: {}", i, watchpoint);
+                }
+            }
+            DebugCommand::DeleteBreakpoint(i) => {
+                if !self.interpreter.remove_breakpoint(i)? {
+                    eprintln!("[!] no breakpoint b{}", i);
+                }
+            }
+            DebugCommand::DeleteWatchpoint(i) => {
+                if !self.interpreter.remove_watchpoint(i)? {
+                    eprintln!("[!] no watchpoint w{}", i);
+                }
+            }
+            DebugCommand::History => {
+                for (i, line) in self.history.iter().enumerate() {
+                    eprintln!("{:4}  {}", i + 1, line);
+                }
+            }
+            DebugCommand::Help => eprintln!("{}", HELP),
+            DebugCommand::Quit => self.quit()?,
+        }
+        Ok(())
+    }
+
+    /// the selected IP, falling back to the first live one once it has terminated
+    fn selected_ip(&mut self) -> Result<Arc<Mutex<IPState>>, InterpreterError> {
+        if let Some(ip) = self.interpreter.ip(self.selected)? {
+            if !ip.lock()?.terminated {
+                return Ok(ip);
+            }
+        }
+        let ips = self.interpreter.ips()?;
+        for ip in &ips {
+            let ip_locked = ip.lock()?;
+            if !ip_locked.terminated {
+                self.selected = ip_locked.id;
+                return Ok(ip.clone());
+            }
+        }
+        ips.into_iter()
+            .next()
+            .ok_or_else(|| InterpreterError::ThreadError("No IP has started".to_string()))
+    }
+
+    /// `id` if such an IP exists, else the selected IP's id
+    fn resolve_ip(&mut self, id: Option<usize>) -> Result<usize, InterpreterError> {
+        match id {
+            Some(id) if self.interpreter.ip(id)?.is_some() => Ok(id),
+            Some(id) => Err(InterpreterError::ParseError(format!("no IP {}", id))),
+            None => Ok(self.selected_ip()?.lock()?.id),
+        }
+    }
+
+    /// the recorded steps no longer lead to the edited state, so `back` starts over from here
+    fn edited(&self) -> Result<(), InterpreterError> {
+        if self.interpreter.history_len()? > 0 {
+            self.interpreter.clear_history()?;
+            eprintln!("[*] history cleared: `back` cannot undo past a manual edit");
+        }
+        Ok(())
+    }
+
+    /// a cell as `(x, y)`, or `(x, y, z)` in Trefunge
+    fn format(&self, at: Position) -> String {
+        self.interpreter.dimensions().format(at)
+    }
+
+    fn describe(&self, ip: &IPState) -> Result<String, InterpreterError> {
+        let next = self
+            .interpreter
+            .grid_region(Region::cell(ip.position()))?
+            .first()
+            .and_then(|row| row.first().copied())
+            .unwrap_or(' ');
+        let state = if ip.terminated {
+            "terminated"
+        } else if self.control.is_frozen(ip.id)? {
+            "frozen"
+        } else if ip.string_mode_active {
+            "string mode"
+        } else {
+            "running"
+        };
+        Ok(format!(
+            "ip {} at {} moving {}, next {:?}, stack depth {} [{}]",
+            ip.id,
+            self.format(ip.position()),
+            ip.direction,
+            next,
+            ip.stk.len(),
+            state
+        ))
+    }
+
+    fn show_selected(&mut self) -> Result<(), InterpreterError> {
+        let ip = self.selected_ip()?;
+        let ip_locked = ip.lock()?;
+        eprintln!("{}", self.describe(&ip_locked)?);
+        Ok(())
+    }
+
+    fn print_grid(&mut self, region: Option<Region>) -> Result<(), InterpreterError> {
+        let Position {
+            x: ip_x,
+            y: ip_y,
+            z: layer,
+        } = self.selected_ip()?.lock()?.position();
+        let region = region.unwrap_or(Region {
+            x: ip_x.saturating_sub(Self::VIEW_WIDTH / 2),
+            y: ip_y.saturating_sub(Self::VIEW_HEIGHT / 2),
+            z: layer,
+            width: Self::VIEW_WIDTH,
+            height: Self::VIEW_HEIGHT,
+        });
+        if self.interpreter.dimensions() == Dimensions::Trefunge {
+            eprintln!("layer {} of {}", region.z, self.interpreter.code_depth());
+        }
+        let rows = self.interpreter.grid_region(region)?;
+        for (row_index, row) in rows.iter().enumerate() {
+            let y = region.y + row_index;
+            let highlight = if y == ip_y && region.z == layer {
+                ip_x.checked_sub(region.x)
+            } else {
+                None
+            };
+            eprintln!("{:5}{}", y, format_cells(row, highlight));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_debug_commands() {
+        assert_eq!("".parse(), Ok(DebugCommand::Step(1)));
+        assert_eq!("step 5".parse(), Ok(DebugCommand::Step(5)));
+        assert_eq!("o".parse(), Ok(DebugCommand::RunUntilOutput));
+        assert_eq!(
+            "print grid 1,2:3,4".parse(),
+            Ok(DebugCommand::PrintGrid(Some(Region {
+                x: 1,
+                y: 2,
+                z: 0,
+                width: 3,
+                height: 4
+            })))
+        );
+        assert_eq!(
+            "set 3,0 'v'".parse(),
+            Ok(DebugCommand::SetCell {
+                x: 3,
+                y: 0,
+                z: None,
+                value: 'v'
+            })
+        );
+        assert_eq!(
+            "set 0,0 64".parse(),
+            Ok(DebugCommand::SetCell {
+                x: 0,
+                y: 0,
+                z: None,
+                value: '@'
+            })
+        );
+        assert_eq!("push 1 2 3".parse(), Ok(DebugCommand::Push(vec![1, 2, 3])));
+        assert_eq!("dir ^".parse(), Ok(DebugCommand::Turn(Direction::Up)));
+        assert_eq!(
+            "move 4,5".parse(),
+            Ok(DebugCommand::MoveIp {
+                x: 4,
+                y: 5,
+                z: None
+            })
+        );
+        assert_eq!(
+            "move 4,5,1".parse(),
+            Ok(DebugCommand::MoveIp {
+                x: 4,
+                y: 5,
+                z: Some(1)
+            })
+        );
+        assert_eq!(
+            "set 1,2,3 'h'".parse(),
+            Ok(DebugCommand::SetCell {
+                x: 1,
+                y: 2,
+                z: Some(3),
+                value: 'h'
+            })
+        );
+        assert_eq!("dir high".parse(), Ok(DebugCommand::Turn(Direction::High)));
+        assert_eq!("d w1".parse(), Ok(DebugCommand::DeleteWatchpoint(1)));
+        assert_eq!("t".parse(), Ok(DebugCommand::Tick(1)));
+        assert_eq!("back 3".parse(), Ok(DebugCommand::Back(3)));
+        assert_eq!(
+            "back-to 2,0".parse(),
+            Ok(DebugCommand::BackTo {
+                x: 2,
+                y: 0,
+                z: None
+            })
+        );
+        assert_eq!("freeze".parse(), Ok(DebugCommand::Freeze(None)));
+        assert_eq!("thaw 2".parse(), Ok(DebugCommand::Thaw(Some(2))));
+        assert!("step x".parse::<DebugCommand>().is_err());
+        assert!("jump".parse::<DebugCommand>().is_err());
+    }
+}
@@ -1,6 +1,6 @@
 // src/interfaces/io_handler.rs
 
-use crate::errors::InterpreterError;
+use crate::errors::{Diagnostic, InterpreterError};
 
 pub trait IOHandle {
     fn write_output(&self, output: &str) -> Result<(), InterpreterError>;
@@ -8,10 +8,16 @@ pub trait IOHandle {
     fn read_number(&self) -> Result<usize, InterpreterError>;
     fn read_char(&self) -> Result<char, InterpreterError>;
     fn display_stack(&self, stack: &[usize]) -> Result<(), InterpreterError>;
+    /// the rows of the IP's layer; `layer` is its number in a Trefunge program
     fn display_grid(
         &self,
         grid: &[Vec<char>],
         ip_x: usize,
         ip_y: usize,
+        layer: Option<usize>,
     ) -> Result<(), InterpreterError>;
+
+    fn display_diagnostic(&self, diagnostic: &Diagnostic) -> Result<(), InterpreterError> {
+        self.write_error(&diagnostic.to_string())
+    }
 }
@@ -0,0 +1,90 @@
+// src/interfaces/memory_io_handler.rs
+
+use crate::errors::{Diagnostic, InterpreterError};
+use crate::interfaces::IOHandle;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Mutex;
+
+/// Reads input from a string and collects the output, like `ConsoleIOHandler` on pipes.
+pub struct MemoryIOHandler {
+    input: Mutex<VecDeque<u8>>,
+    output: Mutex<String>,
+    errors: Mutex<String>,
+}
+
+impl MemoryIOHandler {
+    pub fn new(input: &str) -> Self {
+        Self {
+            input: Mutex::new(input.bytes().collect()),
+            output: Mutex::new(String::new()),
+            errors: Mutex::new(String::new()),
+        }
+    }
+
+    pub fn output(&self) -> Result<String, InterpreterError> {
+        Ok(self.output.lock()?.clone())
+    }
+
+    pub fn errors(&self) -> Result<String, InterpreterError> {
+        Ok(self.errors.lock()?.clone())
+    }
+}
+
+fn eof() -> InterpreterError {
+    InterpreterError::IoError(io::ErrorKind::UnexpectedEof.into())
+}
+
+impl IOHandle for MemoryIOHandler {
+    fn write_output(&self, output: &str) -> Result<(), InterpreterError> {
+        self.output.lock()?.push_str(output);
+        Ok(())
+    }
+
+    fn write_error(&self, error: &str) -> Result<(), InterpreterError> {
+        let mut errors = self.errors.lock()?;
+        errors.push_str(error);
+        errors.push('\n');
+        Ok(())
+    }
+
+    /// one line, as `ConsoleIOHandler` reads it
+    fn read_number(&self) -> Result<usize, InterpreterError> {
+        let mut input = self.input.lock()?;
+        if input.is_empty() {
+            return Err(eof());
+        }
+        let end = input
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(input.len(), |i| i + 1);
+        let line: Vec<u8> = input.drain(..end).collect();
+        String::from_utf8_lossy(&line)
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| InterpreterError::ParseError("Invalid number".to_string()))
+    }
+
+    fn read_char(&self) -> Result<char, InterpreterError> {
+        let byte = self.input.lock()?.pop_front().ok_or_else(eof)?;
+        Ok(byte as char)
+    }
+
+    fn display_stack(&self, _stack: &[usize]) -> Result<(), InterpreterError> {
+        Ok(())
+    }
+
+    fn display_grid(
+        &self,
+        _grid: &[Vec<char>],
+        _ip_x: usize,
+        _ip_y: usize,
+        _layer: Option<usize>,
+    ) -> Result<(), InterpreterError> {
+        Ok(())
+    }
+
+    fn display_diagnostic(&self, diagnostic: &Diagnostic) -> Result<(), InterpreterError> {
+        self.write_error(&diagnostic.to_string())
+    }
+}
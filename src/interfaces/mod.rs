@@ -2,9 +2,17 @@
 
 pub mod command_registry;
 pub mod console_io_handler;
+pub mod debugger;
 pub mod io_handler;
+#[cfg(test)]
+pub mod memory_io_handler;
+pub mod step_observer;
+pub mod topology;
 
 // 再エクスポート
 pub use command_registry::CommandResolve;
 pub use console_io_handler::ConsoleIOHandler;
+pub use debugger::Debugger;
 pub use io_handler::IOHandle;
+pub use step_observer::StepObserver;
+pub use topology::Topology;
@@ -0,0 +1,14 @@
+// src/interfaces/step_observer.rs
+
+use crate::engine::history::StepRecord;
+use crate::errors::InterpreterError;
+
+/// Sees every executed instruction (trace writers, replay checks, ...).
+pub trait StepObserver {
+    fn on_step(&self, record: &StepRecord) -> Result<(), InterpreterError>;
+
+    /// called once after all IPs have finished
+    fn finish(&self) -> Result<(), InterpreterError> {
+        Ok(())
+    }
+}
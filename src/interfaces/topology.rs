@@ -0,0 +1,11 @@
+// src/interfaces/topology.rs
+
+use crate::entities::{Direction, Position, Size};
+
+/// Where an IP lands after one step: the shape of Funge-Space around the program.
+pub trait Topology {
+    /// the cell and direction after moving from `at` in a program of `size`, or `None` when
+    /// the IP dies at a wall
+    fn step(&self, at: Position, direction: Direction, size: Size)
+        -> Option<(Position, Direction)>;
+}
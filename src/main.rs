@@ -1,23 +1,48 @@
 // src/main.rs
+mod analysis;
+mod assembler;
+mod compiler;
 mod engine;
 mod entities;
 mod errors;
 mod interfaces;
-use clap::{ArgAction, Parser};
+use analysis::cfg::ControlFlowGraph;
+use analysis::export::CfgFormat;
+use assembler::generate::{self, Shape};
+use assembler::literal::{self, StringOrder};
+use clap::{ArgAction, Args, Parser, Subcommand};
+use compiler::Target;
+use engine::coverage::{Coverage, CoverageCollector, CoverageReport};
+use engine::history::History;
 use engine::interpreter::Interpreter;
+use engine::profile::{Profile, Profiler};
+use engine::replay::{first_divergence, TraceReplay};
+use engine::semantics::{
+    DivisionByZeroPolicy, Semantics, StackUnderflowPolicy, UnknownInstructionPolicy,
+};
+use engine::session::{Session, SessionMode};
+use engine::topology::TopologyKind;
+use engine::trace::{read_trace, TraceFilter, Tracer};
+use entities::breakpoint::Region;
 use entities::code_grid::CodeGrid;
-use errors::InterpreterError;
+use entities::{Breakpoint, Dimensions, Watchpoint};
+use errors::{InterpreterError, Severity};
 use interfaces::command_registry::CommandRegistry;
-use interfaces::ConsoleIOHandler;
+use interfaces::{ConsoleIOHandler, Debugger, IOHandle};
+use std::io::{self, IsTerminal};
 use std::sync::Arc;
-use std::{io, thread};
+use std::{fs, process, thread};
 /// Befunge Interpreter
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the Befunge program file
-    #[arg(value_name = "FILE")]
-    file: String,
+    #[arg(value_name = "FILE", required = true)]
+    file: Option<String>,
 
     /// Enable step-by-step execution
     #[arg(short, long, action = ArgAction::SetTrue)]
@@ -26,36 +51,379 @@ struct Cli {
     /// Enable debug (trace) mode
     #[arg(short, long, action = ArgAction::SetTrue)]
     debug: bool,
+
+    #[command(flatten)]
+    semantics: SemanticsArgs,
+
+    /// Pause at a breakpoint: `x,y`, `'c'` (instruction), `spawn` or `terminate`
+    #[arg(long = "break", value_name = "SPEC")]
+    breakpoints: Vec<Breakpoint>,
+
+    /// Pause on a watchpoint: `x,y` / `x,y:w,h` (writes by `p`) or `top>5` (stack top)
+    #[arg(long = "watch", value_name = "SPEC")]
+    watchpoints: Vec<Watchpoint>,
+
+    /// Instructions remembered for stepping backwards in the debugger
+    #[arg(long, value_name = "N", default_value_t = History::DEFAULT_CAPACITY)]
+    history: usize,
+
+    /// Write every executed instruction to FILE as JSON lines
+    #[arg(long, value_name = "FILE")]
+    trace: Option<String>,
+
+    /// Only trace these IPs
+    #[arg(long, value_name = "ID", requires = "trace")]
+    trace_ip: Vec<usize>,
+
+    /// Only trace instructions inside `x,y:w,h`
+    #[arg(long, value_name = "REGION", requires = "trace")]
+    trace_region: Option<Region>,
+
+    /// Only trace every N-th (filtered) instruction
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "trace")]
+    trace_every: usize,
+
+    /// Count executions per cell, instruction and IP; print a summary and heatmap
+    /// and write the heatmap as HTML to FILE
+    #[arg(long, value_name = "FILE")]
+    profile_out: Option<String>,
+
+    /// Merge visited cells and branch directions into FILE (JSON) and print a coverage overlay
+    #[arg(long, value_name = "FILE")]
+    coverage: Option<String>,
+
+    /// Record the order in which IPs run and all input into a session file
+    #[arg(long, value_name = "FILE", conflicts_with = "replay_session")]
+    record_session: Option<String>,
+
+    /// Repeat a recorded session: same IP order, input taken from the file
+    #[arg(long, value_name = "FILE")]
+    replay_session: Option<String>,
 }
+
+/// Options that change what a program does, shared by running and replaying.
+#[derive(Args, Debug)]
+struct SemanticsArgs {
+    /// Stop with an error after this many executed instructions (all IPs combined)
+    #[arg(long, value_name = "N")]
+    max_steps: Option<usize>,
+
+    /// What `/` and `%` do when dividing by zero
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = DivisionByZeroPolicy::default())]
+    on_division_by_zero: DivisionByZeroPolicy,
+
+    /// What popping an empty stack does
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = StackUnderflowPolicy::default())]
+    on_stack_underflow: StackUnderflowPolicy,
+
+    /// What a cell without a known instruction does
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = UnknownInstructionPolicy::default())]
+    on_unknown_instruction: UnknownInstructionPolicy,
+
+    /// How many coordinates Funge-Space has; Trefunge layers are separated by form feeds
+    #[arg(long, value_enum, value_name = "SPACE", default_value_t = Dimensions::default())]
+    dimensions: Dimensions,
+
+    /// What happens to an IP moving off an edge
+    #[arg(long, value_enum, value_name = "TOPOLOGY", default_value_t = TopologyKind::default())]
+    topology: TopologyKind,
+}
+
+impl SemanticsArgs {
+    /// `file` loaded with `--dimensions`
+    fn load(&self, file: &str) -> Result<CodeGrid, InterpreterError> {
+        CodeGrid::load_as(file, self.dimensions)
+    }
+
+    fn interpreter(&self, program: CodeGrid, debug_mode: bool) -> Interpreter {
+        Interpreter::new(
+            program,
+            debug_mode,
+            Arc::new(CommandRegistry::for_dimensions(self.dimensions)),
+        )
+        .with_max_steps(self.max_steps)
+        .with_semantics(self.semantics())
+        .with_topology(self.topology.topology())
+        .with_dimensions(self.dimensions)
+    }
+
+    fn semantics(&self) -> Semantics {
+        Semantics::new(
+            self.on_division_by_zero,
+            self.on_stack_underflow,
+            self.on_unknown_instruction,
+        )
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Report unreachable code, IPs running off the program, stack underflows and other mistakes, without running it
+    Check {
+        /// Path to the Befunge program file
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// What popping an empty stack will do when the program runs
+        #[arg(long, value_enum, value_name = "POLICY", default_value_t = StackUnderflowPolicy::default())]
+        on_stack_underflow: StackUnderflowPolicy,
+
+        /// What `/` and `%` will do with a zero divisor when the program runs
+        #[arg(long, value_enum, value_name = "POLICY", default_value_t = DivisionByZeroPolicy::default())]
+        on_division_by_zero: DivisionByZeroPolicy,
+    },
+    /// Print the control-flow graph as basic blocks, e.g. `--format dot` for Graphviz
+    Cfg {
+        /// Path to the Befunge program file
+        #[arg(value_name = "FILE")]
+        file: String,
+        #[arg(long, value_enum, value_name = "FORMAT", default_value_t = CfgFormat::default())]
+        format: CfgFormat,
+    },
+    /// Translate a program into a standalone source file
+    Compile {
+        /// Path to the Befunge program file
+        #[arg(value_name = "FILE")]
+        file: String,
+        #[arg(long, value_enum, value_name = "TARGET")]
+        target: Target,
+        /// Where to write the result; stdout by default
+        #[arg(short, long, value_name = "OUT")]
+        output: Option<String>,
+    },
+    /// Lay out a linear assembly program as a Befunge grid
+    Assemble {
+        /// Path to the assembly file
+        #[arg(value_name = "FILE")]
+        file: String,
+        /// Where to write the grid; stdout by default
+        #[arg(short, long, value_name = "OUT")]
+        output: Option<String>,
+    },
+    /// Write a program printing exactly TEXT
+    Generate {
+        /// The text to print; read from stdin when omitted
+        #[arg(value_name = "TEXT")]
+        text: Option<String>,
+        #[arg(long, value_enum, value_name = "SHAPE", default_value_t = Shape::default())]
+        shape: Shape,
+        /// Most columns a folded or boustrophedon program may use
+        #[arg(long, value_name = "COLUMNS", default_value_t = 80)]
+        width: usize,
+        /// Where to write the program; stdout by default
+        #[arg(short, long, value_name = "OUT")]
+        output: Option<String>,
+    },
+    /// Print the shortest cells pushing a number, or a string with `--string`
+    Literal {
+        #[arg(value_name = "VALUE", allow_hyphen_values = true)]
+        value: String,
+        /// Push VALUE as text instead of a number
+        #[arg(long)]
+        string: bool,
+        /// Which end of the text is left on top
+        #[arg(long, value_enum, value_name = "ORDER", default_value_t = StringOrder::default())]
+        order: StringOrder,
+        /// Push a 0 below the text, for loops printing until it
+        #[arg(long)]
+        terminator: bool,
+    },
+    /// Print structured pseudocode with loops, if/else and expressions rebuilt from the CFG
+    Decompile {
+        /// Path to the Befunge program file
+        #[arg(value_name = "FILE")]
+        file: String,
+    },
+    /// Show the first instruction at which two traces differ
+    DiffTrace {
+        #[arg(value_name = "TRACE")]
+        left: String,
+        #[arg(value_name = "TRACE")]
+        right: String,
+    },
+    /// Print the coverage overlay collected with `--coverage`
+    Coverage {
+        /// Path to the Befunge program file
+        #[arg(value_name = "FILE")]
+        file: String,
+        /// Coverage file written by `--coverage`
+        #[arg(value_name = "COVERAGE")]
+        coverage: String,
+    },
+    /// Run a program and fail at the first instruction that differs from a recorded trace
+    Replay {
+        /// Path to the Befunge program file
+        #[arg(value_name = "FILE")]
+        file: String,
+        /// Trace written by `--trace` without filters
+        #[arg(value_name = "TRACE")]
+        trace: String,
+        #[command(flatten)]
+        semantics: SemanticsArgs,
+    },
+}
+
 fn main() -> Result<(), InterpreterError> {
     // parse args
     let cli = Cli::parse();
 
+    match cli.command {
+        Some(Command::Check {
+            file,
+            on_stack_underflow,
+            on_division_by_zero,
+        }) => check(&file, on_stack_underflow, on_division_by_zero),
+        Some(Command::Cfg { file, format }) => {
+            let source = CodeGrid::load(&file)?.grid.lock()?.clone();
+            print!(
+                "{}",
+                format.render(&ControlFlowGraph::build(&source), &file)
+            );
+            Ok(())
+        }
+        Some(Command::Compile {
+            file,
+            target,
+            output,
+        }) => {
+            let source = CodeGrid::load(&file)?.grid.lock()?.clone();
+            let compiled = target.compile(&source, &file);
+            match output {
+                Some(path) => fs::write(path, compiled)?,
+                None => print!("{}", compiled),
+            }
+            Ok(())
+        }
+        Some(Command::Assemble { file, output }) => {
+            let program = assembler::assemble(&fs::read_to_string(&file)?)?;
+            match output {
+                Some(path) => fs::write(path, program)?,
+                None => print!("{}", program),
+            }
+            Ok(())
+        }
+        Some(Command::Generate {
+            text,
+            shape,
+            width,
+            output,
+        }) => {
+            let text = match text {
+                Some(text) => text,
+                None => io::read_to_string(io::stdin())?,
+            };
+            let program = generate::generate(&text, shape, width)?;
+            match output {
+                Some(path) => fs::write(path, program)?,
+                None => print!("{}", program),
+            }
+            Ok(())
+        }
+        Some(Command::Literal {
+            value,
+            string,
+            order,
+            terminator,
+        }) => {
+            if string {
+                println!("{}", literal::string(&value, order, terminator));
+            } else {
+                let number = value
+                    .parse::<i64>()
+                    .map_err(|e| InterpreterError::ParseError(format!("{}: {}", value, e)))?;
+                println!("{}", literal::number(number));
+            }
+            Ok(())
+        }
+        Some(Command::Decompile { file }) => {
+            let source = CodeGrid::load(&file)?.grid.lock()?.clone();
+            print!("{}", analysis::decompile::decompile(&source, &file));
+            Ok(())
+        }
+        Some(Command::DiffTrace { left, right }) => diff_trace(&left, &right),
+        Some(Command::Coverage { file, coverage }) => show_coverage(&file, &coverage),
+        Some(Command::Replay {
+            file,
+            trace,
+            semantics,
+        }) => replay(&file, &trace, &semantics),
+        None => run(cli),
+    }
+}
+
+fn run(cli: Cli) -> Result<(), InterpreterError> {
+    // coverage and profiles are maps of one layer
+    if cli.semantics.dimensions == Dimensions::Trefunge
+        && (cli.coverage.is_some() || cli.profile_out.is_some())
+    {
+        return Err(InterpreterError::ParseError(
+            "--coverage and --profile-out do not support Trefunge programs".to_string(),
+        ));
+    }
+
     // init grid
-    let program = CodeGrid::load(&cli.file)?;
+    let program = cli
+        .semantics
+        .load(cli.file.as_deref().unwrap_or_default())?;
+
+    // the program as loaded, for the coverage overlay
+    let source = program.grid.lock()?.clone();
 
-    // init command registry
-    let command_registry = Arc::new(CommandRegistry::new());
+    // breakpoints need the prompt even without --step
+    let interactive = cli.step || !cli.breakpoints.is_empty() || !cli.watchpoints.is_empty();
 
-    // Initialize ThreadPool with 3 threads.
     // init Interpreter
-    let interpreter = Arc::new(Interpreter::new(
-        program,
-        if cli.step == true { true } else { cli.debug },
-        command_registry,
-    ));
+    let mut interpreter = cli
+        .semantics
+        .interpreter(program, cli.step || cli.debug)
+        .with_history(interactive.then_some(cli.history));
+    if let Some(path) = &cli.trace {
+        let filter = TraceFilter {
+            ips: cli.trace_ip.clone(),
+            region: cli.trace_region,
+            every: cli.trace_every,
+        };
+        interpreter = interpreter.with_observer(Arc::new(Tracer::create(path, filter)?));
+    }
+    let profiler = cli.profile_out.as_ref().map(|_| Arc::new(Profiler::new()));
+    if let Some(profiler) = &profiler {
+        interpreter = interpreter.with_observer(profiler.clone());
+    }
+    let coverage = cli
+        .coverage
+        .as_ref()
+        .map(|_| Arc::new(CoverageCollector::new()));
+    if let Some(coverage) = &coverage {
+        interpreter = interpreter.with_observer(coverage.clone());
+    }
+    if cli.record_session.is_some() {
+        interpreter = interpreter.with_session(SessionMode::Record);
+    }
+    if let Some(path) = &cli.replay_session {
+        interpreter = interpreter.with_session(SessionMode::Replay(Session::load(path)?));
+    }
+    let interpreter = Arc::new(interpreter);
 
     // init IOHandler
-    let io_handler = Arc::new(ConsoleIOHandler::new(if cli.step || cli.debug {
-        true
-    } else {
-        false
-    }));
+    let io_handler = Arc::new(ConsoleIOHandler::new(cli.step || cli.debug));
 
+    for breakpoint in &cli.breakpoints {
+        interpreter.add_breakpoint(breakpoint.clone())?;
+    }
+    for watchpoint in &cli.watchpoints {
+        interpreter.add_watchpoint(watchpoint.clone())?;
+    }
     if cli.step {
-        interpreter.enable_step_mode();
+        interpreter.control().pause()?;
     }
 
+    // attach before running, so the debugger can step back from the end of the program
+    let debugger = if interactive {
+        Some(Debugger::new(Arc::clone(&interpreter))?)
+    } else {
+        None
+    };
+
     //  interpreter thread
     let interpreter_clone = Arc::clone(&interpreter);
     let io_handler_clone = Arc::clone(&io_handler);
@@ -63,30 +431,142 @@ fn main() -> Result<(), InterpreterError> {
         if let Err(e) = interpreter_clone.run(io_handler_clone) {
             eprintln!("Interpreter Error: {}", e);
         }
-        if cli.step || cli.debug {
+        if cli.debug && !interactive {
             eprintln!("[*] Program has Terminated.");
-            if cli.step {
-                eprintln!("[*] Type 'q' to quit. ");
-            }
         }
     });
 
-    // step mode loop
-    if cli.step {
-        loop {
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            let input = input.trim();
-            if input.eq_ignore_ascii_case("q") {
-                interpreter.clone().disable_step_mode();
-                break;
-            }
-            interpreter.step()?;
-        }
+    // debugger prompt
+    if let Some(mut debugger) = debugger {
+        debugger.run()?;
     }
 
     // wait for interpreter thread
     run_handle.join().expect("Interpreter thread panicked");
 
+    if let (Some(path), Some(session)) = (&cli.record_session, interpreter.session()?) {
+        session.save(path)?;
+    }
+    if let (Some(path), Some(profiler)) = (&cli.profile_out, &profiler) {
+        let file = cli.file.as_deref().unwrap_or_default();
+        write_profile(&interpreter, &profiler.profile()?, file, path)?;
+    }
+    if let (Some(path), Some(coverage)) = (&cli.coverage, &coverage) {
+        // merge with the runs already in the file
+        let mut merged = Coverage::from(&CoverageReport::load_or_default(path)?);
+        merged.merge(&coverage.coverage()?);
+        let report = merged.report(&source);
+        report.save(path)?;
+        eprintln!();
+        eprint!("{}", merged.overlay(&source, io::stderr().is_terminal()));
+        eprintln!("{} over {} run(s)", report.summary, report.runs);
+    }
+
+    Ok(())
+}
+
+/// summary and heatmap on stderr, heatmap and summary as HTML in `path`
+fn write_profile(
+    interpreter: &Interpreter,
+    profile: &Profile,
+    file: &str,
+    path: &str,
+) -> Result<(), InterpreterError> {
+    let (code_width, code_height) = interpreter.code_size();
+    let (width, height) = profile.extent(code_width, code_height);
+    let region = Region {
+        x: 0,
+        y: 0,
+        z: 0,
+        width,
+        height,
+    };
+    let grid = interpreter.grid_region(region)?;
+    eprintln!();
+    eprint!("{}", profile.summary(&grid, 10));
+    eprintln!("\nheatmap");
+    eprint!("{}", profile.heatmap(&grid, io::stderr().is_terminal()));
+    fs::write(path, profile.html(&grid, file))?;
+    eprintln!("\nprofile written to {}", path);
+    Ok(())
+}
+
+fn show_coverage(file: &str, path: &str) -> Result<(), InterpreterError> {
+    let source = CodeGrid::load(file)?.grid.lock()?.clone();
+    let coverage = Coverage::from(&CoverageReport::load(path)?);
+    let report = coverage.report(&source);
+    print!("{}", coverage.overlay(&source, io::stdout().is_terminal()));
+    println!("{} over {} run(s)", report.summary, report.runs);
     Ok(())
 }
+
+fn check(
+    file: &str,
+    stack_underflow: StackUnderflowPolicy,
+    division_by_zero: DivisionByZeroPolicy,
+) -> Result<(), InterpreterError> {
+    let source = CodeGrid::load(file)?.grid.lock()?.clone();
+    let diagnostics = analysis::check::check(&source, stack_underflow, division_by_zero);
+    let console = ConsoleIOHandler::new(false);
+    for diagnostic in &diagnostics {
+        console.display_diagnostic(diagnostic)?;
+        eprintln!();
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    eprintln!(
+        "{}: {} error(s), {} warning(s)",
+        file,
+        errors,
+        diagnostics.len() - errors
+    );
+    if errors > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn diff_trace(left: &str, right: &str) -> Result<(), InterpreterError> {
+    let left_events = read_trace(left)?;
+    let right_events = read_trace(right)?;
+    match first_divergence(&left_events, &right_events) {
+        Some(divergence) => {
+            print!("{}", divergence.render(left, right));
+            process::exit(1);
+        }
+        None => {
+            println!("traces match ({} instructions)", left_events.len());
+            Ok(())
+        }
+    }
+}
+
+fn replay(file: &str, trace: &str, semantics: &SemanticsArgs) -> Result<(), InterpreterError> {
+    let program = semantics.load(file)?;
+    let replay = Arc::new(TraceReplay::new(read_trace(trace)?));
+    let interpreter = Arc::new(
+        semantics
+            .interpreter(program, false)
+            .with_observer(replay.clone()),
+    );
+    if let Err(e) = interpreter.run(Arc::new(ConsoleIOHandler::new(false))) {
+        eprintln!("Interpreter Error: {}", e);
+    }
+
+    match replay.divergence()? {
+        Some(divergence) => {
+            eprint!("{}", divergence.render("expected", "actual"));
+            process::exit(1);
+        }
+        None => {
+            eprintln!(
+                "replay matches {} ({} instructions)",
+                trace,
+                replay.matched()?
+            );
+            Ok(())
+        }
+    }
+}